@@ -0,0 +1,155 @@
+/*
+ * C interface of detect-changed-files
+ *
+ * Load a configuration, feed it changed paths and query which groups
+ * matched. Handles are opaque pointers released with their free function,
+ * strings are NUL-terminated, and functions report failures with a status
+ * code. Build the library with `cargo build --release`, which produces
+ * both a shared and a static library.
+ *
+ * Keep in sync with src/ffi.rs, whose tests compare the declarations of
+ * this header with the interface.
+ */
+
+#ifndef DETECT_CHANGED_FILES_H
+#define DETECT_CHANGED_FILES_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Outcome of a call. Configuration errors have a code of their own for each
+ * kind of error. */
+typedef enum dcf_status {
+    DCF_OK = 0,
+    /* A required pointer argument is NULL */
+    DCF_NULL_POINTER = 1,
+    /* A string argument is not valid UTF-8 */
+    DCF_INVALID_UTF8 = 2,
+    /* The configuration file could not be read */
+    DCF_IO = 3,
+    /* A changed path is invalid, like one going above the repository root */
+    DCF_INVALID_PATH = 4,
+    /* No group has the given name */
+    DCF_UNKNOWN_GROUP = 5,
+    /* A group has line thresholds, which need the numbers of changed lines */
+    DCF_UNKNOWN_LINE_COUNTS = 6,
+    /* The groups could not be evaluated */
+    DCF_DETECT_FAILED = 7,
+    /* The configuration holds a NUL byte, which C strings cannot */
+    DCF_NUL_BYTE = 8,
+    /* An argument has a value out of its range */
+    DCF_INVALID_ARGUMENT = 9,
+    DCF_UNCLOSED_SECTION_HEADER = 10,
+    DCF_EMPTY_SECTION_NAME = 11,
+    DCF_DUPLICATE_SECTION = 12,
+    DCF_ITEM_BEFORE_SECTION = 13,
+    DCF_UNKNOWN_OPTION = 14,
+    DCF_INVALID_OPTION_VALUE = 15,
+    DCF_INVALID_PATTERN = 16,
+    DCF_INVALID_REGION = 17,
+    DCF_UNKNOWN_DEPENDENCY = 18,
+    DCF_DEPENDENCY_CYCLE = 19,
+    DCF_INVALID_TEMPLATE = 20,
+    DCF_INVALID_SECTION_NAME = 21,
+} dcf_status;
+
+/* Location of the first error of an invalid configuration */
+typedef struct dcf_parse_error {
+    /* 1-based line number */
+    size_t line;
+    /* 1-based columns (in characters) of the offending span, the end being
+     * excluded */
+    size_t column;
+    size_t end_column;
+    /* Number of errors found in the configuration */
+    size_t count;
+} dcf_parse_error;
+
+/* Unicode normalization forms, as given to dcf_config_normalize_unicode */
+typedef enum dcf_unicode_form {
+    /* Canonical decomposition followed by canonical composition */
+    DCF_NFC = 1,
+    /* Canonical decomposition */
+    DCF_NFD = 2,
+} dcf_unicode_form;
+
+typedef struct dcf_config dcf_config;
+typedef struct dcf_changes dcf_changes;
+typedef struct dcf_results dcf_results;
+
+/* Parse a configuration given as text. On success, *config_out is set to a
+ * handle to release with dcf_config_free. On a configuration error, the
+ * status tells the kind of the first error and *error_out, if not NULL, its
+ * location. */
+dcf_status dcf_config_parse(const char *text, dcf_config **config_out,
+                            dcf_parse_error *error_out);
+
+/* Read and parse a configuration file, like dcf_config_parse */
+dcf_status dcf_config_load(const char *path, dcf_config **config_out,
+                           dcf_parse_error *error_out);
+
+/* Release a configuration. NULL is ignored. */
+void dcf_config_free(dcf_config *config);
+
+/* Number of groups of a configuration, 0 if config is NULL */
+size_t dcf_config_group_count(const dcf_config *config);
+
+/* Name of a group, in declaration order. The string belongs to the
+ * configuration. Returns NULL if config is NULL or index is out of range.
+ * Template sections are listed as declared, like "svc-{name}"; the groups
+ * they expand to are listed by the results. */
+const char *dcf_config_group_name(const dcf_config *config, size_t index);
+
+/* Convert the patterns of a configuration to a Unicode normalization form,
+ * a dcf_unicode_form, and the changed paths checked against it by
+ * dcf_detect likewise. Group names are left as written. */
+dcf_status dcf_config_normalize_unicode(dcf_config *config, int form);
+
+/* Create an empty set of changed paths, to release with dcf_changes_free */
+dcf_changes *dcf_changes_new(void);
+
+/* Add a changed path, relative to the repository root. It need not be valid
+ * UTF-8, and is normalized lexically like the paths given to the command
+ * line tool. */
+dcf_status dcf_changes_add_path(dcf_changes *changes, const char *path);
+
+/* Release a set of changed paths. NULL is ignored. */
+void dcf_changes_free(dcf_changes *changes);
+
+/* Check the changed paths against the groups of the configuration. On
+ * success, *results_out is set to a handle to release with
+ * dcf_results_free. Only the paths of the changes are known, so line-range
+ * rules match on their paths, and groups with line thresholds fail with
+ * DCF_UNKNOWN_LINE_COUNTS. Template sections are expanded for the
+ * directories of the changed paths. */
+dcf_status dcf_detect(const dcf_config *config, const dcf_changes *changes,
+                      int case_insensitive, dcf_results **results_out);
+
+/* Tell whether a group matched: *matched_out is set to 1 if it did and to 0
+ * otherwise */
+dcf_status dcf_results_matched(const dcf_results *results, const char *group,
+                               int *matched_out);
+
+/* Number of groups of the results, templates being expanded, 0 if results
+ * is NULL */
+size_t dcf_results_group_count(const dcf_results *results);
+
+/* Name of a group of the results, in configuration order, the groups of a
+ * template standing in its place. The string belongs to the results.
+ * Returns NULL if results is NULL or index is out of range. */
+const char *dcf_results_group_name(const dcf_results *results, size_t index);
+
+/* Release results. NULL is ignored. */
+void dcf_results_free(dcf_results *results);
+
+/* Describe a status code. The string is static. */
+const char *dcf_status_message(int status);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* DETECT_CHANGED_FILES_H */
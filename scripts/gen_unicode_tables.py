@@ -0,0 +1,111 @@
+#!/usr/bin/env python3
+# Generate src/unicode/tables.rs from the Unicode database shipped with Python
+#
+# Usage: python3 scripts/gen_unicode_tables.py > src/unicode/tables.rs
+
+import sys
+import unicodedata
+
+
+def rust_char(cp):
+    return "'\\u{%x}'" % cp
+
+
+def rust_str(s):
+    return '"' + "".join("\\u{%x}" % ord(c) for c in s) + '"'
+
+
+def case_folding():
+    entries = []
+    for cp in range(sys.maxunicode + 1):
+        c = chr(cp)
+        if unicodedata.category(c) == "Cs":
+            continue
+        folded = c.casefold()
+        if folded != c:
+            entries.append((cp, folded))
+    return entries
+
+
+def is_hangul_syllable(cp):
+    return 0xAC00 <= cp <= 0xD7A3
+
+
+def canonical_decompositions():
+    """Full canonical decompositions, Hangul syllables excluded since they
+    are decomposed algorithmically"""
+    entries = []
+    for cp in range(sys.maxunicode + 1):
+        c = chr(cp)
+        if is_hangul_syllable(cp) or unicodedata.category(c) == "Cs":
+            continue
+        decomposed = unicodedata.normalize("NFD", c)
+        if decomposed != c:
+            entries.append((cp, decomposed))
+    return entries
+
+
+def combining_classes():
+    entries = []
+    for cp in range(sys.maxunicode + 1):
+        ccc = unicodedata.combining(chr(cp))
+        if ccc != 0:
+            entries.append((cp, ccc))
+    return entries
+
+
+def compositions():
+    """Primary composites: pairs composing into a single character in NFC"""
+    entries = []
+    for cp in range(sys.maxunicode + 1):
+        c = chr(cp)
+        if is_hangul_syllable(cp):
+            continue
+        decomposition = unicodedata.decomposition(c)
+        if not decomposition or decomposition.startswith("<"):
+            continue
+        parts = [int(x, 16) for x in decomposition.split()]
+        if len(parts) != 2:
+            continue
+        if unicodedata.normalize("NFC", c) != c:
+            # Composition exclusion
+            continue
+        entries.append((parts[0], parts[1], cp))
+    entries.sort()
+    return entries
+
+
+def main():
+    out = sys.stdout
+    out.write("// Generated by scripts/gen_unicode_tables.py from Unicode %s.\n"
+              % unicodedata.unidata_version)
+    out.write("// Do not edit manually.\n\n")
+
+    out.write("/// Full case folding, sorted by code point\n")
+    out.write("pub static CASE_FOLDING: &[(char, &str)] = &[\n")
+    for cp, folded in case_folding():
+        out.write("    (%s, %s),\n" % (rust_char(cp), rust_str(folded)))
+    out.write("];\n\n")
+
+    out.write("/// Full canonical decompositions, sorted by code point\n")
+    out.write("pub static CANONICAL_DECOMPOSITION: &[(char, &str)] = &[\n")
+    for cp, decomposed in canonical_decompositions():
+        out.write("    (%s, %s),\n" % (rust_char(cp), rust_str(decomposed)))
+    out.write("];\n\n")
+
+    out.write("/// Non-zero canonical combining classes, sorted by code point\n")
+    out.write("pub static COMBINING_CLASS: &[(char, u8)] = &[\n")
+    for cp, ccc in combining_classes():
+        out.write("    (%s, %d),\n" % (rust_char(cp), ccc))
+    out.write("];\n\n")
+
+    out.write("/// Canonical compositions of pairs, sorted by pair\n")
+    out.write("pub static COMPOSITION: &[((char, char), char)] = &[\n")
+    for first, second, composed in compositions():
+        out.write("    ((%s, %s), %s),\n"
+                  % (rust_char(first), rust_char(second), rust_char(composed)))
+    out.write("];\n")
+
+
+if __name__ == "__main__":
+    main()
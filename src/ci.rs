@@ -0,0 +1,318 @@
+// Detect the range of changes to consider from the CI environment
+
+use crate::git::DiffRange;
+use crate::json;
+use std::fmt;
+use std::fs;
+use std::io;
+
+/// Range of changes found in the CI environment, and where it comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DetectedRange {
+    /// Range of changes to consider
+    pub range: DiffRange,
+    /// Description of where the range comes from, like "GitHub pull request"
+    pub source: String,
+}
+
+/// Error raised when the CI environment cannot be read
+#[derive(Debug)]
+pub enum CiError {
+    /// The GitHub event payload could not be read
+    EventFile {
+        /// Path of the payload, from GITHUB_EVENT_PATH
+        path: String,
+        /// Error raised when reading it
+        error: io::Error,
+    },
+    /// The GitHub event payload is not valid JSON
+    InvalidEvent {
+        /// Path of the payload, from GITHUB_EVENT_PATH
+        path: String,
+        /// Error raised when parsing it
+        error: json::JsonError,
+    },
+}
+
+impl fmt::Display for CiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CiError::EventFile { path, error } => {
+                write!(f, "cannot read event payload '{}': {}", path, error)
+            }
+            CiError::InvalidEvent { path, error } => {
+                write!(f, "cannot parse event payload '{}': {}", path, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CiError {}
+
+/// SHA used by CI systems when there is no previous commit, like when
+/// pushing a new branch
+fn is_null_sha(sha: &str) -> bool {
+    !sha.is_empty() && sha.chars().all(|c| c == '0')
+}
+
+fn detected(base: &str, head: &str, source: &str) -> DetectedRange {
+    DetectedRange {
+        range: DiffRange::Commits {
+            base: base.to_string(),
+            head: head.to_string(),
+        },
+        source: source.to_string(),
+    }
+}
+
+/// Range used when nothing better is known: the last commit
+fn fallback(source: &str) -> DetectedRange {
+    detected("HEAD^", "HEAD", source)
+}
+
+/// Base to use for a new branch: the default branch, unless the new branch is
+/// the default branch itself
+fn new_branch_base(default_branch: Option<&str>, branch: Option<&str>, head: &str) -> String {
+    match default_branch {
+        Some(default) if branch != Some(default) => format!("origin/{}", default),
+        _ => format!("{}^", head),
+    }
+}
+
+/// Pick the range from a GitHub Actions event payload
+fn github_range(event_name: &str, payload: &json::Value) -> DetectedRange {
+    let sha = |keys: &[&str]| {
+        payload
+            .pointer(keys)
+            .and_then(json::Value::as_str)
+            .filter(|sha| !sha.is_empty())
+    };
+    let source = format!("GitHub {} event", event_name);
+
+    match event_name {
+        "pull_request"
+        | "pull_request_target"
+        | "pull_request_review"
+        | "pull_request_review_comment" => {
+            match (
+                sha(&["pull_request", "base", "sha"]),
+                sha(&["pull_request", "head", "sha"]),
+            ) {
+                (Some(base), Some(head)) => detected(base, head, &source),
+                _ => fallback(&format!("{} without base and head, last commit", source)),
+            }
+        }
+        "merge_group" => match (
+            sha(&["merge_group", "base_sha"]),
+            sha(&["merge_group", "head_sha"]),
+        ) {
+            (Some(base), Some(head)) => detected(base, head, &source),
+            _ => fallback(&format!("{} without base and head, last commit", source)),
+        },
+        "push" => match (sha(&["before"]), sha(&["after"])) {
+            (Some(before), Some(after)) if is_null_sha(before) => {
+                let branch = sha(&["ref"]).and_then(|r| r.strip_prefix("refs/heads/"));
+                let base = new_branch_base(sha(&["repository", "default_branch"]), branch, after);
+                detected(&base, after, &format!("{} creating a branch", source))
+            }
+            (Some(before), Some(after)) => detected(before, after, &source),
+            _ => fallback(&format!("{} without before and after, last commit", source)),
+        },
+        _ => fallback(&format!("{}, last commit", source)),
+    }
+}
+
+/// Pick the range from GitLab CI predefined variables
+fn gitlab_range(env: &dyn Fn(&str) -> Option<String>) -> DetectedRange {
+    let head = env("CI_COMMIT_SHA").unwrap_or_else(|| "HEAD".to_string());
+
+    if let Some(base) = env("CI_MERGE_REQUEST_DIFF_BASE_SHA") {
+        return detected(&base, &head, "GitLab merge request");
+    }
+    match env("CI_COMMIT_BEFORE_SHA") {
+        Some(before) if is_null_sha(&before) => {
+            let base = new_branch_base(
+                env("CI_DEFAULT_BRANCH").as_deref(),
+                env("CI_COMMIT_BRANCH").as_deref(),
+                &head,
+            );
+            detected(&base, &head, "GitLab push creating a branch")
+        }
+        Some(before) => detected(&before, &head, "GitLab push"),
+        None => fallback("GitLab pipeline without previous commit, last commit"),
+    }
+}
+
+/// Pick the range from Bitbucket Pipelines variables
+fn bitbucket_range(env: &dyn Fn(&str) -> Option<String>) -> DetectedRange {
+    let head = env("BITBUCKET_COMMIT").unwrap_or_else(|| "HEAD".to_string());
+    match env("BITBUCKET_PR_DESTINATION_BRANCH") {
+        Some(branch) => detected(
+            &format!("origin/{}", branch),
+            &head,
+            "Bitbucket pull request",
+        ),
+        None => fallback("Bitbucket pipeline, last commit"),
+    }
+}
+
+/// Detect the range of changes from the CI environment, `env` giving access
+/// to the environment variables. Falls back to the last commit. Empty
+/// variables count as unset.
+pub fn detect_range(env: &dyn Fn(&str) -> Option<String>) -> Result<DetectedRange, CiError> {
+    let env = |name: &str| env(name).filter(|value| !value.is_empty());
+    let is_set = |name: &str| env(name).is_some();
+
+    if is_set("GITHUB_ACTIONS") {
+        let event_name = env("GITHUB_EVENT_NAME").unwrap_or_default();
+        let Some(path) = env("GITHUB_EVENT_PATH") else {
+            return Ok(fallback(
+                "GitHub Actions without event payload, last commit",
+            ));
+        };
+        let content = fs::read_to_string(&path).map_err(|error| CiError::EventFile {
+            path: path.clone(),
+            error,
+        })?;
+        let payload =
+            json::parse(&content).map_err(|error| CiError::InvalidEvent { path, error })?;
+        return Ok(github_range(&event_name, &payload));
+    }
+    if is_set("GITLAB_CI") {
+        return Ok(gitlab_range(&env));
+    }
+    if is_set("BITBUCKET_BUILD_NUMBER") {
+        return Ok(bitbucket_range(&env));
+    }
+    Ok(fallback("no CI environment detected, last commit"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commits(range: &DetectedRange) -> (&str, &str) {
+        match &range.range {
+            DiffRange::Commits { base, head } => (base, head),
+            other => panic!("unexpected range {:?}", other),
+        }
+    }
+
+    fn env_from<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| {
+            vars.iter()
+                .find(|(k, _)| *k == name)
+                .map(|(_, v)| v.to_string())
+        }
+    }
+
+    #[test]
+    fn github_pull_request() {
+        let payload =
+            json::parse(r#"{"pull_request": {"base": {"sha": "b1"}, "head": {"sha": "h1"}}}"#)
+                .unwrap();
+        let range = github_range("pull_request", &payload);
+        assert_eq!(commits(&range), ("b1", "h1"));
+        assert_eq!(range.source, "GitHub pull_request event");
+    }
+
+    #[test]
+    fn github_push() {
+        let payload = json::parse(r#"{"before": "aaa", "after": "bbb"}"#).unwrap();
+        assert_eq!(commits(&github_range("push", &payload)), ("aaa", "bbb"));
+
+        let null = "0000000000000000000000000000000000000000";
+        let payload = json::parse(&format!(
+            r#"{{"ref": "refs/heads/feature", "before": "{}", "after": "bbb",
+                "repository": {{"default_branch": "main"}}}}"#,
+            null
+        ))
+        .unwrap();
+        assert_eq!(
+            commits(&github_range("push", &payload)),
+            ("origin/main", "bbb")
+        );
+
+        let payload = json::parse(&format!(
+            r#"{{"ref": "refs/heads/main", "before": "{}", "after": "bbb",
+                "repository": {{"default_branch": "main"}}}}"#,
+            null
+        ))
+        .unwrap();
+        assert_eq!(commits(&github_range("push", &payload)), ("bbb^", "bbb"));
+    }
+
+    #[test]
+    fn github_other_events() {
+        let payload = json::parse("{}").unwrap();
+        assert_eq!(
+            commits(&github_range("workflow_dispatch", &payload)),
+            ("HEAD^", "HEAD")
+        );
+        assert_eq!(
+            commits(&github_range("pull_request", &payload)),
+            ("HEAD^", "HEAD")
+        );
+        let payload = json::parse(r#"{"before": "", "after": "bbb"}"#).unwrap();
+        assert_eq!(commits(&github_range("push", &payload)), ("HEAD^", "HEAD"));
+    }
+
+    #[test]
+    fn gitlab_variables() {
+        let vars = [
+            ("GITLAB_CI", "true"),
+            ("CI_COMMIT_SHA", "h2"),
+            ("CI_MERGE_REQUEST_DIFF_BASE_SHA", "b2"),
+            (
+                "CI_COMMIT_BEFORE_SHA",
+                "0000000000000000000000000000000000000000",
+            ),
+        ];
+        let range = detect_range(&env_from(&vars)).unwrap();
+        assert_eq!(commits(&range), ("b2", "h2"));
+        assert_eq!(range.source, "GitLab merge request");
+
+        let vars = [
+            ("GITLAB_CI", "true"),
+            ("CI_COMMIT_SHA", "h2"),
+            (
+                "CI_COMMIT_BEFORE_SHA",
+                "0000000000000000000000000000000000000000",
+            ),
+            ("CI_COMMIT_BRANCH", "topic"),
+            ("CI_DEFAULT_BRANCH", "trunk"),
+        ];
+        let range = detect_range(&env_from(&vars)).unwrap();
+        assert_eq!(commits(&range), ("origin/trunk", "h2"));
+
+        // Empty variables count as unset
+        let vars = [
+            ("GITLAB_CI", "true"),
+            ("CI_COMMIT_SHA", ""),
+            ("CI_MERGE_REQUEST_DIFF_BASE_SHA", ""),
+            ("CI_COMMIT_BEFORE_SHA", "b3"),
+        ];
+        let range = detect_range(&env_from(&vars)).unwrap();
+        assert_eq!(commits(&range), ("b3", "HEAD"));
+        assert_eq!(range.source, "GitLab push");
+    }
+
+    #[test]
+    fn no_ci_environment() {
+        let range = detect_range(&env_from(&[])).unwrap();
+        assert_eq!(commits(&range), ("HEAD^", "HEAD"));
+    }
+
+    #[test]
+    fn github_missing_event_file() {
+        let vars = [
+            ("GITHUB_ACTIONS", "true"),
+            ("GITHUB_EVENT_NAME", "push"),
+            ("GITHUB_EVENT_PATH", "/nonexistent/event.json"),
+        ];
+        assert!(matches!(
+            detect_range(&env_from(&vars)),
+            Err(CiError::EventFile { .. })
+        ));
+    }
+}
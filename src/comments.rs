@@ -0,0 +1,326 @@
+// Comment syntaxes of common languages, to tell cosmetic changes from real
+// ones
+
+/// How comments and string literals are written in a language
+#[derive(Debug)]
+pub struct Syntax {
+    /// Markers starting a comment running to the end of the line. A "#"
+    /// marker only starts a comment at the start of a word.
+    line: &'static [&'static str],
+    /// Markers opening and closing a block comment
+    block: Option<(&'static str, &'static str)>,
+    /// Delimiters of string literals, in which comment markers and
+    /// whitespace are significant
+    quotes: &'static [char],
+    /// Whether single quotes delimit character literals, like 'a' or '\n',
+    /// and are left alone otherwise, like Rust lifetimes
+    char_literals: bool,
+    /// Whether the indentation of lines is significant, like in Python
+    indentation: bool,
+}
+
+const C_LIKE: Syntax = Syntax {
+    line: &["//"],
+    block: Some(("/*", "*/")),
+    quotes: &['"'],
+    char_literals: true,
+    indentation: false,
+};
+
+const JS_LIKE: Syntax = Syntax {
+    line: &["//"],
+    block: Some(("/*", "*/")),
+    quotes: &['"', '\'', '`'],
+    char_literals: false,
+    indentation: false,
+};
+
+const CSS: Syntax = Syntax {
+    line: &[],
+    block: Some(("/*", "*/")),
+    quotes: &['"', '\''],
+    char_literals: false,
+    indentation: false,
+};
+
+const HASH: Syntax = Syntax {
+    line: &["#"],
+    block: None,
+    quotes: &['"', '\''],
+    char_literals: false,
+    indentation: false,
+};
+
+/// Hash comments in a language where indentation matters
+const INDENTED_HASH: Syntax = Syntax {
+    indentation: true,
+    ..HASH
+};
+
+const INI: Syntax = Syntax {
+    line: &["#", ";"],
+    block: None,
+    quotes: &['"', '\''],
+    char_literals: false,
+    indentation: false,
+};
+
+const SQL: Syntax = Syntax {
+    line: &["--"],
+    block: Some(("/*", "*/")),
+    quotes: &['"', '\''],
+    char_literals: false,
+    indentation: false,
+};
+
+const LUA: Syntax = Syntax {
+    line: &["--"],
+    block: None,
+    quotes: &['"', '\''],
+    char_literals: false,
+    indentation: false,
+};
+
+const HASKELL: Syntax = Syntax {
+    line: &["--"],
+    block: Some(("{-", "-}")),
+    quotes: &['"'],
+    char_literals: true,
+    indentation: true,
+};
+
+const MARKUP: Syntax = Syntax {
+    line: &[],
+    block: Some(("<!--", "-->")),
+    quotes: &[],
+    char_literals: false,
+    indentation: false,
+};
+
+/// The comment syntax of a file, guessed from its name
+pub fn syntax_for(path: &str) -> Option<&'static Syntax> {
+    let name = path.rsplit('/').next().unwrap_or(path).to_lowercase();
+    match name.as_str() {
+        "makefile" | "gnumakefile" | "justfile" => return Some(&INDENTED_HASH),
+        "dockerfile" | "cmakelists.txt" => return Some(&HASH),
+        _ => {}
+    }
+    let (_, extension) = name.rsplit_once('.')?;
+    Some(match extension {
+        "c" | "h" | "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" | "m" | "rs" | "go" | "java"
+        | "kt" | "kts" | "swift" | "cs" | "scala" | "dart" | "proto" | "groovy" | "gradle" => {
+            &C_LIKE
+        }
+        "js" | "jsx" | "mjs" | "cjs" | "ts" | "tsx" | "mts" | "cts" => &JS_LIKE,
+        "css" => &CSS,
+        "py" | "pyi" | "yaml" | "yml" | "mk" => &INDENTED_HASH,
+        "sh" | "bash" | "zsh" | "rb" | "pl" | "pm" | "toml" | "cmake" | "r" | "tf" | "nix"
+        | "dockerfile" => &HASH,
+        "ini" | "cfg" | "conf" => &INI,
+        "sql" => &SQL,
+        "lua" => &LUA,
+        "hs" => &HASKELL,
+        "html" | "htm" | "xml" | "svg" | "xhtml" => &MARKUP,
+        _ => return None,
+    })
+}
+
+fn starts_with(chars: &[char], pos: usize, marker: &str) -> bool {
+    (pos..)
+        .zip(marker.chars())
+        .all(|(i, m)| chars.get(i) == Some(&m))
+}
+
+/// Position just after the next occurrence of `marker` from `pos`, or the
+/// end of the text
+fn skip_past(chars: &[char], pos: usize, marker: &str) -> usize {
+    (pos..chars.len())
+        .find(|&i| starts_with(chars, i, marker))
+        .map_or(chars.len(), |i| i + marker.chars().count())
+}
+
+/// End of the string literal opened at `pos`, honoring backslash escapes
+fn string_end(chars: &[char], pos: usize) -> usize {
+    let quote = chars[pos];
+    let mut i = pos + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 2,
+            c if c == quote => return i + 1,
+            _ => i += 1,
+        }
+    }
+    chars.len()
+}
+
+/// End of the character literal opened at `pos`, if there is one
+fn char_literal_end(chars: &[char], pos: usize) -> Option<usize> {
+    match chars.get(pos + 1)? {
+        '\\' => (pos + 3..chars.len().min(pos + 12))
+            .find(|&i| chars[i] == '\'')
+            .map(|i| i + 1),
+        _ => (chars.get(pos + 2) == Some(&'\'')).then_some(pos + 3),
+    }
+}
+
+/// Remove the whitespace of a text, and its comments when `comments` is set.
+/// String literals are kept as they are, so that changes inside them remain
+/// visible. Without a syntax, only whitespace is removed. When indentation
+/// is significant, each line keeps its indentation and its line break,
+/// except lines holding only whitespace and comments.
+pub fn strip(text: &str, syntax: Option<&Syntax>, comments: bool) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let indentation = syntax.is_some_and(|s| s.indentation);
+    let mut out = String::new();
+    // Leading whitespace of the current line, until part of it is kept
+    let mut indent = Some(String::new());
+    let keep = |out: &mut String, indent: &mut Option<String>, kept: &[char]| {
+        if indentation && let Some(indent) = indent.take() {
+            out.push('\n');
+            out.push_str(&indent);
+        }
+        out.extend(kept);
+    };
+    let mut pos = 0;
+    while pos < chars.len() {
+        let c = chars[pos];
+        if let Some(syntax) = syntax {
+            if comments {
+                if let Some((open, close)) = syntax.block
+                    && starts_with(&chars, pos, open)
+                {
+                    pos = skip_past(&chars, pos + open.chars().count(), close);
+                    continue;
+                }
+                let at_word_start = pos == 0 || chars[pos - 1].is_whitespace();
+                if syntax.line.iter().any(|marker| {
+                    starts_with(&chars, pos, marker) && (!marker.starts_with('#') || at_word_start)
+                }) {
+                    pos = skip_past(&chars, pos, "\n");
+                    indent = Some(String::new());
+                    continue;
+                }
+            }
+            if syntax.quotes.contains(&c) || (c == '\'' && syntax.char_literals) {
+                let end = if c == '\'' && syntax.char_literals {
+                    char_literal_end(&chars, pos)
+                } else {
+                    Some(string_end(&chars, pos))
+                };
+                if let Some(end) = end {
+                    keep(&mut out, &mut indent, &chars[pos..end]);
+                    pos = end;
+                    continue;
+                }
+            }
+        }
+        if c == '\n' {
+            indent = Some(String::new());
+        } else if c.is_whitespace() {
+            if let Some(indent) = &mut indent {
+                indent.push(c);
+            }
+        } else {
+            keep(&mut out, &mut indent, &[c]);
+        }
+        pos += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn same(path: &str, old: &str, new: &str, comments: bool) -> bool {
+        let syntax = syntax_for(path);
+        strip(old, syntax, comments) == strip(new, syntax, comments)
+    }
+
+    #[test]
+    fn whitespace_changes() {
+        assert!(same(
+            "a.rs",
+            "fn main() {\n    run();\n}\n",
+            "fn main() { run(); }",
+            false
+        ));
+        assert!(same("a.txt", "a b\n", "a\tb  \n\n", false));
+        assert!(!same(
+            "a.rs",
+            "fn main() { run(); }",
+            "fn main() { walk(); }",
+            false
+        ));
+        // Whitespace is significant in string literals
+        assert!(!same("a.rs", "let s = \"a b\";", "let s = \"ab\";", false));
+        assert!(!same("a.py", "s = 'a b'", "s = 'ab'", false));
+        assert!(same("a.txt", "s = 'a b'", "s = 'ab'", false));
+        // Comments only count as whitespace when asked
+        assert!(!same("a.rs", "run();", "run(); // go", false));
+    }
+
+    #[test]
+    fn indentation_changes() {
+        let body = "if a:\n    b()\n    c()\n";
+        assert!(!same("a.py", body, "if a:\n    b()\nc()\n", false));
+        assert!(!same("a.py", body, "if a: b(); c()\n", false));
+        assert!(!same("config.yml", "a:\n  b: 1\n", "a:\nb: 1\n", false));
+        assert!(!same("Makefile", "all:\n\tmake\n", "all:\nmake\n", false));
+        // Other whitespace, blank lines and comment lines still don't count
+        assert!(same(
+            "a.py",
+            body,
+            "if a :\n\n    b( )  \n    # c\n    c()\n",
+            true
+        ));
+        assert!(same(
+            "config.yaml",
+            "a:\n  b: 1\n",
+            "a:  # x\n  b:   1\n",
+            true
+        ));
+        // A string literal starts its line like other code
+        assert!(!same("a.py", "x = (\n    's')\n", "x = (\n's')\n", false));
+    }
+
+    #[test]
+    fn comment_changes() {
+        assert!(same("a.rs", "run(); // go", "run(); // walk", true));
+        assert!(same("a.c", "/* a\n * b */\nint x;", "int x; /* c */", true));
+        assert!(same("a.py", "x = 1  # one\n", "# header\nx = 1\n", true));
+        assert!(same("a.sql", "SELECT 1; -- a", "SELECT 1;", true));
+        assert!(same("a.html", "<p>a</p><!-- x -->", "<p>a</p>", true));
+        assert!(same(
+            "Makefile",
+            "all:\n\t# a\n\tmake",
+            "all:\n\tmake",
+            true
+        ));
+        assert!(!same("a.rs", "run(); // go", "walk(); // go", true));
+        // Comment markers in strings are not comments
+        assert!(!same(
+            "a.rs",
+            "let u = \"http://a\";",
+            "let u = \"http://b\";",
+            true
+        ));
+        assert!(!same("a.js", "s = '/* a */'", "s = '/* b */'", true));
+        assert!(!same(
+            "a.rs",
+            "let c = '\"'; let u = \"//a\";",
+            "let c = '\"'; let u = \"//b\";",
+            true
+        ));
+        assert!(!same("a.sh", "echo ${#a}", "echo ${#b}", true));
+        // Lifetimes are not character literals
+        assert!(same(
+            "a.rs",
+            "fn f<'a>(x: &'a str) {} // a",
+            "fn f<'a>(x: &'a str) {}",
+            true
+        ));
+        // Unknown languages have no comments
+        assert!(!same("a.txt", "a # b", "a # c", true));
+    }
+}
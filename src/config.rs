@@ -1,25 +1,573 @@
-use std::collections::HashMap;
+use crate::matching::{MatchPath, Pattern};
+use crate::region::Region;
+use crate::unicode::NormalizationForm;
 use std::fmt;
+use std::ops::Index;
+use std::slice;
 
+/// A pattern, optionally restricted to a region of the matching files
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Rule {
+    /// Pattern matched against the changed paths
+    pub pattern: Pattern,
+    /// The rule only matches when lines of this region changed
+    pub region: Option<Region>,
+}
+
+/// The rules of a group, in declaration order
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct PatternSet {
+    pub(crate) rules: Vec<Rule>,
+}
+
+impl PatternSet {
+    /// Convert the patterns to a Unicode normalization form. Regions are
+    /// left as they are, as they apply to the contents of the files.
+    pub fn normalize_unicode(&mut self, form: NormalizationForm) {
+        for rule in &mut self.rules {
+            rule.pattern = rule.pattern.normalize_unicode(form);
+        }
+    }
+
+    /// Number of rules
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Whether the group has no rule
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Iterate over the rules in declaration order
+    pub fn iter(&self) -> slice::Iter<'_, Rule> {
+        self.rules.iter()
+    }
+
+    /// Whether the path matches one of the patterns, whatever the regions
+    /// of the rules
+    pub fn matches_path(&self, path: &MatchPath, ignore_case: bool) -> bool {
+        self.rules.iter().any(|rule| {
+            if ignore_case {
+                rule.pattern.is_match_ignore_case(path)
+            } else {
+                rule.pattern.is_match(path)
+            }
+        })
+    }
+}
+
+impl Index<usize> for PatternSet {
+    type Output = Rule;
+
+    fn index(&self, index: usize) -> &Rule {
+        &self.rules[index]
+    }
+}
+
+impl<'a> IntoIterator for &'a PatternSet {
+    type Item = &'a Rule;
+    type IntoIter = slice::Iter<'a, Rule>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// A group of patterns, defined by a section of the configuration file
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Group {
+    /// Name of the section
+    pub name: String,
+    /// Rules of the section, in declaration order
+    pub patterns: PatternSet,
+    /// Whether patterns of this group ignore case differences. When not set
+    /// in the section, the global setting applies.
+    pub case_insensitive: Option<bool>,
+    /// Disregard files whose changes only touch whitespace
+    pub ignore_whitespace: bool,
+    /// Disregard files whose changes only touch comments or whitespace
+    pub ignore_comments: bool,
+    /// The group only matches when more lines than this were added or
+    /// deleted in its files
+    pub changed_lines_above: Option<usize>,
+    /// The group only matches when more lines than this were added
+    pub added_lines_above: Option<usize>,
+    /// The group only matches when more lines than this were deleted
+    pub deleted_lines_above: Option<usize>,
+    /// Fields set with `meta.<key> = value` options, in declaration order,
+    /// added to the entry of the group in the matrix output
+    pub metadata: Vec<(String, String)>,
+    /// Groups set with `depends-on`: the group is changed when one of them
+    /// is
+    pub depends_on: Vec<String>,
+    /// Placeholder of a template section, like `name` in `[svc-{name}]`,
+    /// standing for a path component in its patterns. Templates are
+    /// replaced by groups with [`Config::expand_templates`].
+    pub placeholder: Option<String>,
+}
+
+impl Group {
+    fn new(name: &str) -> Self {
+        Group {
+            name: name.to_string(),
+            patterns: PatternSet::default(),
+            case_insensitive: None,
+            ignore_whitespace: false,
+            ignore_comments: false,
+            changed_lines_above: None,
+            added_lines_above: None,
+            deleted_lines_above: None,
+            metadata: Vec::new(),
+            depends_on: Vec::new(),
+            placeholder: None,
+        }
+    }
+
+    /// Whether the group depends on the numbers of changed lines
+    pub fn has_thresholds(&self) -> bool {
+        self.changed_lines_above.is_some()
+            || self.added_lines_above.is_some()
+            || self.deleted_lines_above.is_some()
+    }
+}
+
+/// The parsed configuration, with groups kept in declaration order
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Config {
+    /// Groups, one per section
+    pub groups: Vec<Group>,
+}
+
+impl Config {
+    /// Parse a configuration, see [`parse_config`]
+    pub fn parse(content: &str) -> Result<Self, Vec<ParseError>> {
+        parse_config(content)
+    }
+
+    /// Find a group by name
+    pub fn get(&self, name: &str) -> Option<&Group> {
+        self.groups.iter().find(|g| g.name == name)
+    }
+
+    /// Whether a group has this name
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Convert the patterns of every group to a Unicode normalization form,
+    /// to match the paths converted with [`PathNormalization::unicode`].
+    /// Group names are left as written.
+    ///
+    /// [`PathNormalization::unicode`]: crate::PathNormalization::unicode
+    pub fn normalize_unicode(&mut self, form: NormalizationForm) {
+        for group in &mut self.groups {
+            group.patterns.normalize_unicode(form);
+        }
+    }
+}
+
+impl Index<&str> for Config {
+    type Output = Group;
+
+    fn index(&self, name: &str) -> &Group {
+        self.get(name)
+            .unwrap_or_else(|| panic!("no group named '{}'", name))
+    }
+}
+
+/// Kind of a problem found in the configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// A section header without its closing "]"
+    UnclosedSectionHeader,
+    /// A section header with an empty name, like "[ ]"
+    EmptySectionName,
+    /// Two sections with the same name
+    DuplicateSection,
+    /// A pattern or option before the first section header
+    ItemBeforeSection,
+    /// A metadata option whose key is invalid or reserved
+    UnknownOption,
+    /// A section option with a value of the wrong type
+    InvalidOptionValue,
+    /// A pattern with a syntax error
+    InvalidPattern,
+    /// The region of a line-range rule with a syntax error
+    InvalidRegion,
+    /// A `depends-on` option naming a group that does not exist
+    UnknownDependency,
+    /// Groups depending on each other through `depends-on` options
+    DependencyCycle,
+    /// A template section with an invalid placeholder, or whose placeholder
+    /// is not a whole path component of its patterns
+    InvalidTemplate,
+    /// A section name that looks like a character class, like "[!]" or
+    /// "[^a]"
+    InvalidSectionName,
+}
+
+/// Problem found in the configuration, located by its span
 #[derive(Debug, Clone)]
 pub struct ParseError {
+    /// Kind of the problem
+    pub kind: ParseErrorKind,
+    /// 1-based line number
     pub line: usize,
+    /// 1-based column (in characters) where the offending span starts
+    pub column: usize,
+    /// 1-based column (in characters) one past the end of the offending span
+    pub end_column: usize,
+    /// Description of the problem
     pub message: String,
 }
 
+impl ParseError {
+    fn new(kind: ParseErrorKind, line: usize, columns: (usize, usize), message: String) -> Self {
+        ParseError {
+            kind,
+            line,
+            column: columns.0,
+            end_column: columns.1.max(columns.0 + 1),
+            message,
+        }
+    }
+
+    /// Render the error in a compiler-like format, quoting the offending line
+    /// of `source` and underlining the span with carets.
+    pub fn render(&self, source: &str, origin: &str) -> String {
+        let source_line = source.lines().nth(self.line - 1).unwrap_or("");
+        let gutter = " ".repeat(self.line.to_string().len());
+        let underline = format!(
+            "{}{}",
+            " ".repeat(self.column - 1),
+            "^".repeat(self.end_column - self.column)
+        );
+
+        format!(
+            "error: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}\n",
+            self.message,
+            gutter,
+            origin,
+            self.line,
+            self.column,
+            gutter,
+            self.line,
+            source_line,
+            gutter,
+            underline
+        )
+    }
+}
+
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Parse error at line {}: {}", self.line, self.message)
+        write!(
+            f,
+            "Parse error at line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
     }
 }
 
 impl std::error::Error for ParseError {}
 
-/// Parses a configuration string into a HashMap of sections and their items.
-pub fn parse_config(content: &str) -> Result<HashMap<String, Vec<String>>, ParseError> {
-    let mut result = HashMap::new();
-    let mut current_section = String::new();
-    let mut vec_section: Vec<String> = Vec::new();
+/// Returns the 1-based column span (in characters) of `trimmed` within `line`.
+fn span_of(line: &str, trimmed: &str) -> (usize, usize) {
+    let offset = trimmed.as_ptr() as usize - line.as_ptr() as usize;
+    let start = line[..offset].chars().count() + 1;
+    (start, start + trimmed.chars().count())
+}
+
+/// Names of the fields of the entries of the matrix output, which metadata
+/// cannot use
+const RESERVED_METADATA_KEYS: [&str; 4] = ["group", "files", "added", "deleted"];
+
+/// Names of the section options, besides metadata options
+const OPTION_NAMES: [&str; 7] = [
+    "case-insensitive",
+    "ignore-whitespace",
+    "ignore-comments",
+    "changed-lines-above",
+    "added-lines-above",
+    "deleted-lines-above",
+    "depends-on",
+];
+
+/// Split a section option line of the form `name = value`, where `name` is
+/// one of [`OPTION_NAMES`] or starts with `meta.`. Other lines, like
+/// `a=b.txt`, are patterns.
+fn split_option(trimmed: &str) -> Option<(&str, &str)> {
+    let (name, value) = trimmed.split_once('=')?;
+    let name = name.trim_end();
+    let is_option = name.starts_with("meta.") || OPTION_NAMES.contains(&name);
+    is_option.then(|| (name, value.trim_start()))
+}
+
+/// Split a rule into its pattern and its region, separated by the first
+/// colon followed by a line number or a slash. Such a colon is part of the
+/// pattern when escaped as `\:`.
+fn split_rule(trimmed: &str) -> (&str, Option<&str>) {
+    for (i, _) in trimmed.match_indices(':') {
+        let region = &trimmed[i + 1..];
+        let escaped = trimmed[..i].ends_with('\\');
+        if !escaped && region.starts_with(|c: char| c == '/' || c.is_ascii_digit()) {
+            return (&trimmed[..i], Some(region));
+        }
+    }
+    (trimmed, None)
+}
+
+/// Split the value of a `depends-on` option into group names, separated by
+/// commas or whitespace
+fn dependency_names(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|name| !name.is_empty())
+}
+
+/// Find the placeholder of a template section name, like `name` in
+/// `svc-{name}`
+fn section_placeholder(name: &str) -> Result<Option<&str>, &'static str> {
+    let Some(open) = name.find(['{', '}']) else {
+        return Ok(None);
+    };
+    if name[open..].starts_with('}') {
+        return Err("unmatched '}' in section name");
+    }
+    let Some(close) = name[open..].find('}').map(|i| open + i) else {
+        return Err("unclosed '{' in section name");
+    };
+    let placeholder = &name[open + 1..close];
+    if name[close + 1..].contains(['{', '}']) {
+        return Err("a section name has at most one placeholder");
+    }
+    let is_name = placeholder.starts_with(|c: char| c.is_ascii_lowercase())
+        && placeholder
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if !is_name {
+        return Err("placeholders are made of lowercase ASCII letters, digits and underscores");
+    }
+    Ok(Some(placeholder))
+}
+
+/// Whether every occurrence of `token` in the pattern is a whole path
+/// component
+fn is_whole_component(pattern: &str, token: &str) -> bool {
+    pattern.match_indices(token).all(|(i, _)| {
+        (i == 0 || pattern[..i].ends_with('/'))
+            && (pattern[i + token.len()..].is_empty()
+                || pattern[i + token.len()..].starts_with('/'))
+    })
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" | "yes" | "on" => Some(true),
+        "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Apply a section option to the group being parsed
+fn apply_option(
+    group: &mut Group,
+    line: &str,
+    line_number: usize,
+    name: &str,
+    value: &str,
+) -> Result<(), ParseError> {
+    let value_error = |message: String| {
+        ParseError::new(
+            ParseErrorKind::InvalidOptionValue,
+            line_number,
+            span_of(line, value),
+            message,
+        )
+    };
+
+    let flag = || {
+        parse_bool(value).ok_or_else(|| {
+            value_error(format!("Invalid boolean value for '{}': '{}'", name, value))
+        })
+    };
+
+    let count = || {
+        value
+            .parse()
+            .map_err(|_| value_error(format!("Invalid line count for '{}': '{}'", name, value)))
+    };
+
+    match name {
+        "case-insensitive" => group.case_insensitive = Some(flag()?),
+        "ignore-whitespace" => group.ignore_whitespace = flag()?,
+        "ignore-comments" => group.ignore_comments = flag()?,
+        "changed-lines-above" => group.changed_lines_above = Some(count()?),
+        "added-lines-above" => group.added_lines_above = Some(count()?),
+        "deleted-lines-above" => group.deleted_lines_above = Some(count()?),
+        "depends-on" => {
+            for dependency in dependency_names(value) {
+                if !group.depends_on.iter().any(|d| d == dependency) {
+                    group.depends_on.push(dependency.to_string());
+                }
+            }
+        }
+        // Besides the options above, only metadata options are split
+        _ => {
+            let key = &name["meta.".len()..];
+            // Underscores are allowed too, as they are common in matrix keys
+            let is_key = key.starts_with(|c: char| c.is_ascii_lowercase())
+                && key
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+            if !is_key {
+                return Err(ParseError::new(
+                    ParseErrorKind::UnknownOption,
+                    line_number,
+                    span_of(line, name),
+                    format!("Invalid metadata key: '{}'", key),
+                ));
+            }
+            if RESERVED_METADATA_KEYS.contains(&key) {
+                return Err(ParseError::new(
+                    ParseErrorKind::UnknownOption,
+                    line_number,
+                    span_of(line, name),
+                    format!("Reserved metadata key: '{}'", key),
+                ));
+            }
+            match group.metadata.iter_mut().find(|(k, _)| k == key) {
+                Some((_, v)) => *v = value.to_string(),
+                None => group.metadata.push((key.to_string(), value.to_string())),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A `depends-on` option line, to be checked once every group is known
+struct DependencyLine<'a> {
+    group: usize,
+    line_number: usize,
+    line: &'a str,
+    value: &'a str,
+}
+
+/// A dependency between groups, located by the name of its target
+#[derive(Clone, Copy)]
+struct Dependency {
+    target: usize,
+    line_number: usize,
+    span: (usize, usize),
+}
+
+/// Check that the groups named by `depends-on` options exist and that no
+/// group depends on itself, directly or through other groups
+fn check_dependencies(config: &Config, lines: &[DependencyLine]) -> Vec<ParseError> {
+    let mut errors = Vec::new();
+    // Dependencies of each group
+    let mut dependencies: Vec<Vec<Dependency>> = vec![Vec::new(); config.groups.len()];
+    for dependency_line in lines {
+        for name in dependency_names(dependency_line.value) {
+            let span = span_of(dependency_line.line, name);
+            match config.groups.iter().position(|g| g.name == name) {
+                Some(target) => dependencies[dependency_line.group].push(Dependency {
+                    target,
+                    line_number: dependency_line.line_number,
+                    span,
+                }),
+                None => errors.push(ParseError::new(
+                    ParseErrorKind::UnknownDependency,
+                    dependency_line.line_number,
+                    span,
+                    format!("Unknown group in depends-on: '{}'", name),
+                )),
+            }
+        }
+    }
+
+    // Depth-first search, reporting each cycle at the dependency closing it
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        New,
+        Visiting,
+        Done,
+    }
+    fn visit(
+        group: usize,
+        config: &Config,
+        dependencies: &[Vec<Dependency>],
+        states: &mut [State],
+        stack: &mut Vec<usize>,
+        errors: &mut Vec<ParseError>,
+    ) {
+        states[group] = State::Visiting;
+        stack.push(group);
+        for &Dependency {
+            target,
+            line_number,
+            span,
+        } in &dependencies[group]
+        {
+            match states[target] {
+                State::New => visit(target, config, dependencies, states, stack, errors),
+                State::Visiting => {
+                    let start = stack.iter().position(|&g| g == target).unwrap_or(0);
+                    let cycle: Vec<&str> = stack[start..]
+                        .iter()
+                        .chain([&target])
+                        .map(|&g| config.groups[g].name.as_str())
+                        .collect();
+                    errors.push(ParseError::new(
+                        ParseErrorKind::DependencyCycle,
+                        line_number,
+                        span,
+                        format!("Dependency cycle: {}", cycle.join(" -> ")),
+                    ));
+                }
+                State::Done => {}
+            }
+        }
+        stack.pop();
+        states[group] = State::Done;
+    }
+    let mut states = vec![State::New; config.groups.len()];
+    for group in 0..config.groups.len() {
+        if states[group] == State::New {
+            visit(
+                group,
+                config,
+                &dependencies,
+                &mut states,
+                &mut Vec::new(),
+                &mut errors,
+            );
+        }
+    }
+    errors
+}
+
+/// Parses a configuration string into its groups and their compiled
+/// patterns, in the order they are declared.
+///
+/// Parsing does not stop at the first error: every problem found in the
+/// configuration is reported in the returned vector.
+pub fn parse_config(content: &str) -> Result<Config, Vec<ParseError>> {
+    let mut result = Config::default();
+    let mut errors = Vec::new();
+    let mut current: Option<Group> = None;
+    // Set when the current section header is invalid, so that its items are
+    // not reported as being found before any section
+    let mut skip_items = false;
+    let mut dependency_lines = Vec::new();
+    // Group index, line number and span of the headers of template sections
+    let mut template_headers = Vec::new();
 
     for (line_num, line) in content.lines().enumerate() {
         let line_number = line_num + 1;
@@ -30,64 +578,185 @@ pub fn parse_config(content: &str) -> Result<HashMap<String, Vec<String>>, Parse
             continue;
         }
 
-        // Parse section headers
-        if trimmed.starts_with('[') && trimmed.ends_with(']') {
-            // Save previous section items if any
-            if !current_section.is_empty() {
-                result.insert(
-                    std::mem::take(&mut current_section),
-                    std::mem::take(&mut vec_section),
-                );
+        let span = span_of(line, trimmed);
+
+        // Parse section headers: a line starting with '[' is a pattern when
+        // its bracket is closed before the end of the line, like
+        // "[Mm]akefile", and a header otherwise
+        if trimmed.starts_with('[') && (trimmed.ends_with(']') || !trimmed[1..].contains(']')) {
+            // Save previous section if any
+            if let Some(group) = current.take() {
+                result.groups.push(group);
             }
-            if trimmed.len() < 3 {
-                return Err(ParseError {
-                    line: line_number,
-                    message: "Invalid section header: too short".to_string(),
-                });
+            skip_items = true;
+
+            if !trimmed.ends_with(']') || trimmed.len() < 2 {
+                errors.push(ParseError::new(
+                    ParseErrorKind::UnclosedSectionHeader,
+                    line_number,
+                    span,
+                    "Invalid section header: missing closing ']'".to_string(),
+                ));
+                continue;
             }
 
-            current_section = trimmed[1..trimmed.len() - 1].to_string();
+            let name = trimmed[1..trimmed.len() - 1].trim();
+            if name.is_empty() {
+                errors.push(ParseError::new(
+                    ParseErrorKind::EmptySectionName,
+                    line_number,
+                    span,
+                    "Invalid section header: empty section name".to_string(),
+                ));
+                continue;
+            }
 
-            if result.contains_key(&current_section) {
-                return Err(ParseError {
-                    line: line_number,
-                    message: format!("Duplicate section: '{}'", current_section),
-                });
+            if name.starts_with(['!', '^']) || name.contains(['[', ']']) {
+                errors.push(ParseError::new(
+                    ParseErrorKind::InvalidSectionName,
+                    line_number,
+                    span_of(line, name),
+                    format!(
+                        "Invalid section name '{}': it looks like a character class",
+                        name
+                    ),
+                ));
+                continue;
             }
+
+            if result.contains(name) {
+                errors.push(ParseError::new(
+                    ParseErrorKind::DuplicateSection,
+                    line_number,
+                    span_of(line, name),
+                    format!("Duplicate section: '{}'", name),
+                ));
+                continue;
+            }
+
+            let mut group = Group::new(name);
+            match section_placeholder(name) {
+                Ok(Some(placeholder)) => {
+                    group.placeholder = Some(placeholder.to_string());
+                    template_headers.push((result.groups.len(), line_number, span_of(line, name)));
+                }
+                Ok(None) => {}
+                Err(message) => errors.push(ParseError::new(
+                    ParseErrorKind::InvalidTemplate,
+                    line_number,
+                    span_of(line, name),
+                    format!("Invalid template: {}", message),
+                )),
+            }
+            current = Some(group);
+            skip_items = false;
         } else {
-            if current_section.is_empty() {
-                return Err(ParseError {
-                    line: line_number,
-                    message: "Item found before any section is defined".to_string(),
-                });
+            let Some(group) = current.as_mut() else {
+                if !skip_items {
+                    errors.push(ParseError::new(
+                        ParseErrorKind::ItemBeforeSection,
+                        line_number,
+                        span,
+                        "Item found before any section is defined".to_string(),
+                    ));
+                }
+                continue;
+            };
+
+            if let Some((name, value)) = split_option(trimmed) {
+                match apply_option(group, line, line_number, name, value) {
+                    Ok(()) if name == "depends-on" => dependency_lines.push(DependencyLine {
+                        group: result.groups.len(),
+                        line_number,
+                        line,
+                        value,
+                    }),
+                    Ok(()) => {}
+                    Err(e) => errors.push(e),
+                }
+                continue;
             }
 
-            let item = trimmed.to_string();
-            if item.is_empty() {
-                return Err(ParseError {
-                    line: line_number,
-                    message: "Item cannot be empty".to_string(),
-                });
+            let (pattern, region) = split_rule(trimmed);
+            if let Some(placeholder) = &group.placeholder {
+                let token = format!("{{{}}}", placeholder);
+                if !is_whole_component(pattern, &token) {
+                    errors.push(ParseError::new(
+                        ParseErrorKind::InvalidTemplate,
+                        line_number,
+                        span_of(line, pattern),
+                        format!(
+                            "Invalid template: '{}' must be a whole path component",
+                            token
+                        ),
+                    ));
+                }
+            }
+            let pattern = Pattern::compile(pattern).map_err(|e| {
+                ParseError::new(
+                    ParseErrorKind::InvalidPattern,
+                    line_number,
+                    (span.0 + e.start, span.0 + e.end),
+                    format!("Invalid pattern: {}", e),
+                )
+            });
+            let region = region.map(Region::parse).transpose().map_err(|e| {
+                let offset = span_of(line, region.unwrap_or_default()).0;
+                ParseError::new(
+                    ParseErrorKind::InvalidRegion,
+                    line_number,
+                    (offset + e.start, offset + e.end),
+                    format!("Invalid region: {}", e.message),
+                )
+            });
+            match (pattern, region) {
+                (Ok(pattern), Ok(region)) => group.patterns.rules.push(Rule { pattern, region }),
+                (pattern, region) => errors.extend(pattern.err().into_iter().chain(region.err())),
             }
-            vec_section.push(item);
         }
     }
-    if !current_section.is_empty() {
-        result.insert(current_section, vec_section);
+    if let Some(group) = current {
+        result.groups.push(group);
+    }
+    errors.extend(check_dependencies(&result, &dependency_lines));
+    for (index, line_number, span) in template_headers {
+        let group = &result.groups[index];
+        let token = format!("{{{}}}", group.placeholder.as_deref().unwrap_or_default());
+        if !group
+            .patterns
+            .iter()
+            .any(|r| r.pattern.as_str().contains(&token))
+        {
+            errors.push(ParseError::new(
+                ParseErrorKind::InvalidTemplate,
+                line_number,
+                span,
+                format!(
+                    "Invalid template: no pattern of the section uses '{}'",
+                    token
+                ),
+            ));
+        }
     }
+    errors.sort_by_key(|e| (e.line, e.column));
 
-    Ok(result)
+    if errors.is_empty() {
+        Ok(result)
+    } else {
+        Err(errors)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::matching::MatchPath;
 
     #[test]
     fn test_empty_config() {
         let content = "";
         let result = parse_config(content).unwrap();
-        assert!(result.is_empty());
+        assert!(result.groups.is_empty());
     }
 
     #[test]
@@ -102,19 +771,19 @@ tests/**
 "#;
 
         let result = parse_config(content).unwrap();
-        assert_eq!(result.len(), 2);
-        assert!(result.contains_key("compile"));
-        assert_eq!(result["compile"].len(), 2);
-        assert!(result.contains_key("test"));
-        assert_eq!(result["test"].len(), 1);
+        assert_eq!(result.groups.len(), 2);
+        assert!(result.contains("compile"));
+        assert_eq!(result["compile"].patterns.len(), 2);
+        assert!(result.contains("test"));
+        assert_eq!(result["test"].patterns.len(), 1);
     }
 
     #[test]
     fn test_empty_section_alone() {
         let content = "[empty-section]\n";
         let result = parse_config(content).unwrap();
-        assert!(result.contains_key("empty-section"));
-        assert_eq!(result["empty-section"].len(), 0);
+        assert!(result.contains("empty-section"));
+        assert_eq!(result["empty-section"].patterns.len(), 0);
     }
 
     #[test]
@@ -126,10 +795,10 @@ item1
 item2
 "#;
         let result = parse_config(content).unwrap();
-        assert!(result.contains_key("empty-section"));
-        assert_eq!(result["empty-section"].len(), 0);
-        assert!(result.contains_key("section"));
-        assert_eq!(result["section"].len(), 2);
+        assert!(result.contains("empty-section"));
+        assert_eq!(result["empty-section"].patterns.len(), 0);
+        assert!(result.contains("section"));
+        assert_eq!(result["section"].patterns.len(), 2);
     }
 
     #[test]
@@ -142,7 +811,7 @@ item1
 item2
 "#;
         let result = parse_config(content).unwrap();
-        assert_eq!(result["section"].len(), 2);
+        assert_eq!(result["section"].patterns.len(), 2);
     }
 
     #[test]
@@ -153,9 +822,11 @@ item1
 [section]
 item2
 "#;
-        let err = parse_config(content).unwrap_err();
-        assert_eq!(err.line, 4);
-        assert!(err.message.contains("Duplicate section"));
+        let errs = parse_config(content).unwrap_err();
+        assert_eq!(errs.len(), 1);
+        assert_eq!(errs[0].line, 4);
+        assert_eq!((errs[0].column, errs[0].end_column), (2, 9));
+        assert!(errs[0].message.contains("Duplicate section"));
     }
 
     #[test]
@@ -164,9 +835,10 @@ item2
 item1
 [section]
 "#;
-        let err = parse_config(content).unwrap_err();
-        assert_eq!(err.line, 2);
-        assert!(err.message.contains("before any section"));
+        let errs = parse_config(content).unwrap_err();
+        assert_eq!(errs.len(), 1);
+        assert_eq!(errs[0].line, 2);
+        assert!(errs[0].message.contains("before any section"));
     }
 
     #[test]
@@ -178,7 +850,370 @@ item1
 item1
 "#;
         let result = parse_config(content).unwrap();
-        assert_eq!(result["section"].len(), 1);
-        assert_eq!(result["section"][0], "item1");
+        assert_eq!(result["section"].patterns.len(), 1);
+        assert!(
+            result["section"].patterns[0]
+                .pattern
+                .is_match(&MatchPath::from_bytes(b"item1"))
+        );
+    }
+
+    #[test]
+    fn test_malformed_headers() {
+        let content = "[foo\nitem1\n[]\nitem2\n";
+        let errs = parse_config(content).unwrap_err();
+        assert_eq!(errs.len(), 2);
+        assert_eq!(errs[0].line, 1);
+        assert!(errs[0].message.contains("missing closing"));
+        assert_eq!(errs[1].line, 3);
+        assert!(errs[1].message.contains("empty section name"));
+    }
+
+    #[test]
+    fn test_normalize_unicode() {
+        // "café" composed in the section name, decomposed in the pattern
+        let content = "[caf\u{e9}]\ncafe\u{301}/**\n[e\u{301}t\u{e9}]\n[!e\u{301}]*.md\n";
+        let mut result = parse_config(content).unwrap();
+        result.normalize_unicode(NormalizationForm::Nfc);
+        let names: Vec<&str> = result.groups.iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(names, ["caf\u{e9}", "e\u{301}t\u{e9}"]);
+
+        fn matches(config: &Config, group: &str, path: &str) -> bool {
+            config[group]
+                .patterns
+                .matches_path(&MatchPath::from_bytes(path.as_bytes()), false)
+        }
+        assert_eq!(
+            result["caf\u{e9}"].patterns[0].pattern.as_str(),
+            "caf\u{e9}/**"
+        );
+        assert!(matches(&result, "caf\u{e9}", "caf\u{e9}/menu.txt"));
+        assert!(!matches(&result, "caf\u{e9}", "cafe\u{301}/menu.txt"));
+        // A decomposed character of a class becomes a single member
+        assert!(!matches(&result, "e\u{301}t\u{e9}", "\u{e9}.md"));
+        assert!(matches(&result, "e\u{301}t\u{e9}", "a.md"));
+
+        result.normalize_unicode(NormalizationForm::Nfd);
+        assert!(matches(&result, "caf\u{e9}", "cafe\u{301}/menu.txt"));
+        assert_eq!(result.groups[0].name, "caf\u{e9}");
+    }
+
+    #[test]
+    fn test_bracket_patterns() {
+        let content = "[build]\n[Mm]akefile\n[!.]*.mk\n";
+        let result = parse_config(content).unwrap();
+        assert_eq!(result.groups.len(), 1);
+        let patterns = &result["build"].patterns;
+        assert_eq!(patterns.len(), 2);
+        let matches = |path: &[u8]| patterns.matches_path(&MatchPath::from_bytes(path), false);
+        assert!(matches(b"Makefile"));
+        assert!(matches(b"src/makefile"));
+        assert!(matches(b"rules.mk"));
+        assert!(!matches(b".rules.mk"));
+    }
+
+    #[test]
+    fn test_class_like_section_names() {
+        let content = "[!]\nitem1\n[^a]\nitem2\n[]]\nitem3\n";
+        let errs = parse_config(content).unwrap_err();
+        let found: Vec<(ParseErrorKind, usize, usize, usize)> = errs
+            .iter()
+            .map(|e| (e.kind, e.line, e.column, e.end_column))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (ParseErrorKind::InvalidSectionName, 1, 2, 3),
+                (ParseErrorKind::InvalidSectionName, 3, 2, 4),
+                (ParseErrorKind::InvalidSectionName, 5, 2, 3),
+            ]
+        );
+        assert!(errs[0].message.contains("character class"));
+    }
+
+    #[test]
+    fn test_all_errors_reported() {
+        let content = r#"
+orphan
+[section]
+item1
+[section]
+item2
+  [broken
+"#;
+        let errs = parse_config(content).unwrap_err();
+        let lines: Vec<usize> = errs.iter().map(|e| e.line).collect();
+        assert_eq!(lines, vec![2, 5, 7]);
+        assert_eq!((errs[2].column, errs[2].end_column), (3, 10));
+        let kinds: Vec<ParseErrorKind> = errs.iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                ParseErrorKind::ItemBeforeSection,
+                ParseErrorKind::DuplicateSection,
+                ParseErrorKind::UnclosedSectionHeader
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_error() {
+        let content = "[section]\n  [broken\n";
+        let errs = parse_config(content).unwrap_err();
+        let rendered = errs[0].render(content, "test.conf");
+        assert_eq!(
+            rendered,
+            "error: Invalid section header: missing closing ']'\n \
+             --> test.conf:2:3\n  |\n2 |   [broken\n  |   ^^^^^^^\n"
+        );
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        let content = r#"
+[section]
+src/[ab
+  a**b/c
+valid/**
+"#;
+        let errs = parse_config(content).unwrap_err();
+        assert_eq!(errs.len(), 2);
+        assert_eq!(errs[0].line, 3);
+        assert_eq!((errs[0].column, errs[0].end_column), (5, 6));
+        assert!(errs[0].message.contains("unclosed '['"));
+        assert_eq!(errs[1].line, 4);
+        assert_eq!((errs[1].column, errs[1].end_column), (3, 7));
+        assert!(errs[1].message.contains("whole path component"));
+    }
+
+    #[test]
+    fn test_declaration_order() {
+        let content = "[zeta]\n[alpha]\n[mid]\n";
+        let result = parse_config(content).unwrap();
+        let names: Vec<&str> = result.groups.iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(names, vec!["zeta", "alpha", "mid"]);
+    }
+
+    #[test]
+    fn test_section_options() {
+        let content = r#"
+[docs]
+case-insensitive = true
+README.md
+[src]
+src/**
+case-insensitive=no
+[default]
+a=b.txt
+"#;
+        let result = parse_config(content).unwrap();
+        assert_eq!(result["docs"].case_insensitive, Some(true));
+        assert_eq!(result["docs"].patterns.len(), 1);
+        assert_eq!(result["src"].case_insensitive, Some(false));
+        assert_eq!(result["default"].case_insensitive, None);
+        // Lines not naming an option are patterns, as before options
+        let patterns = &result["default"].patterns;
+        assert_eq!(patterns.len(), 1);
+        assert_eq!(patterns[0].pattern.as_str(), "a=b.txt");
+        let result = parse_config("[x]\ncase-sensitive = true\n").unwrap();
+        assert_eq!(result["x"].patterns.len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_option_value() {
+        let content = "[docs]\ncase-insensitive = maybe\n";
+        let errs = parse_config(content).unwrap_err();
+        assert_eq!(errs.len(), 1);
+        assert_eq!((errs[0].column, errs[0].end_column), (20, 25));
+        assert!(errs[0].message.contains("Invalid boolean value"));
+    }
+
+    #[test]
+    fn test_line_range_rules() {
+        let content = r"[deps]
+Cargo.toml:/^\[dependencies\]/,/^\[/
+src/main.rs:10-20
+docs/a:b.md
+logs/12\:30.txt
+";
+        let result = parse_config(content).unwrap();
+        let rules = &result["deps"].patterns;
+        assert!(matches!(rules[0].region, Some(Region::Regex { .. })));
+        assert!(
+            rules[0]
+                .pattern
+                .is_match(&MatchPath::from_bytes(b"Cargo.toml"))
+        );
+        assert!(matches!(
+            rules[1].region,
+            Some(Region::Lines {
+                start: 10,
+                end: Some(20)
+            })
+        ));
+        assert!(rules[2].region.is_none());
+        assert!(
+            rules[2]
+                .pattern
+                .is_match(&MatchPath::from_bytes(b"docs/a:b.md"))
+        );
+        assert!(rules[3].region.is_none());
+        assert_eq!(rules[3].pattern.as_str(), r"logs/12\:30.txt");
+        assert!(
+            rules[3]
+                .pattern
+                .is_match(&MatchPath::from_bytes(b"logs/12:30.txt"))
+        );
+
+        // An unescaped colon followed by a digit always starts a region
+        let errs = parse_config("[logs]\nlogs/12:30.txt\n").unwrap_err();
+        assert_eq!(errs[0].kind, ParseErrorKind::InvalidRegion);
+
+        let errs = parse_config("[deps]\n  Cargo.toml:/^(dep/\nsrc/*.rs:20-10\n").unwrap_err();
+        assert_eq!(errs.len(), 2);
+        assert_eq!(
+            (errs[0].line, errs[0].column, errs[0].end_column),
+            (2, 14, 21)
+        );
+        assert!(errs[0].message.starts_with("Invalid region: invalid regex"));
+        assert_eq!(
+            (errs[1].line, errs[1].column, errs[1].end_column),
+            (3, 10, 15)
+        );
+    }
+
+    #[test]
+    fn test_ignore_options() {
+        let content = "[a]\nignore-whitespace = yes\n[b]\nignore-comments = true\n[c]\n";
+        let result = parse_config(content).unwrap();
+        assert!(result["a"].ignore_whitespace && !result["a"].ignore_comments);
+        assert!(!result["b"].ignore_whitespace && result["b"].ignore_comments);
+        assert!(!result["c"].ignore_whitespace && !result["c"].ignore_comments);
+        assert!(parse_config("[a]\nignore-comments = 1\n").is_err());
+    }
+
+    #[test]
+    fn test_line_thresholds() {
+        let content = "[big]\nchanged-lines-above = 500\n[growth]\nadded-lines-above=10\n\
+                       deleted-lines-above = 0\n[any]\n";
+        let result = parse_config(content).unwrap();
+        assert_eq!(result["big"].changed_lines_above, Some(500));
+        assert_eq!(result["growth"].added_lines_above, Some(10));
+        assert_eq!(result["growth"].deleted_lines_above, Some(0));
+        assert!(result["big"].has_thresholds() && result["growth"].has_thresholds());
+        assert!(!result["any"].has_thresholds());
+
+        let errors = parse_config("[big]\nchanged-lines-above = many\n").unwrap_err();
+        assert_eq!((errors[0].column, errors[0].end_column), (23, 27));
+        assert!(parse_config("[big]\nadded-lines-above = -1\n").is_err());
+    }
+
+    #[test]
+    fn test_dependencies() {
+        let content = "[core]\ncore/**\n[api]\ndepends-on = core\n[web]\n\
+                       depends-on = core, api\ndepends-on = api\n";
+        let result = parse_config(content).unwrap();
+        assert!(result["core"].depends_on.is_empty());
+        assert_eq!(result["api"].depends_on, ["core"]);
+        assert_eq!(result["web"].depends_on, ["core", "api"]);
+
+        let errors = parse_config("[api]\ndepends-on = core\n").unwrap_err();
+        assert_eq!(errors[0].kind, ParseErrorKind::UnknownDependency);
+        assert_eq!((errors[0].column, errors[0].end_column), (14, 18));
+
+        let content = "[a]\ndepends-on = c\n[b]\ndepends-on = a\n[c]\ndepends-on = x b\n\
+                       [d]\ndepends-on = d\n";
+        let errors = parse_config(content).unwrap_err();
+        let found: Vec<_> = errors
+            .iter()
+            .map(|e| (e.kind, e.line, e.column, e.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    ParseErrorKind::DependencyCycle,
+                    4,
+                    14,
+                    "Dependency cycle: a -> c -> b -> a"
+                ),
+                (
+                    ParseErrorKind::UnknownDependency,
+                    6,
+                    14,
+                    "Unknown group in depends-on: 'x'"
+                ),
+                (
+                    ParseErrorKind::DependencyCycle,
+                    8,
+                    14,
+                    "Dependency cycle: d -> d"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_metadata() {
+        let content = "[api]\nmeta.runner = ubuntu-latest\nmeta.node_version=20\n\
+                       meta.runner = macos-latest\nsrc/**\n[docs]\n";
+        let result = parse_config(content).unwrap();
+        assert_eq!(
+            result["api"].metadata,
+            [
+                ("runner".to_string(), "macos-latest".to_string()),
+                ("node_version".to_string(), "20".to_string())
+            ]
+        );
+        assert!(result["docs"].metadata.is_empty());
+
+        let errors =
+            parse_config("[api]\nmeta.files = a\nmeta.runsOn = b\nmeta. = c\n").unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert!(
+            errors
+                .iter()
+                .all(|e| e.kind == ParseErrorKind::UnknownOption)
+        );
+        assert_eq!((errors[0].column, errors[0].end_column), (1, 11));
+        assert!(errors[0].message.contains("Reserved metadata key"));
+        assert_eq!((errors[1].column, errors[1].end_column), (1, 12));
+        assert_eq!(errors[1].message, "Invalid metadata key: 'runsOn'");
+        assert_eq!(errors[2].message, "Invalid metadata key: ''");
+    }
+
+    #[test]
+    fn test_templates() {
+        let content = "[svc-{name}]\nservices/{name}/**\nshared/**\n[docs]\ndocs/**\n";
+        let result = parse_config(content).unwrap();
+        assert_eq!(result["svc-{name}"].placeholder.as_deref(), Some("name"));
+        assert_eq!(result["docs"].placeholder, None);
+
+        let errors = parse_config(
+            "[a-{Name}]\nx/**\n[b-{x}-{y}]\n{x}/**\n[c-{x]\n[d-}]\n\
+             [e-{name}]\nsrc/{name}.rs\n[f-{name}]\nsrc/**\n",
+        )
+        .unwrap_err();
+        let found: Vec<_> = errors
+            .iter()
+            .map(|e| (e.kind, e.line, e.column, e.end_column))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (ParseErrorKind::InvalidTemplate, 1, 2, 10),
+                (ParseErrorKind::InvalidTemplate, 3, 2, 11),
+                (ParseErrorKind::InvalidTemplate, 5, 2, 6),
+                (ParseErrorKind::InvalidTemplate, 6, 2, 5),
+                (ParseErrorKind::InvalidTemplate, 8, 1, 14),
+                (ParseErrorKind::InvalidTemplate, 9, 2, 10),
+            ]
+        );
+        assert!(errors[0].message.contains("lowercase ASCII letters"));
+        assert!(errors[4].message.contains("must be a whole path component"));
+        assert_eq!(
+            errors[5].message,
+            "Invalid template: no pattern of the section uses '{name}'"
+        );
     }
 }
@@ -0,0 +1,1214 @@
+// Evaluate the groups of a configuration against a set of changed files
+
+use crate::config::{Config, Group};
+use crate::diff::{
+    self, ChangedFile, ChangedFiles, ContentSource, LineChanges, LineStats, NumstatError,
+    PatchError, PathError,
+};
+use crate::git::{GitError, VersionReader};
+use crate::json;
+use crate::paths;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Error raised while reading the changed files or evaluating the groups
+#[derive(Debug)]
+pub enum Error {
+    /// The changed files could not be read
+    Input(io::Error),
+    /// The input is not a valid patch
+    Patch(PatchError),
+    /// The input is not a valid numstat listing
+    Numstat(NumstatError),
+    /// A changed path is invalid, like an absolute path outside of the
+    /// repository root
+    Path(PathError),
+    /// git failed to list the changed files
+    Git(GitError),
+    /// A version of a changed file, needed to evaluate line-range rules or
+    /// ignored changes, could not be read
+    Contents {
+        /// Path of the file relative to the repository root
+        path: Vec<u8>,
+        /// Why it could not be read
+        message: String,
+    },
+    /// A group has line thresholds, but the numbers of changed lines are
+    /// not known, like when only the paths of the changes are given
+    UnknownLineCounts {
+        /// Name of the group
+        group: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Input(e) => write!(f, "{}", e),
+            Error::Patch(e) => write!(f, "{}", e),
+            Error::Numstat(e) => write!(f, "{}", e),
+            Error::Path(e) => write!(f, "{}", e),
+            Error::Git(e) => write!(f, "{}", e),
+            Error::Contents { path, message } => {
+                write!(f, "cannot read '{}': {}", paths::display(path), message)
+            }
+            Error::UnknownLineCounts { group } => write!(
+                f,
+                "group '{}' has line thresholds, but the numbers of changed lines are \
+                 unknown: give a patch or a numstat listing on stdin, or let the tool run git",
+                group
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<PathError> for Error {
+    fn from(error: PathError) -> Self {
+        Error::Path(error)
+    }
+}
+
+/// Settings of the evaluation of the groups
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DetectOptions {
+    /// Ignore case differences, unless a group sets `case-insensitive`
+    pub case_insensitive: bool,
+    /// Count the changed lines of every group, and not only of the groups
+    /// with line thresholds
+    pub count_lines: bool,
+    /// Record the files matching the rules of every group, and not only
+    /// whether one does
+    pub list_files: bool,
+}
+
+/// Order of the groups in the JSON output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputOrder {
+    /// Groups in the order they are declared in the configuration
+    Config,
+    /// Groups sorted alphabetically
+    Alpha,
+}
+
+/// Outcome of the check of a group
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct GroupResult {
+    /// Name of the group
+    pub name: String,
+    /// Whether the group changed, directly or through its dependencies
+    pub matched: bool,
+    /// Whether changed files match the rules of the group itself
+    pub direct: bool,
+    /// Dependencies of the group, set with `depends-on`, that changed
+    pub via: Vec<String>,
+    /// Lines added and deleted in the files of the group, when they were
+    /// counted and are known
+    pub lines: Option<LineStats>,
+    /// Changed files matching the rules of the group, when they were listed
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_paths"))]
+    pub files: Vec<Vec<u8>>,
+}
+
+/// A path written as a string, or as its bytes when it is not valid UTF-8
+/// so that it can be read back as it is
+#[cfg(feature = "serde")]
+struct SerializedPath<'a>(&'a [u8]);
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for SerializedPath<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match std::str::from_utf8(self.0) {
+            Ok(path) => serializer.serialize_str(path),
+            Err(_) => serializer.serialize_bytes(self.0),
+        }
+    }
+}
+
+/// Write paths as strings, those that are not valid UTF-8 as bytes
+#[cfg(feature = "serde")]
+fn serialize_paths<S: serde::Serializer>(
+    files: &[Vec<u8>],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(files.iter().map(|path| SerializedPath(path)))
+}
+
+/// Outcome of the check of all groups, in the configuration order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Results {
+    /// Outcomes of the groups
+    pub groups: Vec<GroupResult>,
+}
+
+impl Results {
+    /// The outcome of the group of that name
+    pub fn get(&self, name: &str) -> Option<&GroupResult> {
+        self.groups.iter().find(|g| g.name == name)
+    }
+
+    /// Whether the group of that name matched, false if there is none
+    pub fn matched(&self, name: &str) -> bool {
+        self.get(name).is_some_and(|g| g.matched)
+    }
+
+    /// Generate the JSON output. With `stats`, each group gives whether it
+    /// matched and the numbers of lines added and deleted in its files. With
+    /// `explain`, each group also tells whether its own files changed and
+    /// which of its dependencies did.
+    pub fn to_json(&self, order: OutputOrder, stats: bool, explain: bool) -> String {
+        let mut json = String::from("{");
+
+        let mut entries: Vec<_> = self.groups.iter().collect();
+        if order == OutputOrder::Alpha {
+            entries.sort_by_key(|result| &result.name);
+        }
+
+        for (i, result) in entries.iter().enumerate() {
+            let value_str = if result.matched { "true" } else { "false" };
+            if stats || explain {
+                let mut fields = vec![format!("\"changed\": {}", value_str)];
+                if stats {
+                    fields.push(line_fields(result));
+                }
+                if explain {
+                    fields.push(explain_fields(result));
+                }
+                json.push_str(&format!(
+                    "{}: {{{}}}",
+                    json::quote(result.name.as_bytes()),
+                    fields.join(", ")
+                ));
+            } else {
+                json.push_str(&format!(
+                    "{}: {}",
+                    json::quote(result.name.as_bytes()),
+                    value_str
+                ));
+            }
+
+            if i < entries.len() - 1 {
+                json.push(',');
+            }
+        }
+
+        json.push('}');
+        json
+    }
+
+    /// Groups that matched, in the output order
+    fn matched_groups(&self, order: OutputOrder) -> Vec<&GroupResult> {
+        let mut entries: Vec<_> = self.groups.iter().filter(|g| g.matched).collect();
+        if order == OutputOrder::Alpha {
+            entries.sort_by_key(|result| &result.name);
+        }
+        entries
+    }
+
+    /// Generate a JSON array of the names of the groups that matched
+    pub fn to_json_names(&self, order: OutputOrder) -> String {
+        let names: Vec<String> = self
+            .matched_groups(order)
+            .iter()
+            .map(|result| json::quote(result.name.as_bytes()))
+            .collect();
+        format!("[{}]", names.join(","))
+    }
+
+    /// Generate a JSON array with an object for each group that matched,
+    /// giving its name, its files and the metadata declared for it in
+    /// `config`, plus the fields added by `stats` and `explain` like for
+    /// [`Results::to_json`]. The files are only known if they were listed by
+    /// [`detect`].
+    pub fn to_json_matrix(
+        &self,
+        config: &Config,
+        order: OutputOrder,
+        stats: bool,
+        explain: bool,
+    ) -> String {
+        let entries: Vec<String> = self
+            .matched_groups(order)
+            .iter()
+            .map(|result| {
+                let files: Vec<String> = result.files.iter().map(|f| json::quote(f)).collect();
+                let mut fields = vec![
+                    format!("\"group\": {}", json::quote(result.name.as_bytes())),
+                    format!("\"files\": [{}]", files.join(",")),
+                ];
+                if let Some(group) = config.get(&result.name) {
+                    fields.extend(group.metadata.iter().map(|(key, value)| {
+                        format!(
+                            "{}: {}",
+                            json::quote(key.as_bytes()),
+                            json::quote(value.as_bytes())
+                        )
+                    }));
+                }
+                if stats {
+                    fields.push(line_fields(result));
+                }
+                if explain {
+                    fields.push(explain_fields(result));
+                }
+                format!("{{{}}}", fields.join(", "))
+            })
+            .collect();
+        format!("[{}]", entries.join(","))
+    }
+}
+
+/// JSON fields giving the numbers of lines added and deleted in the files of
+/// a group, `null` when unknown
+fn line_fields(result: &GroupResult) -> String {
+    let (added, deleted) = match result.lines {
+        Some(lines) => (lines.added.to_string(), lines.deleted.to_string()),
+        None => ("null".to_string(), "null".to_string()),
+    };
+    format!("\"added\": {}, \"deleted\": {}", added, deleted)
+}
+
+/// JSON fields telling whether a group changed directly and through which
+/// dependencies
+fn explain_fields(result: &GroupResult) -> String {
+    let via: Vec<String> = result
+        .via
+        .iter()
+        .map(|name| json::quote(name.as_bytes()))
+        .collect();
+    format!(
+        "\"direct\": {}, \"via\": [{}]",
+        result.direct,
+        via.join(",")
+    )
+}
+
+/// Load the changed lines of a file, and both its versions if
+/// `with_contents` is set. Returns None when only the path is known. The
+/// versions of files listed with git are read with `reader`, opened on
+/// first use.
+fn load_line_changes(
+    file: &ChangedFile,
+    source: &ContentSource,
+    reader: &RefCell<Option<VersionReader>>,
+    with_contents: bool,
+) -> Result<Option<LineChanges>, Error> {
+    let error = |message: String| Error::Contents {
+        path: file.name.clone(),
+        message,
+    };
+    match source {
+        ContentSource::WorkTree { root } => {
+            let Some(patch) = &file.patch else {
+                return Ok(None);
+            };
+            let mut lines = LineChanges {
+                changes: patch.changes(),
+                modified: patch.is_modified(),
+                contents: None,
+            };
+            if !with_contents {
+                return Ok(Some(lines));
+            }
+            let new = match (&patch.old_path, &patch.new_path) {
+                (Some(_), Some(new_path)) => {
+                    let path = root
+                        .as_deref()
+                        .unwrap_or(Path::new("."))
+                        .join(paths::to_path(new_path));
+                    match fs::read(&path) {
+                        Ok(content) => Some(String::from_utf8_lossy(&content).into_owned()),
+                        // Without the working tree, like for a patch checked
+                        // alone, the contents stay unknown and regex regions
+                        // match like plain patterns
+                        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Some(lines)),
+                        Err(e) => return Err(error(format!("{}: {}", path.display(), e))),
+                    }
+                }
+                _ => None,
+            };
+            // The working tree may not match the patch, like for a patch of
+            // a past range: the contents then stay unknown as well
+            lines.contents = patch.contents(new.as_deref()).ok();
+            Ok(Some(lines))
+        }
+        ContentSource::Git {
+            dir,
+            range,
+            mode,
+            backend,
+        } => {
+            let mut reader = reader.borrow_mut();
+            let reader = match &mut *reader {
+                Some(reader) => reader,
+                None => reader.insert(
+                    VersionReader::open(dir.as_deref(), range, *mode, *backend)
+                        .map_err(|e| error(e.to_string()))?,
+                ),
+            };
+            let (old_path, new_path) = &file.listed;
+            let (old, new) = reader
+                .file_versions(old_path, new_path)
+                .map_err(|e| error(e.to_string()))?;
+            let modified = old.is_some() && new.is_some();
+            // Binary files have no lines
+            let binary = [&old, &new]
+                .iter()
+                .any(|content| content.as_deref().is_some_and(diff::is_binary));
+            let split_lines = |content: Option<Vec<u8>>| {
+                content
+                    .as_deref()
+                    .map(diff::split_lines)
+                    .unwrap_or_default()
+            };
+            let contents = diff::Contents {
+                old: split_lines(old),
+                new: split_lines(new),
+            };
+            let changes = if binary {
+                Vec::new()
+            } else {
+                diff::diff_lines(&contents.old, &contents.new)
+            };
+            Ok(Some(LineChanges {
+                changes,
+                modified,
+                contents: Some(contents),
+            }))
+        }
+    }
+}
+
+/// Loads the changed lines of a file, and both its versions if asked
+type LineLoader<'a> = dyn Fn(&ChangedFile, bool) -> Result<Option<LineChanges>, Error> + 'a;
+
+/// Changed lines of a file, loaded on demand and kept in `cache`
+fn cached_lines<'c>(
+    cache: &'c mut HashMap<usize, Option<LineChanges>>,
+    index: usize,
+    file: &ChangedFile,
+    with_contents: bool,
+    load_lines: &LineLoader,
+) -> Result<Option<&'c LineChanges>, Error> {
+    let loaded = match cache.get(&index) {
+        None => false,
+        Some(Some(changes)) => !with_contents || changes.contents.is_some(),
+        Some(None) => true,
+    };
+    if !loaded {
+        cache.insert(index, load_lines(file, with_contents)?);
+    }
+    Ok(cache[&index].as_ref())
+}
+
+/// Whether a changed file counts for a group: one of its rules matches the
+/// path of the file, and lines of the region of the rule changed
+fn file_matches(
+    group: &Group,
+    ignore_case: bool,
+    (index, file): (usize, &ChangedFile),
+    cache: &mut HashMap<usize, Option<LineChanges>>,
+    load_lines: &LineLoader,
+) -> Result<bool, Error> {
+    for rule in &group.patterns {
+        let path_matches = if ignore_case {
+            rule.pattern.is_match_ignore_case(&file.path)
+        } else {
+            rule.pattern.is_match(&file.path)
+        };
+        if !path_matches {
+            continue;
+        }
+
+        if group.ignore_whitespace || group.ignore_comments {
+            let cosmetic = match &file.patch {
+                // The lines of the hunks are enough to compare the versions,
+                // without reading the working tree
+                Some(patch) => LineChanges {
+                    changes: patch.changes(),
+                    modified: patch.is_modified(),
+                    contents: Some(patch.hunk_contents()),
+                }
+                .is_cosmetic(&file.name, group.ignore_comments),
+                None => cached_lines(cache, index, file, true, load_lines)?
+                    .is_some_and(|l| l.is_cosmetic(&file.name, group.ignore_comments)),
+            };
+            if cosmetic {
+                return Ok(false);
+            }
+        }
+
+        let Some(region) = &rule.region else {
+            return Ok(true);
+        };
+        let lines = cached_lines(cache, index, file, region.needs_contents(), load_lines)?;
+        if lines.is_none_or(|l| region.intersects(l)) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Mark the groups as changed when one of their dependencies, given by
+/// index, changed, transitively, and record which dependencies changed.
+/// Dependencies may form cycles.
+pub(crate) fn propagate(results: &mut [GroupResult], dependencies: &[Vec<usize>]) {
+    let mut propagated = true;
+    while propagated {
+        propagated = false;
+        for (i, targets) in dependencies.iter().enumerate() {
+            if !results[i].matched && targets.iter().any(|&j| results[j].matched) {
+                results[i].matched = true;
+                propagated = true;
+            }
+        }
+    }
+    for (i, targets) in dependencies.iter().enumerate() {
+        let via = targets
+            .iter()
+            .filter(|&&j| results[j].matched)
+            .map(|&j| results[j].name.clone())
+            .collect();
+        results[i].via = via;
+    }
+}
+
+/// Check the changed files against the groups of the configuration, loading
+/// the changed lines of the files with `load_lines` when needed
+fn check_patterns(
+    config: &Config,
+    changed_files: &ChangedFiles,
+    options: &DetectOptions,
+    load_lines: &LineLoader,
+) -> Result<Results, Error> {
+    let mut cache = HashMap::new();
+    let mut results = Vec::new();
+    // Template sections stand for the groups they expand to
+    let groups: Vec<&Group> = config
+        .groups
+        .iter()
+        .filter(|g| g.placeholder.is_none())
+        .collect();
+
+    for group in &groups {
+        let ignore_case = group.case_insensitive.unwrap_or(options.case_insensitive);
+        let count = options.count_lines || group.has_thresholds();
+        let mut matched = false;
+        let mut lines = Some(LineStats::default());
+        let mut files = Vec::new();
+        for (index, file) in changed_files.files.iter().enumerate() {
+            if !file_matches(group, ignore_case, (index, file), &mut cache, load_lines)? {
+                continue;
+            }
+            matched = true;
+            if options.list_files {
+                files.push(file.name.clone());
+            }
+            if !count {
+                if !options.list_files {
+                    break;
+                }
+                continue;
+            }
+            let stats = match file.stats {
+                Some(stats) => Some(stats),
+                None => cached_lines(&mut cache, index, file, false, load_lines)?
+                    .map(|l| LineStats::from_changes(&l.changes)),
+            };
+            lines = lines.zip(stats).map(|(total, stats)| total + stats);
+        }
+        let lines = lines.filter(|_| count);
+
+        if matched && group.has_thresholds() {
+            let Some(lines) = lines else {
+                return Err(Error::UnknownLineCounts {
+                    group: group.name.clone(),
+                });
+            };
+            let above =
+                |threshold: Option<usize>, count: usize| threshold.is_none_or(|t| count > t);
+            matched = above(group.changed_lines_above, lines.total())
+                && above(group.added_lines_above, lines.added)
+                && above(group.deleted_lines_above, lines.deleted);
+        }
+        results.push(GroupResult {
+            name: group.name.clone(),
+            matched,
+            direct: matched,
+            via: Vec::new(),
+            lines,
+            files,
+        });
+    }
+
+    let index = |name: &str| groups.iter().position(|g| g.name == name);
+    let dependencies: Vec<Vec<usize>> = groups
+        .iter()
+        .map(|group| group.depends_on.iter().filter_map(|d| index(d)).collect())
+        .collect();
+    propagate(&mut results, &dependencies);
+    Ok(Results { groups: results })
+}
+
+/// Check the changed files against the groups of the configuration.
+/// Results are returned in the configuration order.
+///
+/// Rules restricted to a region of the files only match if lines of that
+/// region changed, and groups may disregard files with cosmetic changes.
+/// The versions of the files needed for this are read from where the
+/// changes come from: git, or the working tree for a patch. When only the
+/// paths of the changes are known, every change counts.
+///
+/// The changed lines of the files of a group are counted for groups with
+/// line thresholds, and for every group if `count_lines` is set. The files
+/// of each group are listed if `list_files` is set.
+///
+/// Groups with `depends-on` options also change when one of their
+/// dependencies does, which [`GroupResult::via`] tells.
+///
+/// Template sections must be expanded first with
+/// [`Config::expand_templates`]: those left are skipped, and have no result.
+pub fn detect(
+    config: &Config,
+    changed_files: &ChangedFiles,
+    options: &DetectOptions,
+) -> Result<Results, Error> {
+    let reader = RefCell::new(None);
+    let load_lines = |file: &ChangedFile, with_contents: bool| {
+        load_line_changes(file, &changed_files.source, &reader, with_contents)
+    };
+    check_patterns(config, changed_files, options, &load_lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_config;
+    use crate::diff::{InputFormat, PathNormalization};
+
+    fn result(name: &str, matched: bool, lines: Option<(usize, usize)>) -> GroupResult {
+        GroupResult {
+            name: name.to_string(),
+            matched,
+            direct: matched,
+            via: Vec::new(),
+            lines: lines.map(|(added, deleted)| LineStats { added, deleted }),
+            files: Vec::new(),
+        }
+    }
+
+    /// Whether each group matched, by name
+    fn matched(results: Results) -> Vec<(String, bool)> {
+        results
+            .groups
+            .into_iter()
+            .map(|r| (r.name, r.matched))
+            .collect()
+    }
+
+    const PATHS_ONLY: DetectOptions = DetectOptions {
+        case_insensitive: false,
+        count_lines: false,
+        list_files: false,
+    };
+
+    const COUNT_LINES: DetectOptions = DetectOptions {
+        case_insensitive: false,
+        count_lines: true,
+        list_files: false,
+    };
+
+    #[test]
+    fn generate_json_order() {
+        let results = Results {
+            groups: vec![result("zeta", true, None), result("alpha", false, None)],
+        };
+        assert_eq!(
+            results.to_json(OutputOrder::Config, false, false),
+            "{\"zeta\": true,\"alpha\": false}"
+        );
+        assert_eq!(
+            results.to_json(OutputOrder::Alpha, false, false),
+            "{\"alpha\": false,\"zeta\": true}"
+        );
+    }
+
+    #[test]
+    fn generate_json_stats() {
+        let results = Results {
+            groups: vec![
+                result("big", true, Some((12, 3))),
+                result("docs", false, None),
+            ],
+        };
+        assert_eq!(
+            results.to_json(OutputOrder::Config, true, false),
+            "{\"big\": {\"changed\": true, \"added\": 12, \"deleted\": 3},\
+             \"docs\": {\"changed\": false, \"added\": null, \"deleted\": null}}"
+        );
+    }
+
+    #[test]
+    fn generate_json_matrix() {
+        let config =
+            Config::parse("[web]\nmeta.runner = ubuntu-latest\nweb/**\n[api]\n[docs]\n").unwrap();
+        let mut web = result("web", true, Some((4, 1)));
+        web.files = vec![b"web/a.ts".to_vec(), b"web/\"b\".ts".to_vec()];
+        let results = Results {
+            groups: vec![web, result("api", true, None), result("docs", false, None)],
+        };
+        assert_eq!(
+            results.to_json_names(OutputOrder::Alpha),
+            "[\"api\",\"web\"]"
+        );
+        assert_eq!(
+            results.to_json_matrix(&config, OutputOrder::Config, false, false),
+            "[{\"group\": \"web\", \"files\": [\"web/a.ts\",\"web/\\\"b\\\".ts\"], \
+             \"runner\": \"ubuntu-latest\"},{\"group\": \"api\", \"files\": []}]"
+        );
+        assert_eq!(
+            results.to_json_matrix(&config, OutputOrder::Alpha, true, false),
+            "[{\"group\": \"api\", \"files\": [], \"added\": null, \"deleted\": null},\
+             {\"group\": \"web\", \"files\": [\"web/a.ts\",\"web/\\\"b\\\".ts\"], \
+             \"runner\": \"ubuntu-latest\", \"added\": 4, \"deleted\": 1}]"
+        );
+        assert_eq!(Results::default().to_json_names(OutputOrder::Alpha), "[]");
+    }
+
+    #[test]
+    fn detect_from_paths() {
+        let config = Config::parse("[docs]\ndocs/**\n[code]\nsrc/**\n").unwrap();
+        let normalization = PathNormalization::default();
+        let changed_files = ChangedFiles::from_paths(["./docs/a.md"], &normalization).unwrap();
+        let results = detect(&config, &changed_files, &DetectOptions::default()).unwrap();
+        assert!(results.matched("docs"));
+        assert!(!results.matched("code"));
+        assert!(!results.matched("unknown"));
+        assert_eq!(results.get("code"), Some(&result("code", false, None)));
+
+        let changed_files =
+            ChangedFiles::from_paths(["docs/b.md", "src/a.rs", "docs/a.md"], &normalization)
+                .unwrap();
+        let options = DetectOptions {
+            list_files: true,
+            ..DetectOptions::default()
+        };
+        let results = detect(&config, &changed_files, &options).unwrap();
+        assert_eq!(
+            results.get("docs").unwrap().files,
+            [b"docs/b.md".to_vec(), b"docs/a.md".to_vec()]
+        );
+        assert_eq!(results.get("code").unwrap().files, [b"src/a.rs".to_vec()]);
+    }
+
+    #[test]
+    fn detect_dependencies() {
+        let config = Config::parse(
+            "[core]\ncore/**\n[api]\ndepends-on = core\napi/**\n[web]\ndepends-on = api\n\
+             [docs]\ndocs/**\n[site]\ndepends-on = docs, api\nsite/**\n",
+        )
+        .unwrap();
+        let normalization = PathNormalization::default();
+        let check = |path: &str| {
+            let changed_files = ChangedFiles::from_paths([path], &normalization).unwrap();
+            detect(&config, &changed_files, &DetectOptions::default()).unwrap()
+        };
+
+        let results = check("core/a.rs");
+        let summary: Vec<_> = results
+            .groups
+            .iter()
+            .map(|r| (r.name.as_str(), r.matched, r.direct, r.via.clone()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("core", true, true, vec![]),
+                ("api", true, false, vec!["core".to_string()]),
+                ("web", true, false, vec!["api".to_string()]),
+                ("docs", false, false, vec![]),
+                ("site", true, false, vec!["api".to_string()]),
+            ]
+        );
+        assert_eq!(
+            results.to_json(OutputOrder::Config, false, true),
+            "{\"core\": {\"changed\": true, \"direct\": true, \"via\": []},\
+             \"api\": {\"changed\": true, \"direct\": false, \"via\": [\"core\"]},\
+             \"web\": {\"changed\": true, \"direct\": false, \"via\": [\"api\"]},\
+             \"docs\": {\"changed\": false, \"direct\": false, \"via\": []},\
+             \"site\": {\"changed\": true, \"direct\": false, \"via\": [\"api\"]}}"
+        );
+
+        // Dependencies do not change with the groups depending on them
+        let results = check("api/a.rs");
+        assert!(!results.matched("core") && results.matched("web"));
+        let results = check("site/index.html");
+        assert_eq!(
+            matched(results),
+            [
+                ("core".to_string(), false),
+                ("api".to_string(), false),
+                ("web".to_string(), false),
+                ("docs".to_string(), false),
+                ("site".to_string(), true)
+            ]
+        );
+    }
+
+    #[test]
+    fn detect_templates() {
+        let mut config = Config::parse(
+            "[svc-{name}]\nservices/{name}/**\n[deploy]\ndepends-on = svc-{name}\ndeploy/**\n",
+        )
+        .unwrap();
+        let normalization = PathNormalization::default();
+        let changed_files =
+            ChangedFiles::from_paths(["services/name/main.go"], &normalization).unwrap();
+
+        // Templates left unexpanded are skipped, rather than matched with
+        // their placeholder taken as a brace alternative
+        let results = detect(&config, &changed_files, &DetectOptions::default()).unwrap();
+        assert_eq!(matched(results), [("deploy".to_string(), false)]);
+
+        config.expand_templates(&changed_files, None, false);
+        let results = detect(&config, &changed_files, &DetectOptions::default()).unwrap();
+        assert_eq!(
+            matched(results),
+            [("svc-name".to_string(), true), ("deploy".to_string(), true)]
+        );
+    }
+
+    #[test]
+    fn check_line_range_rules() {
+        let config = parse_config(
+            r"[deps]
+Cargo.toml:/^\[dependencies\]/,/^\[/
+[head]
+src/main.rs:1-5
+[any]
+Cargo.toml
+",
+        )
+        .unwrap();
+        let patch = b"diff --git a/Cargo.toml b/Cargo.toml
+--- a/Cargo.toml
++++ b/Cargo.toml
+@@ -1,3 +1,3 @@
+ [package]
+-version = \"1.0.0\"
++version = \"1.0.1\"
+ 
+diff --git a/src/main.rs b/src/main.rs
+--- a/src/main.rs
++++ b/src/main.rs
+@@ -8 +8 @@
+-a
++b
+";
+        let normalization = PathNormalization::default();
+        let changed_files =
+            ChangedFiles::from_reader(&patch[..], InputFormat::Patch, &normalization).unwrap();
+        let cargo = "[package]\nversion = \"1.0.1\"\n\n[dependencies]\nserde = \"1\"\n";
+        let load = |file: &ChangedFile, with_contents: bool| {
+            let patch = file.patch.as_ref().unwrap();
+            let contents = with_contents.then(|| patch.contents(Some(cargo)).unwrap());
+            Ok(Some(LineChanges {
+                changes: patch.changes(),
+                modified: patch.old_path.is_some() && patch.old_path == patch.new_path,
+                contents,
+            }))
+        };
+        let results = check_patterns(&config, &changed_files, &PATHS_ONLY, &load).unwrap();
+        assert_eq!(
+            matched(results),
+            vec![
+                ("deps".to_string(), false),
+                ("head".to_string(), false),
+                ("any".to_string(), true)
+            ]
+        );
+
+        // Without the working tree, regex regions match like plain patterns
+        let missing = PathNormalization {
+            root: Some(b"/nonexistent/detect-changed-files".to_vec()),
+            ..PathNormalization::default()
+        };
+        let changed_files =
+            ChangedFiles::from_reader(&patch[..], InputFormat::Patch, &missing).unwrap();
+        let results = detect(&config, &changed_files, &PATHS_ONLY).unwrap();
+        assert_eq!(
+            matched(results),
+            vec![
+                ("deps".to_string(), true),
+                ("head".to_string(), false),
+                ("any".to_string(), true)
+            ]
+        );
+
+        // Nor when the working tree has changed since the patch
+        let dir = std::env::temp_dir().join(format!(
+            "detect-changed-files-{}-{}",
+            "past-patch",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("Cargo.toml"),
+            "[package]\nversion = \"1.0.2\"\n\n[dependencies]\nserde = \"1\"\n",
+        )
+        .unwrap();
+        let past = PathNormalization {
+            root: Some(dir.to_str().unwrap().as_bytes().to_vec()),
+            ..PathNormalization::default()
+        };
+        let changed_files = ChangedFiles::from_reader(&patch[..], InputFormat::Patch, &past);
+        let results = detect(&config, &changed_files.unwrap(), &PATHS_ONLY);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            matched(results.unwrap()),
+            vec![
+                ("deps".to_string(), true),
+                ("head".to_string(), false),
+                ("any".to_string(), true)
+            ]
+        );
+
+        // Without line information, rules match on paths only
+        let changed_files = ChangedFiles::from_paths(["Cargo.toml"], &normalization).unwrap();
+        let results =
+            check_patterns(&config, &changed_files, &PATHS_ONLY, &|_, _| Ok(None)).unwrap();
+        assert_eq!(matched(results)[0], ("deps".to_string(), true));
+    }
+
+    #[test]
+    fn detect_from_git_normalized_paths() {
+        use crate::git::{Backend, DiffMode, DiffRange};
+        use crate::unicode::NormalizationForm;
+        use std::process::Command;
+
+        let dir = std::env::temp_dir().join(format!(
+            "detect-changed-files-{}-{}",
+            "git-nfd",
+            std::process::id()
+        ));
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .args(["-c", "user.name=A", "-c", "user.email=a@example.com"])
+                .args(args)
+                .current_dir(&dir)
+                .output()
+                .is_ok_and(|output| output.status.success())
+        };
+        fs::create_dir_all(dir.join("docs")).unwrap();
+        // "café" with a decomposed "é", as committed from macOS
+        let file = dir.join("docs/cafe\u{301}.md");
+        fs::write(&file, "one\ntwo\nthree\n").unwrap();
+        if !git(&["init", "-q"]) {
+            fs::remove_dir_all(&dir).unwrap();
+            return;
+        }
+        assert!(git(&["add", "."]) && git(&["commit", "-q", "-m", "1"]));
+        fs::write(&file, "one\ntwo\n3\n").unwrap();
+        assert!(git(&["commit", "-q", "-a", "-m", "2"]));
+        fs::write(&file, "1\ntwo\n3\n").unwrap();
+
+        let config = parse_config("[first]\ndocs/caf\u{e9}.md:1\n[last]\ndocs/caf\u{e9}.md:3\n");
+        let normalization = PathNormalization {
+            unicode: Some(NormalizationForm::Nfc),
+            root: Some(dir.to_str().unwrap().as_bytes().to_vec()),
+        };
+        let commits = DiffRange::Commits {
+            base: "HEAD~1".to_string(),
+            head: "HEAD".to_string(),
+        };
+        let working_tree = DiffRange::WorkingTree { base: None };
+        let mut outcomes = Vec::new();
+        for backend in [Backend::Command, Backend::Builtin] {
+            for range in [&commits, &working_tree] {
+                let changed_files =
+                    ChangedFiles::from_git(range, DiffMode::TwoDot, backend, &normalization);
+                let results = detect(
+                    config.as_ref().unwrap(),
+                    &changed_files.unwrap(),
+                    &COUNT_LINES,
+                );
+                outcomes.push(results.map(|r| {
+                    r.groups
+                        .iter()
+                        .map(|g| (g.matched, g.lines))
+                        .collect::<Vec<_>>()
+                }));
+            }
+        }
+        fs::remove_dir_all(&dir).unwrap();
+        let stats = |added, deleted| Some(LineStats { added, deleted });
+        for (i, outcome) in outcomes.into_iter().enumerate() {
+            let expected = if i % 2 == 0 {
+                [(false, stats(0, 0)), (true, stats(1, 1))]
+            } else {
+                [(true, stats(1, 1)), (false, stats(0, 0))]
+            };
+            assert_eq!(outcome.unwrap(), expected, "{}", i);
+        }
+    }
+
+    #[test]
+    fn check_ignored_changes() {
+        let config = parse_config(
+            r"[code]
+ignore-comments = true
+src/**
+[layout]
+ignore-whitespace = true
+src/**
+[all]
+src/**
+",
+        )
+        .unwrap();
+        let normalization = PathNormalization::default();
+        let changed_files = ChangedFiles::from_paths(["src/lib.rs"], &normalization).unwrap();
+        let check = |old: &str, new: &str, modified: bool| {
+            let load = |_: &ChangedFile, _: bool| {
+                let contents = diff::Contents {
+                    old: old.lines().map(String::from).collect(),
+                    new: new.lines().map(String::from).collect(),
+                };
+                Ok(Some(LineChanges {
+                    changes: diff::diff_lines(&contents.old, &contents.new),
+                    modified,
+                    contents: Some(contents),
+                }))
+            };
+            let results = check_patterns(&config, &changed_files, &PATHS_ONLY, &load).unwrap();
+            results
+                .groups
+                .into_iter()
+                .map(|r| r.matched)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            check("fn a() {}\n", "// A\nfn a() {}\n", true),
+            [false, true, true]
+        );
+        assert_eq!(
+            check("fn a() {}\n", "fn a() {\n}\n", true),
+            [false, false, true]
+        );
+        assert_eq!(
+            check("fn a() {}\n", "fn b() {}\n", true),
+            [true, true, true]
+        );
+        // Renamed, added and deleted files always count
+        assert_eq!(check("", "// A\n", false), [true, true, true]);
+
+        // Patches are compared on the lines of their hunks, without the
+        // working tree
+        let patch = b"diff --git a/src/lib.rs b/src/lib.rs
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,2 +1,3 @@
++// A
+ fn a() {
+-}
++ }
+";
+        let missing = PathNormalization {
+            root: Some(b"/nonexistent/detect-changed-files".to_vec()),
+            ..PathNormalization::default()
+        };
+        let changed_files =
+            ChangedFiles::from_reader(&patch[..], InputFormat::Patch, &missing).unwrap();
+        let results = detect(&config, &changed_files, &PATHS_ONLY).unwrap();
+        assert_eq!(
+            matched(results),
+            vec![
+                ("code".to_string(), false),
+                ("layout".to_string(), true),
+                ("all".to_string(), true)
+            ]
+        );
+    }
+
+    #[test]
+    fn check_line_thresholds() {
+        let config = parse_config(
+            r"[big]
+changed-lines-above = 10
+src/**
+[growth]
+added-lines-above = 5
+deleted-lines-above = 0
+src/**
+[docs]
+docs/**
+",
+        )
+        .unwrap();
+        let normalization = PathNormalization::default();
+        let check = |numstat: &str| {
+            let changed_files =
+                ChangedFiles::from_reader(numstat.as_bytes(), InputFormat::Auto, &normalization)
+                    .unwrap();
+            check_patterns(&config, &changed_files, &COUNT_LINES, &|_, _| Ok(None))
+                .unwrap()
+                .groups
+        };
+
+        assert_eq!(
+            check("8\t2\tsrc/a.rs\n1\t0\tsrc/b.rs\n-\t-\tsrc/logo.png\n5\t5\tREADME.md\n"),
+            [
+                result("big", true, Some((9, 2))),
+                result("growth", true, Some((9, 2))),
+                result("docs", false, Some((0, 0))),
+            ]
+        );
+        // Renamed files count once, with their new path
+        assert_eq!(
+            check("6\t0\tsrc/{a => b}.rs\n"),
+            [
+                result("big", false, Some((6, 0))),
+                result("growth", false, Some((6, 0))),
+                result("docs", false, Some((0, 0))),
+            ]
+        );
+
+        // Thresholds need the numbers of changed lines
+        let changed_files = ChangedFiles::from_paths(["src/a.rs"], &normalization).unwrap();
+        assert!(matches!(
+            check_patterns(&config, &changed_files, &PATHS_ONLY, &|_, _| Ok(None)),
+            Err(Error::UnknownLineCounts { group }) if group == "big"
+        ));
+        let changed_files = ChangedFiles::from_paths(["docs/a.md"], &normalization).unwrap();
+        let results =
+            check_patterns(&config, &changed_files, &COUNT_LINES, &|_, _| Ok(None)).unwrap();
+        assert_eq!(results.groups[2], result("docs", true, None));
+    }
+
+    /// Serializer recording strings, bytes and sequences as text, enough to
+    /// check how paths are written
+    #[cfg(feature = "serde")]
+    struct Recorder(String);
+
+    #[cfg(feature = "serde")]
+    macro_rules! unsupported {
+        ($($method:ident($($arg:ty),*) -> $ok:ty;)*) => {
+            $(fn $method(self, $(_: $arg),*) -> Result<$ok, fmt::Error> {
+                Err(fmt::Error)
+            })*
+        };
+    }
+
+    #[cfg(feature = "serde")]
+    impl serde::Serializer for &mut Recorder {
+        type Ok = ();
+        type Error = fmt::Error;
+        type SerializeSeq = Self;
+        type SerializeTuple = serde::ser::Impossible<(), fmt::Error>;
+        type SerializeTupleStruct = serde::ser::Impossible<(), fmt::Error>;
+        type SerializeTupleVariant = serde::ser::Impossible<(), fmt::Error>;
+        type SerializeMap = serde::ser::Impossible<(), fmt::Error>;
+        type SerializeStruct = serde::ser::Impossible<(), fmt::Error>;
+        type SerializeStructVariant = serde::ser::Impossible<(), fmt::Error>;
+
+        fn serialize_str(self, text: &str) -> Result<(), fmt::Error> {
+            self.0.push_str(&format!("str({}) ", text));
+            Ok(())
+        }
+
+        fn serialize_bytes(self, bytes: &[u8]) -> Result<(), fmt::Error> {
+            self.0.push_str(&format!("bytes({:?}) ", bytes));
+            Ok(())
+        }
+
+        fn serialize_seq(self, _: Option<usize>) -> Result<Self, fmt::Error> {
+            Ok(self)
+        }
+
+        fn serialize_some<T: serde::Serialize + ?Sized>(self, _: &T) -> Result<(), fmt::Error> {
+            Err(fmt::Error)
+        }
+
+        fn serialize_newtype_struct<T: serde::Serialize + ?Sized>(
+            self,
+            _: &'static str,
+            _: &T,
+        ) -> Result<(), fmt::Error> {
+            Err(fmt::Error)
+        }
+
+        fn serialize_newtype_variant<T: serde::Serialize + ?Sized>(
+            self,
+            _: &'static str,
+            _: u32,
+            _: &'static str,
+            _: &T,
+        ) -> Result<(), fmt::Error> {
+            Err(fmt::Error)
+        }
+
+        unsupported! {
+            serialize_bool(bool) -> ();
+            serialize_i8(i8) -> ();
+            serialize_i16(i16) -> ();
+            serialize_i32(i32) -> ();
+            serialize_i64(i64) -> ();
+            serialize_u8(u8) -> ();
+            serialize_u16(u16) -> ();
+            serialize_u32(u32) -> ();
+            serialize_u64(u64) -> ();
+            serialize_f32(f32) -> ();
+            serialize_f64(f64) -> ();
+            serialize_char(char) -> ();
+            serialize_none() -> ();
+            serialize_unit() -> ();
+            serialize_unit_struct(&'static str) -> ();
+            serialize_unit_variant(&'static str, u32, &'static str) -> ();
+            serialize_tuple(usize) -> Self::SerializeTuple;
+            serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct;
+            serialize_tuple_variant(&'static str, u32, &'static str, usize)
+                -> Self::SerializeTupleVariant;
+            serialize_map(Option<usize>) -> Self::SerializeMap;
+            serialize_struct(&'static str, usize) -> Self::SerializeStruct;
+            serialize_struct_variant(&'static str, u32, &'static str, usize)
+                -> Self::SerializeStructVariant;
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    impl serde::ser::SerializeSeq for &mut Recorder {
+        type Ok = ();
+        type Error = fmt::Error;
+
+        fn serialize_element<T: serde::Serialize + ?Sized>(
+            &mut self,
+            value: &T,
+        ) -> Result<(), fmt::Error> {
+            value.serialize(&mut **self)
+        }
+
+        fn end(self) -> Result<(), fmt::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serialize_non_utf8_paths() {
+        let mut recorder = Recorder(String::new());
+        let files = [b"src/main.rs".to_vec(), b"caf\xe9.txt".to_vec()];
+        serialize_paths(&files, &mut recorder).unwrap();
+        assert_eq!(
+            recorder.0,
+            "str(src/main.rs) bytes([99, 97, 102, 233, 46, 116, 120, 116]) "
+        );
+    }
+}
@@ -1,31 +1,854 @@
+mod numstat;
+mod patch;
+
+pub(crate) use numstat::parse_numstat;
+pub use numstat::{NumstatEntry, NumstatError};
+pub use patch::{Change, Contents, FilePatch, PatchError};
+
+use crate::comments;
+use crate::detect::Error;
+use crate::git::{self, Backend, DiffMode, DiffRange};
 use crate::matching::MatchPath;
+use crate::paths;
+use crate::unicode::{self, NormalizationForm};
+use std::collections::HashMap;
+use std::fmt;
 use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// How input paths are normalized before being matched
+#[derive(Debug, Default, Clone)]
+pub struct PathNormalization {
+    /// Unicode normalization form to convert paths to, if any
+    pub unicode: Option<NormalizationForm>,
+    /// Repository root, lexically normalized and without trailing slash.
+    /// Absolute input paths are made relative to it.
+    pub root: Option<Vec<u8>>,
+}
+
+/// Error raised when an input path cannot be normalized
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathError {
+    /// The path as given
+    pub path: String,
+    /// What is wrong with it
+    pub message: String,
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid path '{}': {}", self.path, self.message)
+    }
+}
+
+impl std::error::Error for PathError {}
+
+/// Lexically normalize a path: drop empty and "." components and resolve
+/// ".." against the previous component. Returns None if the path goes above
+/// its starting point.
+pub(crate) fn lexical_components(path: &[u8]) -> Option<Vec<&[u8]>> {
+    let mut components = Vec::new();
+    for component in path.split(|&b| b == b'/') {
+        match component {
+            b"" | b"." => {}
+            b".." => {
+                components.pop()?;
+            }
+            _ => components.push(component),
+        }
+    }
+    Some(components)
+}
+
+impl PathNormalization {
+    /// Directory of the repository: the root if set, or else the current
+    /// directory
+    pub fn root_dir(&self) -> PathBuf {
+        match &self.root {
+            Some(root) => paths::to_path(root),
+            None => PathBuf::from("."),
+        }
+    }
+
+    /// Set the repository root. A relative root is resolved against `cwd`.
+    pub fn set_root(&mut self, root: &Path, cwd: &Path) -> Result<(), PathError> {
+        let root = paths::from_os_str(root.as_os_str());
+        let absolute = if root.starts_with(b"/") {
+            root.clone()
+        } else {
+            [&paths::from_os_str(cwd.as_os_str())[..], b"/", &root].concat()
+        };
+        let components = lexical_components(&absolute).ok_or_else(|| PathError {
+            path: paths::display(&root),
+            message: "goes above the filesystem root".to_string(),
+        })?;
+        self.root = Some([&b"/"[..], &components.join(&b'/')].concat());
+        Ok(())
+    }
+
+    /// Apply the Unicode normalization, if any
+    pub fn normalize_unicode(&self, s: &str) -> String {
+        match self.unicode {
+            Some(form) => unicode::normalize(s, form),
+            None => s.to_string(),
+        }
+    }
+
+    /// Apply the Unicode normalization to the valid UTF-8 parts of a path
+    fn normalize_path_unicode(&self, path: &[u8]) -> Vec<u8> {
+        if self.unicode.is_none() {
+            return path.to_vec();
+        }
+        let mut normalized = Vec::with_capacity(path.len());
+        for chunk in path.utf8_chunks() {
+            normalized.extend(self.normalize_unicode(chunk.valid()).into_bytes());
+            normalized.extend(chunk.invalid());
+        }
+        normalized
+    }
+
+    /// Normalize an input path into a path relative to the repository root
+    pub fn normalize_path(&self, path: &[u8]) -> Result<Vec<u8>, PathError> {
+        let error = |message: &str| PathError {
+            path: paths::display(path),
+            message: message.to_string(),
+        };
+
+        let components =
+            lexical_components(path).ok_or_else(|| error("escapes the repository root"))?;
 
-/// Represents a list of changed files from git diff
-pub struct DiffFiles {
-    pub files: Vec<MatchPath>,
+        let relative = match &self.root {
+            Some(root) if path.starts_with(b"/") => {
+                let root_components = lexical_components(root).unwrap_or_default();
+                if !components.starts_with(&root_components) {
+                    return Err(error("is outside of the repository root"));
+                }
+                components[root_components.len()..].join(&b'/')
+            }
+            _ => components.join(&b'/'),
+        };
+
+        if relative.is_empty() {
+            return Err(error("does not name a file"));
+        }
+        Ok(self.normalize_path_unicode(&relative))
+    }
+}
+
+/// Format of the changed files given on stdin
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    /// Detect a unified diff, read a list of paths otherwise
+    Auto,
+    /// One path per line, like the output of `git diff --name-only`
+    Names,
+    /// Unified diff, like the output of `git diff` or `git format-patch`
+    Patch,
+    /// Line counts and paths, like the output of `git diff --numstat`
+    Numstat,
+}
+
+/// Numbers of lines added and deleted
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LineStats {
+    /// Lines added
+    pub added: usize,
+    /// Lines deleted
+    pub deleted: usize,
+}
+
+impl LineStats {
+    /// Count the lines of runs of changed lines
+    pub(crate) fn from_changes(changes: &[Change]) -> Self {
+        changes.iter().fold(LineStats::default(), |stats, change| {
+            stats
+                + LineStats {
+                    added: change.new.len(),
+                    deleted: change.old.len(),
+                }
+        })
+    }
+
+    /// Count the lines added and deleted between two versions of a file,
+    /// empty where it does not exist. Binary files have no lines.
+    pub(crate) fn from_versions(old: &[u8], new: &[u8]) -> Self {
+        if is_binary(old) || is_binary(new) {
+            return LineStats::default();
+        }
+        Self::from_changes(&diff_lines(&split_lines(old), &split_lines(new)))
+    }
+
+    /// Number of lines added or deleted
+    pub fn total(&self) -> usize {
+        self.added + self.deleted
+    }
+}
+
+impl std::ops::Add for LineStats {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        LineStats {
+            added: self.added + other.added,
+            deleted: self.deleted + other.deleted,
+        }
+    }
+}
+
+/// A changed file
+pub struct ChangedFile {
+    /// The path, split for matching
+    pub path: MatchPath,
+    /// Normalized path, relative to the repository root. It need not be
+    /// valid UTF-8.
+    pub name: Vec<u8>,
+    /// Paths of the file before and after the change, as they were listed.
+    /// The versions of the file are read with them: unlike `name`, they are
+    /// not normalized, and they differ for a renamed file.
+    pub(crate) listed: (Vec<u8>, Vec<u8>),
+    /// Changes of the file, when the input is a patch
+    pub(crate) patch: Option<Rc<FilePatch>>,
+    /// Lines added and deleted, when the input gives them. The changes of a
+    /// renamed file are counted with its new path.
+    pub stats: Option<LineStats>,
+}
+
+impl ChangedFile {
+    /// A file listed under `path`, normalized for matching
+    fn new(path: &[u8], normalization: &PathNormalization) -> Result<Self, PathError> {
+        let name = normalization.normalize_path(path)?;
+        Ok(ChangedFile {
+            path: MatchPath::from_bytes(&name),
+            name,
+            listed: (path.to_vec(), path.to_vec()),
+            patch: None,
+            stats: None,
+        })
+    }
+}
+
+/// Line-level changes of a file
+pub struct LineChanges {
+    pub changes: Vec<Change>,
+    /// Whether the file exists, under the same path, before and after the
+    /// change
+    pub modified: bool,
+    /// Both versions of the file, when they were needed
+    pub contents: Option<Contents>,
+}
+
+impl LineChanges {
+    /// Whether a modified file only had whitespace changes, or comment
+    /// changes when `comments` is set. `path` tells the comment syntax.
+    pub fn is_cosmetic(&self, path: &[u8], comments: bool) -> bool {
+        let Some(contents) = &self.contents else {
+            return false;
+        };
+        if !self.modified || self.changes.is_empty() {
+            return false;
+        }
+        let syntax = comments::syntax_for(&String::from_utf8_lossy(path));
+        let strip = |lines: &[String]| comments::strip(&lines.join("\n"), syntax, comments);
+        strip(&contents.old) == strip(&contents.new)
+    }
+}
+
+/// Whether the content of a file is binary: like for git, a NUL byte near
+/// the start marks it
+pub(crate) fn is_binary(content: &[u8]) -> bool {
+    content.iter().take(8000).any(|&b| b == 0)
+}
+
+/// Split the content of a file into lines, invalid UTF-8 being replaced
+pub(crate) fn split_lines(content: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(content)
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+/// Half the number of edits beyond which the shortest edit script of a
+/// part of the files is not searched: all its lines count as changed, like
+/// for a rewrite
+const MAX_EDIT_COST: usize = 4096;
+
+/// Find the middle snake of a shortest edit script from `a` to `b`, which
+/// both start and end with different lines: its start and end points.
+/// `forward` and `backward` hold the furthest x reached on each diagonal.
+/// Returns None when the edit script is too long.
+fn middle_snake(
+    a: &[usize],
+    b: &[usize],
+    forward: &mut [usize],
+    backward: &mut [usize],
+) -> Option<((usize, usize), (usize, usize))> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let delta = n - m;
+    let odd = delta % 2 != 0;
+    let max = (n + m + 1) / 2;
+    // Diagonals k = x - y range from -max - 1 to max + 1
+    let at = |k: isize| (k + max + 1) as usize;
+    forward[at(1)] = 0;
+    backward[at(1)] = 0;
+    for d in 0..=max.min(MAX_EDIT_COST as isize) {
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && forward[at(k - 1)] < forward[at(k + 1)]) {
+                forward[at(k + 1)] as isize
+            } else {
+                forward[at(k - 1)] as isize + 1
+            };
+            let start = (x, x - k);
+            while x < n && x - k < m && a[x as usize] == b[(x - k) as usize] {
+                x += 1;
+            }
+            forward[at(k)] = x as usize;
+            // The backward paths of the previous round end on the
+            // diagonals delta - k for k in -(d - 1)..d
+            let c = delta - k;
+            if odd && -d < c && c < d && x + backward[at(c)] as isize >= n {
+                let point = |(x, y): (isize, isize)| (x as usize, y as usize);
+                return Some((point(start), point((x, x - k))));
+            }
+        }
+        // Backward, on the reversed files
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && backward[at(k - 1)] < backward[at(k + 1)]) {
+                backward[at(k + 1)] as isize
+            } else {
+                backward[at(k - 1)] as isize + 1
+            };
+            let end = (n - x, m - (x - k));
+            while x < n && x - k < m && a[(n - 1 - x) as usize] == b[(m - 1 - (x - k)) as usize] {
+                x += 1;
+            }
+            backward[at(k)] = x as usize;
+            let c = delta - k;
+            if !odd && -d <= c && c <= d && x + forward[at(c)] as isize >= n {
+                let point = |(x, y): (isize, isize)| (x as usize, y as usize);
+                return Some((point((n - x, m - (x - k))), point(end)));
+            }
+        }
+    }
+    None
 }
 
-impl DiffFiles {
-    /// Read changed files from stdin (output of git diff --name-only)
-    pub fn from_stdin() -> io::Result<Self> {
+/// Mark the lines removed from `a` and added to `b` by a shortest edit
+/// script, found with the linear space variant of the algorithm of Myers:
+/// the middle snake of the script splits it into two smaller ones
+fn mark_edits(a: &[usize], b: &[usize], removed: &mut [bool], added: &mut [bool]) {
+    let size = a.len() + b.len() + 4;
+    let mut forward = vec![0; size];
+    let mut backward = vec![0; size];
+    let mut parts = vec![(0, a.len(), 0, b.len())];
+    while let Some((mut x0, mut x1, mut y0, mut y1)) = parts.pop() {
+        while x0 < x1 && y0 < y1 && a[x0] == b[y0] {
+            x0 += 1;
+            y0 += 1;
+        }
+        while x0 < x1 && y0 < y1 && a[x1 - 1] == b[y1 - 1] {
+            x1 -= 1;
+            y1 -= 1;
+        }
+        if x0 == x1 || y0 == y1 {
+            removed[x0..x1].fill(true);
+            added[y0..y1].fill(true);
+            continue;
+        }
+        let (a_part, b_part) = (&a[x0..x1], &b[y0..y1]);
+        match middle_snake(a_part, b_part, &mut forward, &mut backward) {
+            Some(((x, y), (u, v))) => {
+                parts.push((x0, x0 + x, y0, y0 + y));
+                parts.push((x0 + u, x1, y0 + v, y1));
+            }
+            None => {
+                removed[x0..x1].fill(true);
+                added[y0..y1].fill(true);
+            }
+        }
+    }
+}
+
+/// Compute the runs of changed lines between two versions of a file, with
+/// the algorithm of Myers used by git
+pub fn diff_lines(old: &[String], new: &[String]) -> Vec<Change> {
+    // Only diff what lies between the common prefix and suffix
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    // Compare lines by number
+    let (old_lines, new_lines) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+    let mut numbers: HashMap<&str, usize> = HashMap::new();
+    let (mut a, mut b) = (Vec::new(), Vec::new());
+    for (lines, numbered) in [(old_lines, &mut a), (new_lines, &mut b)] {
+        for line in lines {
+            let next = numbers.len();
+            numbered.push(*numbers.entry(line.as_str()).or_insert(next));
+        }
+    }
+
+    let mut removed = vec![false; a.len()];
+    let mut added = vec![false; b.len()];
+    mark_edits(&a, &b, &mut removed, &mut added);
+
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && !removed[i] && !added[j] {
+            i += 1;
+            j += 1;
+            continue;
+        }
+        let (old_start, new_start) = (i, j);
+        while i < a.len() && removed[i] {
+            i += 1;
+        }
+        while j < b.len() && added[j] {
+            j += 1;
+        }
+        changes.push(Change {
+            old: prefix + old_start + 1..prefix + i + 1,
+            new: prefix + new_start + 1..prefix + j + 1,
+        });
+    }
+    changes
+}
+
+/// Where the versions of the changed files are read from, to evaluate
+/// line-range rules and ignored changes
+#[derive(Debug, Clone)]
+pub(crate) enum ContentSource {
+    /// Changes given as input. Patches give the changed lines, and the new
+    /// version of modified files is read from the working tree at `root`.
+    WorkTree { root: Option<PathBuf> },
+    /// Changes listed with git, which gives both versions of the files
+    Git {
+        dir: Option<PathBuf>,
+        range: DiffRange,
+        mode: DiffMode,
+        backend: Backend,
+    },
+}
+
+impl ContentSource {
+    fn work_tree(normalization: &PathNormalization) -> Self {
+        ContentSource::WorkTree {
+            root: normalization.root.as_deref().map(paths::to_path),
+        }
+    }
+}
+
+/// The changed files, in the order they were listed
+pub struct ChangedFiles {
+    /// The files
+    pub files: Vec<ChangedFile>,
+    pub(crate) source: ContentSource,
+}
+
+impl ChangedFiles {
+    /// Read changed files from stdin
+    pub fn from_stdin(
+        format: InputFormat,
+        normalization: &PathNormalization,
+    ) -> Result<Self, Error> {
         let stdin = io::stdin();
-        let reader = stdin.lock();
+        Self::from_reader(stdin.lock(), format, normalization)
+    }
 
-        let mut files = Vec::new();
+    /// Read changed files, as a list of paths, a patch or a numstat listing.
+    /// The new versions of the files are read from the working tree, at the
+    /// root of `normalization` or the current directory, when needed.
+    pub fn from_reader<R: BufRead>(
+        mut reader: R,
+        format: InputFormat,
+        normalization: &PathNormalization,
+    ) -> Result<Self, Error> {
+        let mut input = Vec::new();
+        reader.read_to_end(&mut input).map_err(Error::Input)?;
 
-        for line in reader.lines() {
-            let line = line?;
-            let trimmed = line.trim();
+        let format = match format {
+            InputFormat::Auto if patch::looks_like_patch(&input) => InputFormat::Patch,
+            InputFormat::Auto if numstat::looks_like_numstat(&input) => InputFormat::Numstat,
+            format => format,
+        };
+        match format {
+            InputFormat::Patch => {
+                let patches = patch::parse_patch(&input).map_err(Error::Patch)?;
+                return Ok(Self::from_patches(patches, normalization)?);
+            }
+            InputFormat::Numstat => {
+                let entries = numstat::parse_numstat(&input).map_err(Error::Numstat)?;
+                return Ok(Self::from_numstat(entries, normalization)?);
+            }
+            InputFormat::Auto | InputFormat::Names => {}
+        }
+
+        let mut files = Vec::new();
+        for line in input.split(|&b| b == b'\n') {
+            let trimmed = line.trim_ascii();
 
             // Skip empty lines
             if trimmed.is_empty() {
                 continue;
             }
 
-            files.push(MatchPath::from_str(trimmed));
+            files.push(ChangedFile::new(trimmed, normalization)?);
+        }
+
+        Ok(ChangedFiles {
+            files,
+            source: ContentSource::work_tree(normalization),
+        })
+    }
+
+    /// List the changed files of `range` with git, in the repository at the
+    /// root of `normalization` or in the current directory, with the lines
+    /// added and deleted in each. Like for numstat listings, both the old and
+    /// the new path of a renamed file are changed. Both versions of the files
+    /// are read from git when needed.
+    pub fn from_git(
+        range: &DiffRange,
+        mode: DiffMode,
+        backend: Backend,
+        normalization: &PathNormalization,
+    ) -> Result<Self, Error> {
+        let dir = normalization.root.as_deref().map(paths::to_path);
+        let entries =
+            git::changed_files(dir.as_deref(), range, mode, backend).map_err(Error::Git)?;
+        Ok(ChangedFiles {
+            source: ContentSource::Git {
+                dir,
+                range: range.clone(),
+                mode,
+                backend,
+            },
+            ..Self::from_numstat(entries, normalization)?
+        })
+    }
+
+    /// Build the list from paths obtained by other means. The new versions
+    /// of the files are read from the working tree when needed.
+    pub fn from_paths<I, S>(paths: I, normalization: &PathNormalization) -> Result<Self, PathError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<[u8]>,
+    {
+        let files = paths
+            .into_iter()
+            .map(|path| ChangedFile::new(path.as_ref(), normalization))
+            .collect::<Result<_, _>>()?;
+        Ok(ChangedFiles {
+            files,
+            source: ContentSource::work_tree(normalization),
+        })
+    }
+
+    /// Build the list from the files of a patch. Like for renames listed by
+    /// git, both the old and the new path of a renamed file are changed.
+    fn from_patches(
+        patches: Vec<FilePatch>,
+        normalization: &PathNormalization,
+    ) -> Result<Self, PathError> {
+        let mut files = Vec::new();
+        for patch in patches {
+            let patch = Rc::new(patch);
+            let old = match (&patch.old_path, &patch.new_path) {
+                (Some(old), Some(new)) if old != new => Some(old),
+                _ => None,
+            };
+            let stats = LineStats::from_changes(&patch.changes());
+            let Some(path) = patch.new_path.as_ref().or(patch.old_path.as_ref()) else {
+                continue;
+            };
+            let listed = (old.unwrap_or(path).clone(), path.clone());
+            if let Some(old) = old {
+                files.push(ChangedFile {
+                    listed: listed.clone(),
+                    patch: Some(Rc::clone(&patch)),
+                    stats: Some(LineStats::default()),
+                    ..ChangedFile::new(old, normalization)?
+                });
+            }
+            files.push(ChangedFile {
+                listed,
+                patch: Some(Rc::clone(&patch)),
+                stats: Some(stats),
+                ..ChangedFile::new(path, normalization)?
+            });
+        }
+        Ok(ChangedFiles {
+            files,
+            source: ContentSource::work_tree(normalization),
+        })
+    }
+
+    /// Build the list from a numstat listing. Like for patches, both the old
+    /// and the new path of a renamed file are changed.
+    fn from_numstat(
+        entries: Vec<NumstatEntry>,
+        normalization: &PathNormalization,
+    ) -> Result<Self, PathError> {
+        let mut files = Vec::new();
+        for entry in entries {
+            let old = entry.old_path.filter(|old| *old != entry.path);
+            let listed = (
+                old.as_ref().unwrap_or(&entry.path).clone(),
+                entry.path.clone(),
+            );
+            if let Some(old) = &old {
+                files.push(ChangedFile {
+                    listed: listed.clone(),
+                    stats: Some(LineStats::default()),
+                    ..ChangedFile::new(old, normalization)?
+                });
+            }
+            files.push(ChangedFile {
+                listed,
+                stats: Some(entry.stats),
+                ..ChangedFile::new(&entry.path, normalization)?
+            });
         }
+        Ok(ChangedFiles {
+            files,
+            source: ContentSource::work_tree(normalization),
+        })
+    }
+}
 
-        Ok(DiffFiles { files })
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matching::Pattern;
+
+    #[test]
+    fn unicode_normalization_of_input() {
+        // "café" with a decomposed "é", as committed from macOS
+        let input = "docs/cafe\u{301}.md\n".as_bytes();
+        let pattern = Pattern::compile("docs/caf\u{e9}.md").unwrap();
+
+        let raw =
+            ChangedFiles::from_reader(input, InputFormat::Names, &PathNormalization::default())
+                .unwrap();
+        assert!(!pattern.is_match(&raw.files[0].path));
+
+        let normalization = PathNormalization {
+            unicode: Some(NormalizationForm::Nfc),
+            ..Default::default()
+        };
+        let normalized =
+            ChangedFiles::from_reader(input, InputFormat::Names, &normalization).unwrap();
+        assert!(pattern.is_match(&normalized.files[0].path));
+    }
+
+    #[test]
+    fn non_utf8_names_input() {
+        // "café.txt" in Latin-1
+        let input = &b"docs/caf\xe9.txt\nsrc/main.rs\n"[..];
+        let diff =
+            ChangedFiles::from_reader(input, InputFormat::Names, &PathNormalization::default())
+                .unwrap();
+        assert_eq!(diff.files[0].name, b"docs/caf\xe9.txt");
+        assert!(
+            Pattern::compile("docs/*.txt")
+                .unwrap()
+                .is_match(&diff.files[0].path)
+        );
+        assert!(
+            Pattern::compile("docs/caf?.txt")
+                .unwrap()
+                .is_match(&diff.files[0].path)
+        );
+        assert!(
+            !Pattern::compile("docs/caf\u{e9}.txt")
+                .unwrap()
+                .is_match(&diff.files[0].path)
+        );
+    }
+
+    #[test]
+    fn lexical_path_normalization() {
+        let normalization = PathNormalization::default();
+        for (input, expected) in [
+            (&b"./src/a.rs"[..], "src/a.rs"),
+            (b"src//a.rs", "src/a.rs"),
+            (b"src/x/../a.rs", "src/a.rs"),
+            (b"src/./x/./../a.rs/", "src/a.rs"),
+        ] {
+            assert_eq!(
+                normalization.normalize_path(input).unwrap(),
+                expected.as_bytes()
+            );
+        }
+        assert!(normalization.normalize_path(b"../a.rs").is_err());
+        assert!(normalization.normalize_path(b"src/../../a.rs").is_err());
+        assert!(normalization.normalize_path(b"src/..").is_err());
+    }
+
+    #[test]
+    fn root_path_normalization() {
+        let mut normalization = PathNormalization::default();
+        normalization
+            .set_root(Path::new("../repo/"), Path::new("/home/ci/work"))
+            .unwrap();
+        assert_eq!(normalization.root.as_deref(), Some(&b"/home/ci/repo"[..]));
+
+        assert_eq!(
+            normalization
+                .normalize_path(b"/home/ci/repo/src/a.rs")
+                .unwrap(),
+            b"src/a.rs"
+        );
+        assert_eq!(
+            normalization
+                .normalize_path(b"/home/ci//repo/./src/../a.rs")
+                .unwrap(),
+            b"a.rs"
+        );
+        assert_eq!(
+            normalization.normalize_path(b"src/a.rs").unwrap(),
+            b"src/a.rs"
+        );
+        assert!(
+            normalization
+                .normalize_path(b"/home/ci/other/a.rs")
+                .is_err()
+        );
+        assert!(
+            normalization
+                .normalize_path(b"/home/ci/repository/a.rs")
+                .is_err()
+        );
+        assert!(
+            normalization
+                .normalize_path(b"/home/ci/repo/../a.rs")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn patch_input() {
+        let input = "diff --git a/./src/main.rs b/./src/main.rs
+--- a/./src/main.rs
++++ b/./src/main.rs
+@@ -1 +1 @@
+-a
++b
+diff --git a/old.rs b/new.rs
+similarity index 100%
+rename from old.rs
+rename to new.rs
+diff --git a/gone.rs b/gone.rs
+deleted file mode 100644
+--- a/gone.rs
++++ /dev/null
+@@ -1 +0,0 @@
+-x
+"
+        .as_bytes();
+        let normalization = PathNormalization::default();
+        let expected = ["src/main.rs", "old.rs", "new.rs", "gone.rs"];
+        for format in [InputFormat::Auto, InputFormat::Patch] {
+            let diff = ChangedFiles::from_reader(input, format, &normalization).unwrap();
+            let names: Vec<_> = diff.files.iter().map(|f| paths::display(&f.name)).collect();
+            assert_eq!(names, expected);
+            assert!(diff.files.iter().all(|f| f.patch.is_some()));
+            let stats: Vec<_> = diff
+                .files
+                .iter()
+                .map(|f| f.stats.unwrap().total())
+                .collect();
+            assert_eq!(stats, [2, 0, 0, 1]);
+        }
+        let names = ChangedFiles::from_reader(input, InputFormat::Names, &normalization).unwrap();
+        assert!(names.files.iter().all(|file| file.patch.is_none()));
+        assert!(
+            ChangedFiles::from_reader(&b"a\n@@ -1 +1 @@\n"[..], InputFormat::Patch, &normalization)
+                .is_err()
+        );
+    }
+
+    fn lines(text: &str) -> Vec<String> {
+        text.chars().map(String::from).collect()
+    }
+
+    #[test]
+    fn line_diff() {
+        let diff = |old: &str, new: &str| diff_lines(&lines(old), &lines(new));
+        assert_eq!(diff("abc", "abc"), vec![]);
+        assert_eq!(
+            diff("abcd", "aXcd"),
+            vec![Change {
+                old: 2..3,
+                new: 2..3
+            }]
+        );
+        assert_eq!(
+            diff("abc", "abXYc"),
+            vec![Change {
+                old: 3..3,
+                new: 3..5
+            }]
+        );
+        assert_eq!(
+            diff("abcdef", "bcdXf"),
+            vec![
+                Change {
+                    old: 1..2,
+                    new: 1..1
+                },
+                Change {
+                    old: 5..6,
+                    new: 4..5
+                }
+            ]
+        );
+        assert_eq!(
+            diff("", "ab"),
+            vec![Change {
+                old: 1..1,
+                new: 1..3
+            }]
+        );
+        assert_eq!(
+            diff("abcabba", "cbabac")
+                .iter()
+                .map(|c| c.old.len() + c.new.len())
+                .sum::<usize>(),
+            5
+        );
+    }
+
+    #[test]
+    fn large_rewrite() {
+        let numbered = |prefix: &str, count: usize| -> Vec<String> {
+            (0..count).map(|i| format!("{}{}", prefix, i)).collect()
+        };
+        // Beyond the cost limit, the rewritten lines all count as changed
+        let (old, new) = (numbered("old ", 15000), numbered("new ", 15000));
+        assert_eq!(
+            diff_lines(&old, &new),
+            vec![Change {
+                old: 1..15001,
+                new: 1..15001
+            }]
+        );
+
+        // Within it, the script is the shortest one
+        let old = numbered("", 20000);
+        let mut new = old.clone();
+        for i in (0..20000).step_by(10) {
+            new[i] = format!("changed {}", i);
+        }
+        let changes = diff_lines(&old, &new);
+        assert_eq!(changes.len(), 2000);
+        assert!(changes.iter().all(|c| c.old.len() == 1 && c.new == c.old));
     }
 }
@@ -0,0 +1,199 @@
+// Parse the output of `git diff --numstat`, with or without `-z`
+
+use super::LineStats;
+use super::patch::unquote;
+use crate::paths;
+use std::fmt;
+
+/// A file listed by `git diff --numstat`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumstatEntry {
+    /// Source path of a rename or copy
+    pub old_path: Option<Vec<u8>>,
+    pub path: Vec<u8>,
+    /// Lines added and deleted. Binary files, listed with "-" counts, have
+    /// no lines.
+    pub stats: LineStats,
+}
+
+/// Error raised when a numstat listing cannot be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumstatError {
+    /// 1-based number of the offending record
+    pub line: usize,
+    /// What is wrong with it
+    pub message: String,
+}
+
+impl fmt::Display for NumstatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid numstat at line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for NumstatError {}
+
+/// Split the counts of a record from the rest of it
+fn split_counts(record: &[u8]) -> Option<(Option<usize>, Option<usize>, &[u8])> {
+    let mut parts = record.splitn(3, |&b| b == b'\t');
+    let mut count = || match parts.next()? {
+        b"-" => Some(None),
+        count => std::str::from_utf8(count).ok()?.parse().ok().map(Some),
+    };
+    let (added, deleted) = (count()?, count()?);
+    Some((added, deleted, parts.next()?))
+}
+
+/// Whether the input looks like a numstat listing rather than a list of paths
+pub fn looks_like_numstat(input: &[u8]) -> bool {
+    let mut records = input
+        .split(|&b| b == b'\n' || b == 0)
+        .filter(|record| !record.trim_ascii().is_empty())
+        .peekable();
+    // With -z, rename paths are records of their own
+    let first_is_counts = records
+        .peek()
+        .is_some_and(|record| split_counts(record).is_some());
+    first_is_counts && (input.contains(&0) || records.all(|r| split_counts(r).is_some()))
+}
+
+/// Split a rename of the form "old => new" or "dir/{old => new}/file"
+fn split_rename(path: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+    if let Some((prefix, rest)) = paths::split_once(path, b"{")
+        && let Some((inner, suffix)) = paths::split_once(rest, b"}")
+        && let Some((old, new)) = paths::split_once(inner, b" => ")
+    {
+        return Some((
+            [prefix, old, suffix].concat(),
+            [prefix, new, suffix].concat(),
+        ));
+    }
+    let (old, new) = paths::split_once(path, b" => ")?;
+    Some((old.to_vec(), new.to_vec()))
+}
+
+/// Parse a numstat listing. Renames are recognized in both the "old => new"
+/// form and the separate paths given with `-z`.
+pub fn parse_numstat(input: &[u8]) -> Result<Vec<NumstatEntry>, NumstatError> {
+    let nul_separated = input.contains(&0);
+    let separator = if nul_separated { 0 } else { b'\n' };
+    let mut records = input
+        .split(|&b| b == separator)
+        .map(|record| record.strip_suffix(b"\r").unwrap_or(record))
+        .enumerate();
+
+    let mut entries = Vec::new();
+    while let Some((index, record)) = records.next() {
+        let error = |message: &str| NumstatError {
+            line: index + 1,
+            message: message.to_string(),
+        };
+        if record.trim_ascii().is_empty() {
+            continue;
+        }
+        let (added, deleted, path) =
+            split_counts(record).ok_or_else(|| error("expected added and deleted line counts"))?;
+        let stats = LineStats {
+            added: added.unwrap_or(0),
+            deleted: deleted.unwrap_or(0),
+        };
+
+        let (old_path, path) = if nul_separated && path.is_empty() {
+            let mut next_path = || {
+                records
+                    .next()
+                    .map(|(_, path)| path.to_vec())
+                    .filter(|path| !path.is_empty())
+                    .ok_or_else(|| error("missing path of a rename"))
+            };
+            (Some(next_path()?), next_path()?)
+        } else if nul_separated {
+            (None, path.to_vec())
+        } else if path.starts_with(b"\"") {
+            let path = unquote(path).ok_or_else(|| error("invalid quoted path"))?;
+            (None, path)
+        } else {
+            match split_rename(path) {
+                Some((old, new)) => (Some(old), new),
+                None => (None, path.to_vec()),
+            }
+        };
+        entries.push(NumstatEntry {
+            old_path,
+            path,
+            stats,
+        });
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(old_path: Option<&str>, path: &str, added: usize, deleted: usize) -> NumstatEntry {
+        NumstatEntry {
+            old_path: old_path.map(|p| p.as_bytes().to_vec()),
+            path: path.as_bytes().to_vec(),
+            stats: LineStats { added, deleted },
+        }
+    }
+
+    #[test]
+    fn parse_listings() {
+        let input = b"10\t2\tsrc/main.rs
+-\t-\tlogo.png
+0\t0\told.rs => new.rs
+3\t1\tsrc/{a => b}/lib.rs
+1\t0\tsrc/{ => sub}/mod.rs
+2\t0\t\"tab\\there.txt\"
+";
+        assert_eq!(
+            parse_numstat(input).unwrap(),
+            [
+                entry(None, "src/main.rs", 10, 2),
+                entry(None, "logo.png", 0, 0),
+                entry(Some("old.rs"), "new.rs", 0, 0),
+                entry(Some("src/a/lib.rs"), "src/b/lib.rs", 3, 1),
+                entry(Some("src//mod.rs"), "src/sub/mod.rs", 1, 0),
+                entry(None, "tab\there.txt", 2, 0),
+            ]
+        );
+
+        let input = b"10\t2\tsrc/main.rs\x003\t1\t\x00a b.rs\x00c.rs\x00-\t-\tlogo.png\x00";
+        assert_eq!(
+            parse_numstat(input).unwrap(),
+            [
+                entry(None, "src/main.rs", 10, 2),
+                entry(Some("a b.rs"), "c.rs", 3, 1),
+                entry(None, "logo.png", 0, 0),
+            ]
+        );
+
+        let e = parse_numstat(b"1\t2\ta.rs\nb.rs\n").unwrap_err();
+        assert_eq!(e.line, 2);
+        assert!(parse_numstat(b"1\t2\t\x00a.rs\x00").is_err());
+
+        // Paths are kept as bytes
+        assert_eq!(
+            parse_numstat(b"1\t0\tcaf\xe9.txt\n").unwrap(),
+            [NumstatEntry {
+                old_path: None,
+                path: b"caf\xe9.txt".to_vec(),
+                stats: LineStats {
+                    added: 1,
+                    deleted: 0
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn detect_listings() {
+        assert!(looks_like_numstat(b"1\t2\ta.rs\n-\t-\tb.png\n"));
+        assert!(looks_like_numstat(b"1\t2\t\x00a.rs\x00b.rs\x00"));
+        assert!(!looks_like_numstat(b"a.rs\nb.rs\n"));
+        assert!(!looks_like_numstat(b"1\t2\ta.rs\nb.rs\n"));
+        assert!(!looks_like_numstat(b""));
+    }
+}
@@ -0,0 +1,804 @@
+// Parse unified diffs, as produced by `git diff`, `git format-patch` or
+// `diff -u`
+
+use crate::paths;
+use std::fmt;
+use std::ops::Range;
+
+/// Kind of a line in a hunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    Context,
+    Added,
+    Removed,
+}
+
+/// A line of a hunk, without its leading marker
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffLine {
+    pub kind: LineKind,
+    pub text: String,
+}
+
+/// A run of consecutive changed lines, as 1-based half-open line ranges.
+/// A pure addition has an empty `old` range positioned where the lines were
+/// inserted, and conversely for a pure removal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+/// A hunk of a unified diff
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_count: usize,
+    pub new_start: usize,
+    pub new_count: usize,
+    pub lines: Vec<DiffLine>,
+}
+
+impl Hunk {
+    /// The runs of changed lines of the hunk
+    pub fn changes(&self) -> Vec<Change> {
+        let mut changes: Vec<Change> = Vec::new();
+        // With a zero count, the start is the line before the hunk
+        let mut old_line = self.old_start + (self.old_count == 0) as usize;
+        let mut new_line = self.new_start + (self.new_count == 0) as usize;
+        let mut in_change = false;
+        for line in &self.lines {
+            match line.kind {
+                LineKind::Context => {
+                    in_change = false;
+                    old_line += 1;
+                    new_line += 1;
+                    continue;
+                }
+                _ if !in_change => {
+                    changes.push(Change {
+                        old: old_line..old_line,
+                        new: new_line..new_line,
+                    });
+                    in_change = true;
+                }
+                _ => {}
+            }
+            let change = changes.last_mut().unwrap();
+            if line.kind == LineKind::Added {
+                new_line += 1;
+                change.new.end = new_line;
+            } else {
+                old_line += 1;
+                change.old.end = old_line;
+            }
+        }
+        changes
+    }
+}
+
+/// Changes of one file in a patch
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FilePatch {
+    /// Path before the change, None for an added file
+    pub old_path: Option<Vec<u8>>,
+    /// Path after the change, None for a deleted file
+    pub new_path: Option<Vec<u8>>,
+    /// Whether the file is binary, in which case there are no hunks
+    pub binary: bool,
+    pub hunks: Vec<Hunk>,
+}
+
+/// Lines of a file before and after a change
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Contents {
+    pub old: Vec<String>,
+    pub new: Vec<String>,
+}
+
+impl FilePatch {
+    /// The runs of changed lines of all hunks
+    pub fn changes(&self) -> Vec<Change> {
+        self.hunks.iter().flat_map(Hunk::changes).collect()
+    }
+
+    /// Lines of the given kinds, in the order of the hunks
+    fn lines(&self, kinds: &[LineKind]) -> Vec<String> {
+        self.hunks
+            .iter()
+            .flat_map(|hunk| &hunk.lines)
+            .filter(|line| kinds.contains(&line.kind))
+            .map(|line| line.text.clone())
+            .collect()
+    }
+
+    /// Whether the file exists, under the same path, before and after the
+    /// change
+    pub fn is_modified(&self) -> bool {
+        self.old_path.is_some() && self.old_path == self.new_path
+    }
+
+    /// The lines of the hunks, context included, before and after the
+    /// change. Unlike [`FilePatch::contents`], it needs no version of the
+    /// file.
+    pub fn hunk_contents(&self) -> Contents {
+        Contents {
+            old: self.lines(&[LineKind::Context, LineKind::Removed]),
+            new: self.lines(&[LineKind::Context, LineKind::Added]),
+        }
+    }
+
+    /// Both versions of the file. The patch holds the whole file when it is
+    /// added or deleted; otherwise the new version must be given, and the old
+    /// one is rebuilt by reverting the hunks.
+    pub fn contents(&self, new: Option<&str>) -> Result<Contents, String> {
+        if self.old_path.is_none() {
+            return Ok(Contents {
+                old: Vec::new(),
+                new: self.lines(&[LineKind::Added]),
+            });
+        }
+        if self.new_path.is_none() {
+            return Ok(Contents {
+                old: self.lines(&[LineKind::Removed]),
+                new: Vec::new(),
+            });
+        }
+
+        let new: Vec<String> = new
+            .ok_or("the new version of the file is needed")?
+            .lines()
+            .map(str::to_string)
+            .collect();
+        let mismatch = |line: usize| format!("line {} does not match the patch", line);
+        let mut old = Vec::new();
+        let mut pos = 0;
+        for hunk in &self.hunks {
+            // With a zero count, the start is the line before the hunk
+            let start = hunk
+                .new_start
+                .checked_sub((hunk.new_count > 0) as usize)
+                .ok_or_else(|| mismatch(hunk.new_start))?;
+            if start < pos || start > new.len() {
+                return Err(mismatch(start + 1));
+            }
+            old.extend_from_slice(&new[pos..start]);
+            pos = start;
+            for line in &hunk.lines {
+                if line.kind != LineKind::Added {
+                    old.push(line.text.clone());
+                }
+                if line.kind != LineKind::Removed {
+                    if new.get(pos) != Some(&line.text) {
+                        return Err(mismatch(pos + 1));
+                    }
+                    pos += 1;
+                }
+            }
+        }
+        old.extend_from_slice(&new[pos..]);
+        Ok(Contents { old, new })
+    }
+}
+
+/// Error raised when a patch cannot be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchError {
+    /// 1-based line number
+    pub line: usize,
+    /// What is wrong with it
+    pub message: String,
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid patch at line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+/// Split the input in lines, dropping the line terminators
+fn split_lines(input: &[u8]) -> Vec<&[u8]> {
+    let input = input.strip_suffix(b"\n").unwrap_or(input);
+    if input.is_empty() {
+        return Vec::new();
+    }
+    input
+        .split(|&b| b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .collect()
+}
+
+/// Whether the input looks like a unified diff rather than a list of paths
+pub fn looks_like_patch(input: &[u8]) -> bool {
+    let lines = split_lines(input);
+    lines.iter().enumerate().any(|(i, line)| {
+        line.starts_with(b"diff --git ")
+            || line.starts_with(b"diff --cc ")
+            || (line.starts_with(b"--- ")
+                && lines
+                    .get(i + 1)
+                    .is_some_and(|next| next.starts_with(b"+++ ")))
+    })
+}
+
+/// Undo the C-style quoting git applies to paths with special characters
+pub(super) fn unquote(quoted: &[u8]) -> Option<Vec<u8>> {
+    let inner = quoted.strip_prefix(b"\"")?.strip_suffix(b"\"")?;
+    let mut bytes = Vec::new();
+    let mut iter = inner.iter().copied();
+    while let Some(b) = iter.next() {
+        if b != b'\\' {
+            bytes.push(b);
+            continue;
+        }
+        let byte = match iter.next()? {
+            b'a' => 0x07,
+            b'b' => 0x08,
+            b'f' => 0x0c,
+            b'n' => b'\n',
+            b'r' => b'\r',
+            b't' => b'\t',
+            b'v' => 0x0b,
+            c @ (b'"' | b'\\') => c,
+            c @ b'0'..=b'3' => {
+                let mut value = c - b'0';
+                for _ in 0..2 {
+                    let digit = iter.next().filter(|d| (b'0'..=b'7').contains(d))?;
+                    value = value * 8 + (digit - b'0');
+                }
+                value
+            }
+            _ => return None,
+        };
+        bytes.push(byte);
+    }
+    Some(bytes)
+}
+
+/// Parse a path of a `---`/`+++` line or of an extended header.
+/// Returns None for /dev/null.
+fn parse_path(text: &[u8], prefix: Option<&[u8]>) -> Result<Option<Vec<u8>>, String> {
+    let path = if text.starts_with(b"\"") {
+        unquote(text).ok_or_else(|| format!("invalid quoted path {}", paths::display(text)))?
+    } else {
+        // Other diff tools append a timestamp after a tab
+        text.split(|&b| b == b'\t').next().unwrap_or(text).to_vec()
+    };
+    if path == b"/dev/null" {
+        return Ok(None);
+    }
+    let path = match prefix {
+        Some(prefix) => path
+            .strip_prefix(prefix)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| {
+                format!(
+                    "unsupported path prefix in {}, only the default \"a/\" and \"b/\" \
+                 or --no-prefix without renames are read",
+                    paths::display(&path)
+                )
+            })?,
+        None => path,
+    };
+    Ok(Some(path))
+}
+
+/// Split a quoted path from the start of `text`, returning the rest
+fn split_quoted(text: &[u8]) -> Option<(Vec<u8>, &[u8])> {
+    let mut escaped = false;
+    for (i, &b) in text.iter().enumerate().skip(1) {
+        match b {
+            b'"' if !escaped => return Some((unquote(&text[..=i])?, &text[i + 1..])),
+            b'\\' => escaped = !escaped,
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+/// Paths of a `diff --git a/x b/y` line, when they can be told apart
+fn parse_git_header(text: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+    if text.starts_with(b"\"") {
+        let (old, rest) = split_quoted(text)?;
+        let new = rest.strip_prefix(b" ")?;
+        let new = if new.starts_with(b"\"") {
+            split_quoted(new)?.0
+        } else {
+            new.to_vec()
+        };
+        return Some((old, new));
+    }
+    if let Some((old, _)) = paths::split_once(text, b" \"") {
+        return Some((old.to_vec(), split_quoted(&text[old.len() + 1..])?.0));
+    }
+    // Without renames, both paths are the same apart from their prefix
+    let half = text.len().checked_sub(1)? / 2;
+    let (old, new) = (&text[..half], &text[half + 1..]);
+    (text[half] == b' ' && old.get(2..) == new.get(2..)).then(|| (old.to_vec(), new.to_vec()))
+}
+
+/// Parse the numbers of a hunk header range, like "12,3" or "12"
+fn parse_range(text: &str) -> Option<(usize, usize)> {
+    match text.split_once(',') {
+        Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+        None => Some((text.parse().ok()?, 1)),
+    }
+}
+
+/// Parse a `@@ -a,b +c,d @@` hunk header
+fn parse_hunk_header(line: &str) -> Option<Hunk> {
+    let rest = line.strip_prefix("@@ -")?;
+    let (ranges, _) = rest.split_once(" @@")?;
+    let (old, new) = ranges.split_once(" +")?;
+    let (old_start, old_count) = parse_range(old)?;
+    let (new_start, new_count) = parse_range(new)?;
+    Some(Hunk {
+        old_start,
+        old_count,
+        new_start,
+        new_count,
+        lines: Vec::new(),
+    })
+}
+
+/// Parse a unified diff. Text outside of the file diffs, like the headers and
+/// message of a `git format-patch` email, is ignored.
+pub fn parse_patch(input: &[u8]) -> Result<Vec<FilePatch>, PatchError> {
+    let lines = split_lines(input);
+    let mut files: Vec<FilePatch> = Vec::new();
+    // Whether the current file started with a "diff --git" line, in which
+    // case its paths have the prefixes found there: "a/" and "b/", or none
+    // with --no-prefix
+    let mut git_header = false;
+    let mut prefixes: (&[u8], &[u8]) = (b"a/", b"b/");
+    let mut i = 0;
+
+    while i < lines.len() {
+        let number = i + 1;
+        let error = |message: String| PatchError {
+            line: number,
+            message,
+        };
+        let raw = lines[i];
+        i += 1;
+
+        if raw.starts_with(b"@@ ") {
+            let file = files
+                .last_mut()
+                .ok_or_else(|| error("hunk found before any file header".to_string()))?;
+            let header = std::str::from_utf8(raw).unwrap_or("");
+            let mut hunk = parse_hunk_header(header)
+                .ok_or_else(|| error("invalid hunk header".to_string()))?;
+
+            let (mut old_left, mut new_left) = (hunk.old_count, hunk.new_count);
+            while old_left > 0 || new_left > 0 {
+                let line = lines
+                    .get(i)
+                    .ok_or_else(|| error("hunk ends prematurely".to_string()))?;
+                i += 1;
+                let (kind, text) = match line.split_first() {
+                    // Some tools strip the trailing space of empty context lines
+                    None => (LineKind::Context, &b""[..]),
+                    Some((b' ', text)) => (LineKind::Context, text),
+                    Some((b'+', text)) => (LineKind::Added, text),
+                    Some((b'-', text)) => (LineKind::Removed, text),
+                    Some((b'\\', _)) => continue,
+                    Some(_) => {
+                        return Err(PatchError {
+                            line: i,
+                            message: "hunk ends prematurely".to_string(),
+                        });
+                    }
+                };
+                let (old, new) = match kind {
+                    LineKind::Context => (1, 1),
+                    LineKind::Added => (0, 1),
+                    LineKind::Removed => (1, 0),
+                };
+                if old > old_left || new > new_left {
+                    return Err(PatchError {
+                        line: i,
+                        message: "hunk is longer than its header says".to_string(),
+                    });
+                }
+                old_left -= old;
+                new_left -= new;
+                hunk.lines.push(DiffLine {
+                    kind,
+                    text: String::from_utf8_lossy(text).into_owned(),
+                });
+            }
+            file.hunks.push(hunk);
+            continue;
+        }
+
+        let line = raw;
+        if line.starts_with(b"diff --cc ") || line.starts_with(b"diff --combined ") {
+            return Err(error(
+                "combined diffs of merges are not supported".to_string(),
+            ));
+        }
+
+        if let Some(rest) = line.strip_prefix(b"diff --git ") {
+            // The same path on both sides has no prefix. Other prefixes,
+            // set with --src-prefix or --dst-prefix, cannot be told apart
+            // from the paths.
+            let header = parse_git_header(rest);
+            prefixes = match &header {
+                Some((old, new)) if old == new => (b"", b""),
+                Some((old, new)) if !(old.starts_with(b"a/") && new.starts_with(b"b/")) => {
+                    return Err(error(
+                        "unsupported path prefix, only the default \"a/\" and \"b/\" \
+                         or --no-prefix without renames are read"
+                            .to_string(),
+                    ));
+                }
+                _ => (b"a/", b"b/"),
+            };
+            let (old_path, new_path) = match header {
+                Some((old, new)) => (
+                    old.strip_prefix(prefixes.0).map(<[u8]>::to_vec),
+                    new.strip_prefix(prefixes.1).map(<[u8]>::to_vec),
+                ),
+                None => (None, None),
+            };
+            files.push(FilePatch {
+                old_path,
+                new_path,
+                ..Default::default()
+            });
+            git_header = true;
+            continue;
+        }
+
+        if let Some(rest) = line.strip_prefix(b"--- ")
+            && let Some(next) = lines.get(i).and_then(|l| l.strip_prefix(b"+++ "))
+        {
+            let (old_prefix, new_prefix) = if git_header {
+                (Some(prefixes.0), Some(prefixes.1))
+            } else {
+                (None, None)
+            };
+            let old_path = parse_path(rest, old_prefix).map_err(error)?;
+            let new_path = parse_path(next, new_prefix).map_err(error)?;
+            i += 1;
+
+            // Within a git diff, the paths complete the "diff --git" line,
+            // otherwise they start the diff of a new file
+            match files.last_mut() {
+                Some(file) if git_header && file.hunks.is_empty() => {
+                    file.old_path = old_path;
+                    file.new_path = new_path;
+                }
+                _ => {
+                    files.push(FilePatch {
+                        old_path,
+                        new_path,
+                        ..Default::default()
+                    });
+                    git_header = false;
+                }
+            }
+            continue;
+        }
+
+        // Extended headers of git diffs
+        let Some(file) = files.last_mut().filter(|_| git_header) else {
+            continue;
+        };
+        if let Some(path) = line
+            .strip_prefix(b"rename from ")
+            .or_else(|| line.strip_prefix(b"copy from "))
+        {
+            file.old_path = parse_path(path, None).map_err(error)?;
+        } else if let Some(path) = line
+            .strip_prefix(b"rename to ")
+            .or_else(|| line.strip_prefix(b"copy to "))
+        {
+            file.new_path = parse_path(path, None).map_err(error)?;
+        } else if line.starts_with(b"new file mode ") {
+            file.old_path = None;
+        } else if line.starts_with(b"deleted file mode ") {
+            file.new_path = None;
+        } else if line == b"GIT binary patch"
+            || (line.starts_with(b"Binary files ") && line.ends_with(b" differ"))
+        {
+            file.binary = true;
+        }
+    }
+
+    if let Some(index) = files
+        .iter()
+        .position(|file| file.old_path.is_none() && file.new_path.is_none())
+    {
+        return Err(PatchError {
+            line: 0,
+            message: format!("cannot find the paths of file #{}", index + 1),
+        });
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(files: &[FilePatch]) -> Vec<(Option<&str>, Option<&str>)> {
+        fn text(path: &Option<Vec<u8>>) -> Option<&str> {
+            path.as_deref().map(|p| std::str::from_utf8(p).unwrap())
+        }
+        files
+            .iter()
+            .map(|f| (text(&f.old_path), text(&f.new_path)))
+            .collect()
+    }
+
+    #[test]
+    fn git_diff_headers() {
+        let input = b"diff --git a/src/main.rs b/src/main.rs
+index 1111111..2222222 100644
+--- a/src/main.rs
++++ b/src/main.rs
+@@ -1,3 +1,3 @@
+ fn main() {
+-    old();
++    new();
+ }
+diff --git a/new.txt b/new.txt
+new file mode 100644
+index 0000000..3333333
+--- /dev/null
++++ b/new.txt
+@@ -0,0 +1 @@
++hello
+diff --git a/gone.txt b/gone.txt
+deleted file mode 100644
+index 3333333..0000000
+--- a/gone.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-hello
+diff --git a/old name.md b/docs/new name.md
+similarity index 90%
+rename from old name.md
+rename to docs/new name.md
+diff --git a/script.sh b/script.sh
+old mode 100644
+new mode 100755
+diff --git a/logo.png b/logo.png
+index 4444444..5555555 100644
+Binary files a/logo.png and b/logo.png differ
+diff --git \"a/caf\\303\\251 \\\"1\\\".md\" \"b/caf\\303\\251 \\\"1\\\".md\"
+index 6666666..7777777 100644
+--- \"a/caf\\303\\251 \\\"1\\\".md\"
++++ \"b/caf\\303\\251 \\\"1\\\".md\"
+@@ -1 +1 @@
+-a
++b
+";
+        let files = parse_patch(input).unwrap();
+        assert_eq!(
+            paths(&files),
+            vec![
+                (Some("src/main.rs"), Some("src/main.rs")),
+                (None, Some("new.txt")),
+                (Some("gone.txt"), None),
+                (Some("old name.md"), Some("docs/new name.md")),
+                (Some("script.sh"), Some("script.sh")),
+                (Some("logo.png"), Some("logo.png")),
+                (Some("caf\u{e9} \"1\".md"), Some("caf\u{e9} \"1\".md")),
+            ]
+        );
+        assert!(files[5].binary);
+        assert!(!files[0].binary);
+        assert_eq!(files[0].hunks[0].lines.len(), 4);
+        assert_eq!(files[0].hunks[0].lines[1].text, "    old();");
+        assert_eq!(files[0].hunks[0].lines[2].kind, LineKind::Added);
+
+        // Paths that are not valid UTF-8 are kept as bytes
+        let files =
+            parse_patch(b"diff --git \"a/caf\\351\" \"b/caf\\351\"\nnew file mode 100644\n")
+                .unwrap();
+        assert_eq!(files[0].new_path.as_deref(), Some(&b"caf\xe9"[..]));
+    }
+
+    #[test]
+    fn format_patch_and_plain_diffs() {
+        let input = b"From 0123456789abcdef Mon Sep 17 00:00:00 2001
+From: A <a@example.com>
+Subject: [PATCH] Update
+
+--- a/not/a/file.txt in the message
+---
+ README.md | 2 +-
+ 1 file changed, 1 insertion(+), 1 deletion(-)
+
+diff --git a/README.md b/README.md
+index 1111111..2222222 100644
+--- a/README.md
++++ b/README.md
+@@ -1 +1 @@
+-old
+\\ No newline at end of file
++new
+\\ No newline at end of file
+diff --git a/logo.png b/logo.png
+new file mode 100644
+index 0000000000000000000000000000000000000000..1111111111111111111111111111111111111111
+GIT binary patch
+literal 5
+McmZQzWMXCj00Bw>6951J
+
+literal 0
+HcmV?d00001
+
+--
+2.40.0
+";
+        let files = parse_patch(input).unwrap();
+        assert_eq!(
+            paths(&files),
+            vec![
+                (Some("README.md"), Some("README.md")),
+                (None, Some("logo.png"))
+            ]
+        );
+        assert!(files[1].binary);
+
+        let input = b"diff -u old.txt new.txt
+--- old.txt\t2024-01-01 00:00:00.000000000 +0000
++++ new.txt\t2024-01-02 00:00:00.000000000 +0000
+@@ -1,2 +1,2 @@
+ a
+-b
++c
+";
+        let files = parse_patch(input).unwrap();
+        assert_eq!(paths(&files), vec![(Some("old.txt"), Some("new.txt"))]);
+    }
+
+    #[test]
+    fn hunk_changes() {
+        let input = b"--- a/f
++++ b/f
+@@ -1,8 +1,7 @@
+ 1
+-2
++two
++2.5
+ 3
+
+ 5
+-6
+-7
+ 8
+@@ -20,0 +22,2 @@
++x
++y
+@@ -30 +33,0 @@
+-z
+";
+        let files = parse_patch(input).unwrap();
+        let changes: Vec<_> = files[0].hunks.iter().flat_map(|h| h.changes()).collect();
+        assert_eq!(
+            changes,
+            vec![
+                Change {
+                    old: 2..3,
+                    new: 2..4
+                },
+                Change {
+                    old: 6..8,
+                    new: 7..7
+                },
+                Change {
+                    old: 21..21,
+                    new: 22..24
+                },
+                Change {
+                    old: 30..31,
+                    new: 34..34
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rebuild_contents() {
+        let input = b"--- a/f
++++ b/f
+@@ -2,3 +2,3 @@
+ b
+-c
++C
+ d
+@@ -7,0 +8 @@
++h
+";
+        let files = parse_patch(input).unwrap();
+        let contents = files[0]
+            .contents(Some("a\nb\nC\nd\ne\nf\ng\nh\ni\n"))
+            .unwrap();
+        assert_eq!(contents.old, ["a", "b", "c", "d", "e", "f", "g", "i"]);
+        assert_eq!(contents.new, ["a", "b", "C", "d", "e", "f", "g", "h", "i"]);
+        assert!(files[0].contents(Some("a\nb\nc\nd\n")).is_err());
+        assert!(files[0].contents(Some("a\nb\nC\nd\n")).is_err());
+        assert!(files[0].contents(None).is_err());
+
+        let input = b"diff --git a/f b/f
+new file mode 100644
+--- /dev/null
++++ b/f
+@@ -0,0 +1,2 @@
++x
++y
+";
+        let files = parse_patch(input).unwrap();
+        let contents = files[0].contents(None).unwrap();
+        assert!(contents.old.is_empty());
+        assert_eq!(contents.new, ["x", "y"]);
+
+        // A hunk starting before the first line
+        let input = b"--- a/f\n+++ b/f\n@@ -1,2 +0,1 @@\n-a\n-b\n+c\n";
+        let files = parse_patch(input).unwrap();
+        assert_eq!(
+            files[0].contents(Some("c\n")).unwrap_err(),
+            "line 0 does not match the patch"
+        );
+    }
+
+    #[test]
+    fn path_prefixes() {
+        let input = b"diff --git src/f src/f\n--- src/f\n+++ src/f\n@@ -1 +1 @@\n-a\n+b\n\
+                      diff --git g g\nnew file mode 100644\n--- /dev/null\n+++ g\n@@ -0,0 +1 @@\n+x\n";
+        let files = parse_patch(input).unwrap();
+        let paths: Vec<_> = files
+            .iter()
+            .map(|f| (f.old_path.clone(), f.new_path.clone()))
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                (Some(b"src/f".to_vec()), Some(b"src/f".to_vec())),
+                (None, Some(b"g".to_vec())),
+            ]
+        );
+        for input in [
+            &b"diff --git old/f new/f\n--- old/f\n+++ new/f\n"[..],
+            b"diff --git f g\nrename from f\nrename to g\n",
+        ] {
+            let error = parse_patch(input).unwrap_err();
+            assert!(error.message.contains("unsupported path prefix"));
+        }
+    }
+
+    #[test]
+    fn invalid_patches() {
+        assert_eq!(
+            parse_patch(b"--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n a\n").unwrap_err(),
+            PatchError {
+                line: 3,
+                message: "hunk ends prematurely".to_string()
+            }
+        );
+        assert_eq!(
+            parse_patch(b"--- a/f\n+++ b/f\n@@ -1 +1 @@\n+a\n+b\n")
+                .unwrap_err()
+                .line,
+            5
+        );
+        assert!(parse_patch(b"--- a/f\n+++ b/f\n@@ -x +1 @@\n").is_err());
+        assert!(parse_patch(b"@@ -1 +1 @@\n-a\n+b\n").is_err());
+        assert!(parse_patch(b"diff --cc f\n").is_err());
+    }
+
+    #[test]
+    fn detect_patches() {
+        assert!(looks_like_patch(b"diff --git a/f b/f\n"));
+        assert!(looks_like_patch(b"--- f\n+++ f\n@@ -1 +1 @@\n"));
+        assert!(!looks_like_patch(b"src/main.rs\n--- f\n"));
+        assert!(!looks_like_patch(b"src/main.rs\nREADME.md\n"));
+    }
+}
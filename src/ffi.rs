@@ -0,0 +1,815 @@
+// C interface, declared in include/detect_changed_files.h. Handles are
+// opaque pointers released with their free function, strings are
+// NUL-terminated, and functions report failures with a status code.
+
+use crate::config::{Config, ParseErrorKind};
+use crate::detect::{self, DetectOptions, Error, Results};
+use crate::diff::{ChangedFiles, PathNormalization};
+use crate::paths;
+use crate::unicode::NormalizationForm;
+use std::ffi::{CStr, CString, c_char, c_int};
+use std::fs;
+use std::ptr;
+
+/// Outcome of a call. Configuration errors have a code of their own for
+/// each kind of `ParseError`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok = 0,
+    /// A required pointer argument is NULL
+    NullPointer = 1,
+    /// A string argument is not valid UTF-8
+    InvalidUtf8 = 2,
+    /// The configuration file could not be read
+    Io = 3,
+    /// A changed path is invalid, like one going above the repository root
+    InvalidPath = 4,
+    /// No group has the given name
+    UnknownGroup = 5,
+    /// A group has line thresholds, which need the numbers of changed lines
+    UnknownLineCounts = 6,
+    /// The groups could not be evaluated
+    DetectFailed = 7,
+    /// The configuration holds a NUL byte, which C strings cannot
+    NulByte = 8,
+    /// An argument has a value out of its range
+    InvalidArgument = 9,
+    UnclosedSectionHeader = 10,
+    EmptySectionName = 11,
+    DuplicateSection = 12,
+    ItemBeforeSection = 13,
+    UnknownOption = 14,
+    InvalidOptionValue = 15,
+    InvalidPattern = 16,
+    InvalidRegion = 17,
+    UnknownDependency = 18,
+    DependencyCycle = 19,
+    InvalidTemplate = 20,
+    InvalidSectionName = 21,
+}
+
+impl From<ParseErrorKind> for Status {
+    fn from(kind: ParseErrorKind) -> Self {
+        match kind {
+            ParseErrorKind::UnclosedSectionHeader => Status::UnclosedSectionHeader,
+            ParseErrorKind::EmptySectionName => Status::EmptySectionName,
+            ParseErrorKind::DuplicateSection => Status::DuplicateSection,
+            ParseErrorKind::ItemBeforeSection => Status::ItemBeforeSection,
+            ParseErrorKind::UnknownOption => Status::UnknownOption,
+            ParseErrorKind::InvalidOptionValue => Status::InvalidOptionValue,
+            ParseErrorKind::InvalidPattern => Status::InvalidPattern,
+            ParseErrorKind::InvalidRegion => Status::InvalidRegion,
+            ParseErrorKind::UnknownDependency => Status::UnknownDependency,
+            ParseErrorKind::DependencyCycle => Status::DependencyCycle,
+            ParseErrorKind::InvalidTemplate => Status::InvalidTemplate,
+            ParseErrorKind::InvalidSectionName => Status::InvalidSectionName,
+        }
+    }
+}
+
+/// Location of the first error of an invalid configuration
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseErrorInfo {
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based columns (in characters) of the offending span, the end
+    /// being excluded
+    pub column: usize,
+    pub end_column: usize,
+    /// Number of errors found in the configuration
+    pub count: usize,
+}
+
+/// Unicode normalization forms, as given to `dcf_config_normalize_unicode`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnicodeForm {
+    /// Canonical decomposition followed by canonical composition
+    Nfc = 1,
+    /// Canonical decomposition
+    Nfd = 2,
+}
+
+/// A parsed configuration, with the names of its groups as C strings.
+/// Template sections are listed under their declared names.
+pub struct ConfigHandle {
+    config: Config,
+    names: Vec<CString>,
+    /// Normalization form of the patterns, applied to the changed paths too
+    unicode: Option<NormalizationForm>,
+}
+
+/// Changed paths, normalized as they are added
+#[derive(Default)]
+pub struct ChangesHandle {
+    paths: Vec<Vec<u8>>,
+}
+
+/// Outcome of the check of the groups, with their names as C strings.
+/// Template sections are expanded into their groups.
+pub struct ResultsHandle {
+    results: Results,
+    names: Vec<CString>,
+}
+
+/// Names as C strings
+fn c_names<'a>(names: impl Iterator<Item = &'a str>) -> Vec<CString> {
+    // Names come from texts without NUL bytes, checked when they are passed
+    names
+        .map(|name| CString::new(name).unwrap_or_default())
+        .collect()
+}
+
+/// Borrow the bytes of a NUL-terminated string
+///
+/// # Safety
+///
+/// `text` must be NULL or point to a NUL-terminated string that outlives
+/// the returned slice.
+unsafe fn c_bytes<'a>(text: *const c_char) -> Result<&'a [u8], Status> {
+    if text.is_null() {
+        return Err(Status::NullPointer);
+    }
+    Ok(unsafe { CStr::from_ptr(text) }.to_bytes())
+}
+
+/// Borrow a NUL-terminated UTF-8 string
+///
+/// # Safety
+///
+/// Same as [`c_bytes`].
+unsafe fn c_str<'a>(text: *const c_char) -> Result<&'a str, Status> {
+    std::str::from_utf8(unsafe { c_bytes(text) }?).map_err(|_| Status::InvalidUtf8)
+}
+
+/// Parse a configuration and store its handle in `config_out`
+///
+/// # Safety
+///
+/// `config_out` must be valid for writes, and `error_out` must be NULL or
+/// valid for writes.
+unsafe fn parse_into(
+    text: &str,
+    config_out: *mut *mut ConfigHandle,
+    error_out: *mut ParseErrorInfo,
+) -> Status {
+    if text.contains('\0') {
+        return Status::NulByte;
+    }
+    match Config::parse(text) {
+        Ok(config) => {
+            let names = c_names(config.groups.iter().map(|group| group.name.as_str()));
+            let handle = Box::new(ConfigHandle {
+                config,
+                names,
+                unicode: None,
+            });
+            unsafe { *config_out = Box::into_raw(handle) };
+            Status::Ok
+        }
+        Err(errors) => {
+            let first = &errors[0];
+            if !error_out.is_null() {
+                let info = ParseErrorInfo {
+                    line: first.line,
+                    column: first.column,
+                    end_column: first.end_column,
+                    count: errors.len(),
+                };
+                unsafe { *error_out = info };
+            }
+            Status::from(first.kind)
+        }
+    }
+}
+
+/// Parse a configuration given as text. On success, `*config_out` is set to
+/// a handle to release with `dcf_config_free`. On a configuration error,
+/// the status tells the kind of the first error and `*error_out`, if not
+/// NULL, its location.
+///
+/// # Safety
+///
+/// `text` must be a NUL-terminated string, `config_out` must be valid for
+/// writes, and `error_out` must be NULL or valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dcf_config_parse(
+    text: *const c_char,
+    config_out: *mut *mut ConfigHandle,
+    error_out: *mut ParseErrorInfo,
+) -> Status {
+    if config_out.is_null() {
+        return Status::NullPointer;
+    }
+    match unsafe { c_str(text) } {
+        Ok(text) => unsafe { parse_into(text, config_out, error_out) },
+        Err(status) => status,
+    }
+}
+
+/// Read and parse a configuration file, like `dcf_config_parse`
+///
+/// # Safety
+///
+/// `path` must be a NUL-terminated string, `config_out` must be valid for
+/// writes, and `error_out` must be NULL or valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dcf_config_load(
+    path: *const c_char,
+    config_out: *mut *mut ConfigHandle,
+    error_out: *mut ParseErrorInfo,
+) -> Status {
+    if config_out.is_null() {
+        return Status::NullPointer;
+    }
+    let path = match unsafe { c_bytes(path) } {
+        Ok(path) => paths::to_path(path),
+        Err(status) => return status,
+    };
+    match fs::read_to_string(path) {
+        Ok(text) => unsafe { parse_into(&text, config_out, error_out) },
+        Err(e) if e.kind() == std::io::ErrorKind::InvalidData => Status::InvalidUtf8,
+        Err(_) => Status::Io,
+    }
+}
+
+/// Release a configuration. NULL is ignored.
+///
+/// # Safety
+///
+/// `config` must be NULL or a handle returned by `dcf_config_parse` or
+/// `dcf_config_load`, not released yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dcf_config_free(config: *mut ConfigHandle) {
+    if !config.is_null() {
+        drop(unsafe { Box::from_raw(config) });
+    }
+}
+
+/// Number of groups of a configuration, 0 if `config` is NULL
+///
+/// # Safety
+///
+/// `config` must be NULL or a valid configuration handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dcf_config_group_count(config: *const ConfigHandle) -> usize {
+    unsafe { config.as_ref() }.map_or(0, |handle| handle.names.len())
+}
+
+/// Name of a group, in declaration order. The string belongs to the
+/// configuration. Returns NULL if `config` is NULL or `index` is out of
+/// range. Template sections are listed as declared, like `svc-{name}`;
+/// the groups they expand to are listed by the results.
+///
+/// # Safety
+///
+/// `config` must be NULL or a valid configuration handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dcf_config_group_name(
+    config: *const ConfigHandle,
+    index: usize,
+) -> *const c_char {
+    unsafe { config.as_ref() }
+        .and_then(|handle| handle.names.get(index))
+        .map_or(ptr::null(), |name| name.as_ptr())
+}
+
+/// Convert the patterns of a configuration to a Unicode normalization form,
+/// a `UnicodeForm`, and the changed paths checked against it by
+/// `dcf_detect` likewise. Group names are left as written.
+///
+/// # Safety
+///
+/// `config` must be NULL or a valid configuration handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dcf_config_normalize_unicode(
+    config: *mut ConfigHandle,
+    form: c_int,
+) -> Status {
+    let Some(handle) = (unsafe { config.as_mut() }) else {
+        return Status::NullPointer;
+    };
+    let form = match form {
+        f if f == UnicodeForm::Nfc as c_int => NormalizationForm::Nfc,
+        f if f == UnicodeForm::Nfd as c_int => NormalizationForm::Nfd,
+        _ => return Status::InvalidArgument,
+    };
+    handle.config.normalize_unicode(form);
+    handle.unicode = Some(form);
+    Status::Ok
+}
+
+/// Create an empty set of changed paths, to release with
+/// `dcf_changes_free`
+#[unsafe(no_mangle)]
+pub extern "C" fn dcf_changes_new() -> *mut ChangesHandle {
+    Box::into_raw(Box::default())
+}
+
+/// Add a changed path, relative to the repository root. It need not be
+/// valid UTF-8, and is normalized lexically like the paths given to the
+/// command line tool.
+///
+/// # Safety
+///
+/// `changes` must be a valid handle and `path` a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dcf_changes_add_path(
+    changes: *mut ChangesHandle,
+    path: *const c_char,
+) -> Status {
+    let Some(handle) = (unsafe { changes.as_mut() }) else {
+        return Status::NullPointer;
+    };
+    let path = match unsafe { c_bytes(path) } {
+        Ok(path) => path,
+        Err(status) => return status,
+    };
+    match PathNormalization::default().normalize_path(path) {
+        Ok(path) => {
+            handle.paths.push(path);
+            Status::Ok
+        }
+        Err(_) => Status::InvalidPath,
+    }
+}
+
+/// Release a set of changed paths. NULL is ignored.
+///
+/// # Safety
+///
+/// `changes` must be NULL or a handle returned by `dcf_changes_new`, not
+/// released yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dcf_changes_free(changes: *mut ChangesHandle) {
+    if !changes.is_null() {
+        drop(unsafe { Box::from_raw(changes) });
+    }
+}
+
+/// Check the changed paths against the groups of the configuration. On
+/// success, `*results_out` is set to a handle to release with
+/// `dcf_results_free`. Only the paths of the changes are known, so
+/// line-range rules match on their paths, and groups with line thresholds
+/// fail with `DCF_UNKNOWN_LINE_COUNTS`. Template sections are expanded for
+/// the directories of the changed paths.
+///
+/// # Safety
+///
+/// `config` and `changes` must be valid handles, and `results_out` must be
+/// valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dcf_detect(
+    config: *const ConfigHandle,
+    changes: *const ChangesHandle,
+    case_insensitive: c_int,
+    results_out: *mut *mut ResultsHandle,
+) -> Status {
+    let (Some(config), Some(changes)) = (unsafe { config.as_ref() }, unsafe { changes.as_ref() })
+    else {
+        return Status::NullPointer;
+    };
+    if results_out.is_null() {
+        return Status::NullPointer;
+    }
+    let normalization = PathNormalization {
+        unicode: config.unicode,
+        ..PathNormalization::default()
+    };
+    let changed_files = match ChangedFiles::from_paths(&changes.paths, &normalization) {
+        Ok(files) => files,
+        Err(_) => return Status::InvalidPath,
+    };
+    let options = DetectOptions {
+        case_insensitive: case_insensitive != 0,
+        ..DetectOptions::default()
+    };
+    let mut config = config.config.clone();
+    config.expand_templates(&changed_files, None, options.case_insensitive);
+    match detect::detect(&config, &changed_files, &options) {
+        Ok(results) => {
+            let names = c_names(results.groups.iter().map(|group| group.name.as_str()));
+            let handle = Box::new(ResultsHandle { results, names });
+            unsafe { *results_out = Box::into_raw(handle) };
+            Status::Ok
+        }
+        Err(Error::UnknownLineCounts { .. }) => Status::UnknownLineCounts,
+        Err(_) => Status::DetectFailed,
+    }
+}
+
+/// Tell whether a group matched: `*matched_out` is set to 1 if it did and
+/// to 0 otherwise
+///
+/// # Safety
+///
+/// `results` must be a valid handle, `group` a NUL-terminated string and
+/// `matched_out` valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dcf_results_matched(
+    results: *const ResultsHandle,
+    group: *const c_char,
+    matched_out: *mut c_int,
+) -> Status {
+    let Some(handle) = (unsafe { results.as_ref() }) else {
+        return Status::NullPointer;
+    };
+    if matched_out.is_null() {
+        return Status::NullPointer;
+    }
+    let group = match unsafe { c_str(group) } {
+        Ok(group) => group,
+        Err(status) => return status,
+    };
+    match handle.results.get(group) {
+        Some(result) => {
+            unsafe { *matched_out = c_int::from(result.matched) };
+            Status::Ok
+        }
+        None => Status::UnknownGroup,
+    }
+}
+
+/// Number of groups of the results, templates being expanded, 0 if
+/// `results` is NULL
+///
+/// # Safety
+///
+/// `results` must be NULL or a valid results handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dcf_results_group_count(results: *const ResultsHandle) -> usize {
+    unsafe { results.as_ref() }.map_or(0, |handle| handle.names.len())
+}
+
+/// Name of a group of the results, in configuration order, the groups of a
+/// template standing in its place. The string belongs to the results.
+/// Returns NULL if `results` is NULL or `index` is out of range.
+///
+/// # Safety
+///
+/// `results` must be NULL or a valid results handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dcf_results_group_name(
+    results: *const ResultsHandle,
+    index: usize,
+) -> *const c_char {
+    unsafe { results.as_ref() }
+        .and_then(|handle| handle.names.get(index))
+        .map_or(ptr::null(), |name| name.as_ptr())
+}
+
+/// Release results. NULL is ignored.
+///
+/// # Safety
+///
+/// `results` must be NULL or a handle returned by `dcf_detect`, not
+/// released yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dcf_results_free(results: *mut ResultsHandle) {
+    if !results.is_null() {
+        drop(unsafe { Box::from_raw(results) });
+    }
+}
+
+/// Describe a status code. The string is static.
+#[unsafe(no_mangle)]
+pub extern "C" fn dcf_status_message(status: c_int) -> *const c_char {
+    let message: &CStr = match status {
+        0 => c"success",
+        1 => c"a required argument is NULL",
+        2 => c"a string is not valid UTF-8",
+        3 => c"the configuration file cannot be read",
+        4 => c"invalid path",
+        5 => c"unknown group",
+        6 => c"a group has line thresholds, but the numbers of changed lines are unknown",
+        7 => c"the groups cannot be evaluated",
+        8 => c"the configuration holds a NUL byte",
+        9 => c"an argument is out of its range",
+        10 => c"invalid section header: missing closing ']'",
+        11 => c"invalid section header: empty section name",
+        12 => c"duplicate section",
+        13 => c"item found before any section is defined",
+        14 => c"unknown section option",
+        15 => c"invalid section option value",
+        16 => c"invalid pattern",
+        17 => c"invalid region",
+        18 => c"unknown group in depends-on",
+        19 => c"dependency cycle",
+        20 => c"invalid template section",
+        21 => c"invalid section name: it looks like a character class",
+        _ => c"unknown status",
+    };
+    message.as_ptr()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &CStr) -> (Status, *mut ConfigHandle, ParseErrorInfo) {
+        let mut config = ptr::null_mut();
+        let mut error = ParseErrorInfo::default();
+        let status = unsafe { dcf_config_parse(text.as_ptr(), &mut config, &mut error) };
+        (status, config, error)
+    }
+
+    #[test]
+    fn detect_through_handles() {
+        let (status, config, _) = parse(c"[docs]\ndocs/**\n[code]\nsrc/**\n");
+        assert_eq!(status, Status::Ok);
+        unsafe {
+            assert_eq!(dcf_config_group_count(config), 2);
+            let name = CStr::from_ptr(dcf_config_group_name(config, 1));
+            assert_eq!(name, c"code");
+            assert!(dcf_config_group_name(config, 2).is_null());
+
+            let changes = dcf_changes_new();
+            assert_eq!(
+                dcf_changes_add_path(changes, c"./docs/a.md".as_ptr()),
+                Status::Ok
+            );
+            assert_eq!(
+                dcf_changes_add_path(changes, c"../a.md".as_ptr()),
+                Status::InvalidPath
+            );
+
+            let mut results = ptr::null_mut();
+            assert_eq!(dcf_detect(config, changes, 0, &mut results), Status::Ok);
+            let mut matched = -1;
+            assert_eq!(
+                dcf_results_matched(results, c"docs".as_ptr(), &mut matched),
+                Status::Ok
+            );
+            assert_eq!(matched, 1);
+            assert_eq!(
+                dcf_results_matched(results, c"code".as_ptr(), &mut matched),
+                Status::Ok
+            );
+            assert_eq!(matched, 0);
+            assert_eq!(
+                dcf_results_matched(results, c"none".as_ptr(), &mut matched),
+                Status::UnknownGroup
+            );
+
+            dcf_results_free(results);
+            dcf_changes_free(changes);
+            dcf_config_free(config);
+        }
+    }
+
+    #[test]
+    fn expand_templates() {
+        let (status, config, _) = parse(c"[svc-{name}]\nservices/{name}/**\n[docs]\ndocs/**\n");
+        assert_eq!(status, Status::Ok);
+        unsafe {
+            assert_eq!(dcf_config_group_count(config), 2);
+            let name = CStr::from_ptr(dcf_config_group_name(config, 0));
+            assert_eq!(name, c"svc-{name}");
+
+            let changes = dcf_changes_new();
+            for path in [c"services/web/a.ts", c"services/api/b.rs"] {
+                dcf_changes_add_path(changes, path.as_ptr());
+            }
+            let mut results = ptr::null_mut();
+            assert_eq!(dcf_detect(config, changes, 0, &mut results), Status::Ok);
+            assert_eq!(dcf_results_group_count(results), 3);
+            let names: Vec<&CStr> = (0..3)
+                .map(|i| CStr::from_ptr(dcf_results_group_name(results, i)))
+                .collect();
+            assert_eq!(names, [c"svc-api", c"svc-web", c"docs"]);
+            assert!(dcf_results_group_name(results, 3).is_null());
+            assert_eq!(dcf_results_group_count(ptr::null()), 0);
+
+            dcf_results_free(results);
+            dcf_changes_free(changes);
+            dcf_config_free(config);
+        }
+    }
+
+    #[test]
+    fn normalize_unicode() {
+        // "café" decomposed in the configuration, composed in the paths
+        let (_, config, _) = parse(c"[cafe\u{301}]\ncafe\u{301}/**\n");
+        unsafe {
+            assert_eq!(
+                dcf_config_normalize_unicode(config, 3),
+                Status::InvalidArgument
+            );
+            assert_eq!(
+                dcf_config_normalize_unicode(ptr::null_mut(), UnicodeForm::Nfc as c_int),
+                Status::NullPointer
+            );
+            let changes = dcf_changes_new();
+            dcf_changes_add_path(changes, c"caf\u{e9}/menu.txt".as_ptr());
+            let mut results = ptr::null_mut();
+            let group = c"cafe\u{301}";
+            let mut matched = 0;
+
+            dcf_detect(config, changes, 0, &mut results);
+            dcf_results_matched(results, group.as_ptr(), &mut matched);
+            assert_eq!(matched, 0);
+            dcf_results_free(results);
+
+            assert_eq!(
+                dcf_config_normalize_unicode(config, UnicodeForm::Nfd as c_int),
+                Status::Ok
+            );
+            assert_eq!(CStr::from_ptr(dcf_config_group_name(config, 0)), group);
+            dcf_detect(config, changes, 0, &mut results);
+            assert_eq!(
+                dcf_results_matched(results, group.as_ptr(), &mut matched),
+                Status::Ok
+            );
+            assert_eq!(matched, 1);
+
+            dcf_results_free(results);
+            dcf_changes_free(changes);
+            dcf_config_free(config);
+        }
+    }
+
+    #[test]
+    fn report_errors() {
+        let (status, config, error) = parse(c"[a]\nsrc/**\n[a]\n[b\n");
+        assert_eq!(status, Status::DuplicateSection);
+        assert!(config.is_null());
+        assert_eq!(
+            error,
+            ParseErrorInfo {
+                line: 3,
+                column: 2,
+                end_column: 3,
+                count: 2
+            }
+        );
+        assert_eq!(parse(c"[a]\nsrc/[x\n").0, Status::InvalidPattern);
+
+        unsafe {
+            let mut config = ptr::null_mut();
+            assert_eq!(
+                dcf_config_parse(ptr::null(), &mut config, ptr::null_mut()),
+                Status::NullPointer
+            );
+            assert_eq!(
+                dcf_config_load(c"/nonexistent.conf".as_ptr(), &mut config, ptr::null_mut()),
+                Status::Io
+            );
+            let path = std::env::temp_dir().join(format!(
+                "detect-changed-files-nul-{}.conf",
+                std::process::id()
+            ));
+            fs::write(&path, "[a\0b]\nsrc/**\n").unwrap();
+            let c_path = CString::new(path.to_str().unwrap()).unwrap();
+            assert_eq!(
+                dcf_config_load(c_path.as_ptr(), &mut config, ptr::null_mut()),
+                Status::NulByte
+            );
+            fs::remove_file(&path).unwrap();
+            assert_eq!(dcf_config_group_count(ptr::null()), 0);
+        }
+
+        let (_, config, _) = parse(c"[big]\nchanged-lines-above = 1\nsrc/**\n");
+        unsafe {
+            let changes = dcf_changes_new();
+            dcf_changes_add_path(changes, c"src/a.rs".as_ptr());
+            let mut results = ptr::null_mut();
+            assert_eq!(
+                dcf_detect(config, changes, 0, &mut results),
+                Status::UnknownLineCounts
+            );
+            dcf_changes_free(changes);
+            dcf_config_free(config);
+        }
+        let message = unsafe { CStr::from_ptr(dcf_status_message(12)) };
+        assert_eq!(message, c"duplicate section");
+    }
+
+    /// C type of a Rust type of the interface
+    fn c_type(rust: &str) -> String {
+        if let Some(pointee) = rust.strip_prefix("*const ") {
+            return format!("const {} *", c_type(pointee));
+        }
+        if let Some(pointee) = rust.strip_prefix("*mut ") {
+            return format!("{} *", c_type(pointee));
+        }
+        match rust {
+            "usize" => "size_t",
+            "c_int" => "int",
+            "c_char" => "char",
+            "Status" => "dcf_status",
+            "ParseErrorInfo" => "dcf_parse_error",
+            "ConfigHandle" => "dcf_config",
+            "ChangesHandle" => "dcf_changes",
+            "ResultsHandle" => "dcf_results",
+            _ => panic!("no C type for {}", rust),
+        }
+        .to_string()
+    }
+
+    /// C code without its comments and whitespace, to compare declarations
+    fn squeeze(code: &str) -> String {
+        let mut code = code.to_string();
+        while let Some(start) = code.find("/*") {
+            let end = start + code[start..].find("*/").unwrap() + 2;
+            code.replace_range(start..end, "");
+        }
+        code.split_whitespace().collect()
+    }
+
+    /// Text between `start` and the next "}"
+    fn block<'a>(text: &'a str, start: &str) -> &'a str {
+        let from = text.find(start).unwrap() + start.len();
+        &text[from..from + text[from..].find('}').unwrap()]
+    }
+
+    /// Lines of a Rust block, doc comments left out
+    fn items(block: &str) -> impl Iterator<Item = &str> {
+        block
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with("//"))
+    }
+
+    #[test]
+    fn header_matches_interface() {
+        let header = squeeze(include_str!("../include/detect_changed_files.h"));
+        let source = include_str!("ffi.rs");
+        let source = &source[..source.find("#[cfg(test)]").unwrap()];
+
+        // Enumerations, with their values and in the same order
+        for (rust, c) in [
+            ("Status", "dcf_status"),
+            ("UnicodeForm", "dcf_unicode_form"),
+        ] {
+            let mut constants = String::new();
+            for item in items(block(source, &format!("pub enum {} {{", rust))) {
+                let (name, value) = item.split_once(" = ").unwrap();
+                let mut constant = String::from("DCF");
+                for c in name.chars() {
+                    if c.is_ascii_uppercase() {
+                        constant.push('_');
+                    }
+                    constant.push(c.to_ascii_uppercase());
+                }
+                constants.push_str(&format!("{}={}", constant, value));
+                if rust == "Status" {
+                    let status = value.trim_end_matches(',').parse().unwrap();
+                    let message = unsafe { CStr::from_ptr(dcf_status_message(status)) };
+                    assert_ne!(message, c"unknown status", "{} has no message", name);
+                }
+            }
+            let declaration = block(&header, &format!("typedefenum{}{{", c));
+            assert_eq!(declaration, constants);
+        }
+
+        // Fields of the error location, with their types
+        let mut fields = String::new();
+        for item in items(block(source, "pub struct ParseErrorInfo {")) {
+            let (name, rust) = item.trim_start_matches("pub ").split_once(": ").unwrap();
+            fields.push_str(&squeeze(&format!(
+                "{} {};",
+                c_type(rust.trim_end_matches(',')),
+                name
+            )));
+        }
+        assert_eq!(block(&header, "typedefstructdcf_parse_error{"), fields);
+
+        for handle in ["ConfigHandle", "ChangesHandle", "ResultsHandle"] {
+            let name = c_type(handle);
+            let declaration = format!("typedefstruct{}{};", name, name);
+            assert!(header.contains(&declaration), "{} is not declared", name);
+        }
+
+        // Functions, with the types of their arguments and results
+        for declaration in source.split("extern \"C\" fn ").skip(1) {
+            let (name, rest) = declaration.split_once('(').unwrap();
+            let (arguments, rest) = rest.split_once(')').unwrap();
+            let result = rest[..rest.find('{').unwrap()]
+                .trim()
+                .strip_prefix("-> ")
+                .map_or("void".to_string(), c_type);
+            let arguments: Vec<String> = arguments
+                .split(',')
+                .map(str::trim)
+                .filter(|argument| !argument.is_empty())
+                .map(|argument| {
+                    let (name, rust) = argument.split_once(": ").unwrap();
+                    format!("{} {}", c_type(rust), name)
+                })
+                .collect();
+            let arguments = if arguments.is_empty() {
+                "void".to_string()
+            } else {
+                arguments.join(", ")
+            };
+            let prototype = format!("{} {}({});", result, name, arguments);
+            assert!(
+                header.contains(&squeeze(&prototype)),
+                "{} is not declared",
+                prototype
+            );
+        }
+    }
+}
@@ -0,0 +1,726 @@
+// List the changed files, by running git or by reading the repository
+
+mod index;
+mod inflate;
+mod objects;
+mod repository;
+mod sha1;
+
+use crate::diff::{self, NumstatEntry};
+use crate::paths;
+pub use objects::ObjectId;
+use repository::{Endpoints, Repository};
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+/// Which changes to list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffRange {
+    /// Changes between two commits
+    Commits {
+        /// Revision the changes are compared to
+        base: String,
+        /// Revision holding the changes
+        head: String,
+    },
+    /// Staged changes, compared to `base` or to HEAD
+    Staged {
+        /// Revision the index is compared to
+        base: Option<String>,
+    },
+    /// Changes in the working tree, compared to `base` or to the index
+    WorkingTree {
+        /// Revision the working tree is compared to
+        base: Option<String>,
+    },
+}
+
+/// How the base of a range is compared with its head
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffMode {
+    /// Compare the base directly with the head, like `git diff base..head`.
+    /// Changes made on the base branch show up as well.
+    TwoDot,
+    /// Compare the merge base of base and head with the head, like
+    /// `git diff base...head`, to only list what the head introduced
+    ThreeDot,
+}
+
+impl DiffRange {
+    /// The base of the range, and the commit it is compared to
+    fn base_and_head(&self) -> Option<(&str, &str)> {
+        match self {
+            DiffRange::Commits { base, head } => Some((base, head)),
+            DiffRange::Staged { base } | DiffRange::WorkingTree { base } => {
+                base.as_deref().map(|base| (base, "HEAD"))
+            }
+        }
+    }
+
+    /// The same range with another base
+    fn with_base(&self, new_base: String) -> DiffRange {
+        match self {
+            DiffRange::Commits { head, .. } => DiffRange::Commits {
+                base: new_base,
+                head: head.clone(),
+            },
+            DiffRange::Staged { .. } => DiffRange::Staged {
+                base: Some(new_base),
+            },
+            DiffRange::WorkingTree { .. } => DiffRange::WorkingTree {
+                base: Some(new_base),
+            },
+        }
+    }
+
+    fn diff_args(&self) -> Vec<&str> {
+        match self {
+            DiffRange::Commits { base, head } => vec![base, head],
+            DiffRange::Staged { base } => {
+                let mut args = vec!["--cached"];
+                args.extend(base.as_deref());
+                args
+            }
+            DiffRange::WorkingTree { base } => base.iter().map(|b| b.as_str()).collect(),
+        }
+    }
+
+    fn refs(&self) -> Vec<&str> {
+        match self {
+            DiffRange::Commits { base, head } => vec![base, head],
+            DiffRange::Staged { base } | DiffRange::WorkingTree { base } => {
+                base.iter().map(|b| b.as_str()).collect()
+            }
+        }
+    }
+}
+
+impl fmt::Display for DiffRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DiffRange::Commits { base, head } => write!(f, "{}..{}", base, head),
+            DiffRange::Staged { base } => {
+                write!(
+                    f,
+                    "staged changes against {}",
+                    base.as_deref().unwrap_or("HEAD")
+                )
+            }
+            DiffRange::WorkingTree { base: Some(base) } => {
+                write!(f, "working tree against {}", base)
+            }
+            DiffRange::WorkingTree { base: None } => write!(f, "working tree against the index"),
+        }
+    }
+}
+
+/// Error raised when the changes cannot be listed or read with git
+#[derive(Debug)]
+pub enum GitError {
+    /// git could not be run
+    Spawn(io::Error),
+    /// A reference does not name a commit
+    UnknownRef {
+        /// The reference as given
+        reference: String,
+        /// Whether the repository is a shallow clone, which may lack it
+        shallow: bool,
+    },
+    /// Two commits have no common ancestor
+    NoMergeBase {
+        /// Revision the changes are compared to
+        base: String,
+        /// Revision holding the changes
+        head: String,
+        /// Whether the repository is a shallow clone, which may lack the
+        /// common ancestor
+        shallow: bool,
+    },
+    /// git exited with an error
+    Failed {
+        /// The git command line
+        command: String,
+        /// What git wrote to its standard error
+        stderr: String,
+    },
+    /// The output of git could not be understood
+    InvalidOutput(String),
+    /// The repository could not be read without git
+    Repository(String),
+    /// An object is missing from the repository
+    MissingObject(ObjectId),
+}
+
+impl fmt::Display for GitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GitError::Spawn(e) => write!(f, "cannot run git: {}", e),
+            GitError::UnknownRef {
+                reference,
+                shallow: false,
+            } => write!(f, "unknown git reference '{}'", reference),
+            GitError::UnknownRef {
+                reference,
+                shallow: true,
+            } => write!(
+                f,
+                "unknown git reference '{}': the repository is a shallow clone, \
+                 fetch more history (e.g. 'fetch-depth: 0' with actions/checkout)",
+                reference
+            ),
+            GitError::NoMergeBase {
+                base,
+                head,
+                shallow: false,
+            } => write!(f, "'{}' and '{}' have no common ancestor", base, head),
+            GitError::NoMergeBase {
+                base,
+                head,
+                shallow: true,
+            } => write!(
+                f,
+                "no common ancestor found for '{}' and '{}': the repository is a \
+                 shallow clone, fetch more history (e.g. 'fetch-depth: 0' with \
+                 actions/checkout) or use --diff-mode=two-dot",
+                base, head
+            ),
+            GitError::Failed { command, stderr } => {
+                write!(f, "'{}' failed: {}", command, stderr.trim())
+            }
+            GitError::InvalidOutput(message) => write!(f, "unexpected git output: {}", message),
+            GitError::Repository(message) => write!(f, "{}", message),
+            GitError::MissingObject(id) => write!(f, "object {} is missing", id),
+        }
+    }
+}
+
+impl std::error::Error for GitError {}
+
+/// Runs git commands in a repository
+pub struct Git {
+    /// Directory to run git in, the current directory if None
+    dir: Option<PathBuf>,
+}
+
+impl Git {
+    pub fn new(dir: Option<&Path>) -> Self {
+        Git {
+            dir: dir.map(Path::to_path_buf),
+        }
+    }
+
+    /// A git command run in the repository
+    fn command(&self) -> Command {
+        let mut command = Command::new("git");
+        if let Some(dir) = &self.dir {
+            command.current_dir(dir);
+        }
+        command
+    }
+
+    /// Run git with the given arguments and return its standard output
+    fn run<S: AsRef<OsStr>>(&self, args: &[S]) -> Result<Vec<u8>, GitError> {
+        let output = self
+            .command()
+            .args(args)
+            .output()
+            .map_err(GitError::Spawn)?;
+        if !output.status.success() {
+            let args: Vec<_> = args.iter().map(|a| a.as_ref().to_string_lossy()).collect();
+            return Err(GitError::Failed {
+                command: format!("git {}", args.join(" ")),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            });
+        }
+        Ok(output.stdout)
+    }
+
+    fn is_shallow(&self) -> bool {
+        self.run(&["rev-parse", "--is-shallow-repository"])
+            .map(|out| out.starts_with(b"true"))
+            .unwrap_or(false)
+    }
+
+    /// Check that a reference names a commit
+    pub fn verify_commit(&self, reference: &str) -> Result<(), GitError> {
+        let spec = format!("{}^{{commit}}", reference);
+        match self.run(&["rev-parse", "--verify", "--quiet", &spec]) {
+            Ok(_) => Ok(()),
+            Err(GitError::Failed { .. }) => Err(GitError::UnknownRef {
+                reference: reference.to_string(),
+                shallow: self.is_shallow(),
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Find the best common ancestor of two commits
+    pub fn merge_base(&self, base: &str, head: &str) -> Result<String, GitError> {
+        match self.run(&["merge-base", base, head]) {
+            Ok(output) => Ok(String::from_utf8_lossy(&output).trim().to_string()),
+            Err(GitError::Failed { .. }) => Err(GitError::NoMergeBase {
+                base: base.to_string(),
+                head: head.to_string(),
+                shallow: self.is_shallow(),
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Check the references of a range, and replace its base with the merge
+    /// base in three-dot mode
+    fn resolve_range(&self, range: &DiffRange, mode: DiffMode) -> Result<DiffRange, GitError> {
+        for reference in range.refs() {
+            self.verify_commit(reference)?;
+        }
+        match (mode, range.base_and_head()) {
+            (DiffMode::ThreeDot, Some((base, head))) => {
+                Ok(range.with_base(self.merge_base(base, head)?))
+            }
+            _ => Ok(range.clone()),
+        }
+    }
+
+    /// List the files changed in the given range, with renames, and the
+    /// lines added and deleted in each
+    pub fn changed_files(
+        &self,
+        range: &DiffRange,
+        mode: DiffMode,
+    ) -> Result<Vec<NumstatEntry>, GitError> {
+        let range = self.resolve_range(range, mode)?;
+        let mut args = vec![
+            "diff",
+            "-z",
+            "--numstat",
+            "-M",
+            "--no-ext-diff",
+            "--no-color",
+        ];
+        args.extend(range.diff_args());
+        args.push("--");
+        let output = self.run(&args)?;
+        parse_numstat(&output)
+    }
+
+    /// Read a blob of a commit, or of the index if `rev` is ":0". None if
+    /// the path does not exist there.
+    fn read_blob(&self, rev: &str, path: &[u8]) -> Result<Option<Vec<u8>>, GitError> {
+        let mut spec = OsString::from(format!("{}:", rev));
+        spec.push(paths::to_path(path));
+        match self.run(&[OsStr::new("cat-file"), OsStr::new("blob"), &spec]) {
+            Ok(content) => Ok(Some(content)),
+            Err(GitError::Failed { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Root of the working tree
+    fn top_level(&self) -> Result<PathBuf, GitError> {
+        let top = self.run(&["rev-parse", "--show-toplevel"])?;
+        Ok(paths::to_path(top.strip_suffix(b"\n").unwrap_or(&top)))
+    }
+}
+
+/// A `git cat-file --batch` process, reading blobs one after the other
+struct BlobBatch {
+    child: Child,
+    /// Closed when the process is dropped, to let it exit
+    stdin: Option<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl BlobBatch {
+    fn spawn(git: &Git) -> Result<Self, GitError> {
+        let mut child = git
+            .command()
+            .args(["cat-file", "--batch"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(GitError::Spawn)?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(GitError::InvalidOutput(
+                "no pipe to git cat-file".to_string(),
+            ));
+        };
+        Ok(BlobBatch {
+            child,
+            stdin: Some(stdin),
+            stdout: BufReader::new(stdout),
+        })
+    }
+
+    /// Read the blob of an object name without newlines, like "HEAD:path".
+    /// None if it names no blob.
+    fn read(&mut self, name: &[u8]) -> Result<Option<Vec<u8>>, GitError> {
+        let broken = |e: io::Error| GitError::InvalidOutput(format!("git cat-file --batch: {}", e));
+        let stdin = self.stdin.as_mut().ok_or_else(|| {
+            GitError::InvalidOutput("git cat-file --batch has exited".to_string())
+        })?;
+        stdin
+            .write_all(&[name, b"\n"].concat())
+            .and_then(|()| stdin.flush())
+            .map_err(broken)?;
+
+        // "<id> <type> <size>", or the name followed by "missing" or
+        // "ambiguous"
+        let mut header = Vec::new();
+        self.stdout.read_until(b'\n', &mut header).map_err(broken)?;
+        let header = String::from_utf8_lossy(header.strip_suffix(b"\n").unwrap_or(&header));
+        let fields: Vec<&str> = header.split(' ').collect();
+        let [_, kind, size] = fields[..] else {
+            return Ok(None);
+        };
+        let Ok(size) = size.parse::<usize>() else {
+            return Ok(None);
+        };
+        // The content is followed by a newline
+        let mut content = vec![0; size + 1];
+        self.stdout.read_exact(&mut content).map_err(broken)?;
+        content.pop();
+        Ok((kind == "blob").then_some(content))
+    }
+}
+
+impl Drop for BlobBatch {
+    fn drop(&mut self) {
+        self.stdin = None;
+        let _ = self.child.wait();
+    }
+}
+
+/// How a [`VersionReader`] reads the repository
+enum Reader {
+    Command {
+        git: Git,
+        /// Root of the working tree, for ranges of working tree changes
+        top: Option<PathBuf>,
+        /// Started on the first read
+        batch: Option<BlobBatch>,
+    },
+    Builtin {
+        repository: Repository,
+        endpoints: Endpoints,
+    },
+}
+
+/// Reads the versions of the files changed in a range. The range is
+/// resolved and the repository opened once, for all the files.
+pub struct VersionReader {
+    /// The range, with the merge base as base in three-dot mode when git is
+    /// run
+    range: DiffRange,
+    reader: Reader,
+}
+
+impl VersionReader {
+    /// Open the repository containing `dir` or the current directory
+    pub fn open(
+        dir: Option<&Path>,
+        range: &DiffRange,
+        mode: DiffMode,
+        backend: Backend,
+    ) -> Result<Self, GitError> {
+        let builtin = || {
+            let repository = Repository::discover(dir.unwrap_or(Path::new(".")))?;
+            let endpoints = repository.endpoints(range, mode)?;
+            Ok(VersionReader {
+                range: range.clone(),
+                reader: Reader::Builtin {
+                    repository,
+                    endpoints,
+                },
+            })
+        };
+        let command = || {
+            let git = Git::new(dir);
+            let range = git.resolve_range(range, mode)?;
+            let top = match range {
+                DiffRange::WorkingTree { .. } => Some(git.top_level()?),
+                _ => None,
+            };
+            Ok(VersionReader {
+                range,
+                reader: Reader::Command {
+                    git,
+                    top,
+                    batch: None,
+                },
+            })
+        };
+        match backend {
+            Backend::Builtin => builtin(),
+            Backend::Command => command(),
+            Backend::Auto => match command() {
+                Err(e) if is_git_missing(&e) => builtin(),
+                result => result,
+            },
+        }
+    }
+
+    /// Read the versions of a file before and after the changes of the
+    /// range, under its old and new path
+    pub fn file_versions(
+        &mut self,
+        old_path: &[u8],
+        new_path: &[u8],
+    ) -> Result<FileVersions, GitError> {
+        let (git, top, batch) = match &mut self.reader {
+            Reader::Builtin {
+                repository,
+                endpoints,
+            } => return repository.file_versions(&self.range, *endpoints, old_path, new_path),
+            Reader::Command { git, top, batch } => (git, top, batch),
+        };
+        let mut blob = |rev: &str, path: &[u8]| {
+            // Names end with a newline in batch mode
+            if path.contains(&b'\n') {
+                return git.read_blob(rev, path);
+            }
+            let batch = match batch {
+                Some(batch) => batch,
+                None => batch.insert(BlobBatch::spawn(git)?),
+            };
+            batch.read(&[format!("{}:", rev).as_bytes(), path].concat())
+        };
+        Ok(match &self.range {
+            DiffRange::Commits { base, head } => (blob(base, old_path)?, blob(head, new_path)?),
+            DiffRange::Staged { base } => (
+                blob(base.as_deref().unwrap_or("HEAD"), old_path)?,
+                blob(":0", new_path)?,
+            ),
+            DiffRange::WorkingTree { base } => {
+                let old = blob(base.as_deref().unwrap_or(":0"), old_path)?;
+                let top = top.as_deref().unwrap_or(Path::new("."));
+                (
+                    old,
+                    read_worktree_file(&top.join(paths::to_path(new_path)))?,
+                )
+            }
+        })
+    }
+}
+
+/// Contents of a file before and after a change, None where it does not exist
+pub type FileVersions = (Option<Vec<u8>>, Option<Vec<u8>>);
+
+/// Read a file of the working tree, or the target of a symbolic link, as git
+/// stores them
+fn read_worktree_file(path: &Path) -> Result<Option<Vec<u8>>, GitError> {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return Ok(None);
+    };
+    let content = if metadata.file_type().is_symlink() {
+        std::fs::read_link(path).map(|target| paths::from_os_str(target.as_os_str()))
+    } else if metadata.is_file() {
+        std::fs::read(path)
+    } else {
+        return Ok(None);
+    };
+    content
+        .map(Some)
+        .map_err(|e| GitError::Repository(format!("cannot read '{}': {}", path.display(), e)))
+}
+
+/// How to access the repository
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// Run git, or read the repository directly if git is not installed
+    Auto,
+    /// Run git
+    Command,
+    /// Read the repository directly, without git
+    Builtin,
+}
+
+/// Whether git could not be run because it is not installed. Running it in
+/// a directory that does not exist fails the same way, so this checks that
+/// git itself cannot be found.
+fn is_git_missing(error: &GitError) -> bool {
+    let not_found = |e: &io::Error| e.kind() == io::ErrorKind::NotFound;
+    matches!(error, GitError::Spawn(e) if not_found(e))
+        && Command::new("git")
+            .arg("--version")
+            .output()
+            .is_err_and(|e| not_found(&e))
+}
+
+/// List the files changed in the given range, with renames, and the lines
+/// added and deleted in each, in the repository containing `dir` or the
+/// current directory
+pub fn changed_files(
+    dir: Option<&Path>,
+    range: &DiffRange,
+    mode: DiffMode,
+    backend: Backend,
+) -> Result<Vec<NumstatEntry>, GitError> {
+    let builtin =
+        || Repository::discover(dir.unwrap_or(Path::new(".")))?.changed_files(range, mode);
+    match backend {
+        Backend::Builtin => builtin(),
+        Backend::Command => Git::new(dir).changed_files(range, mode),
+        Backend::Auto => match Git::new(dir).changed_files(range, mode) {
+            Err(e) if is_git_missing(&e) => builtin(),
+            result => result,
+        },
+    }
+}
+
+/// Parse the output of `git diff -z --numstat`
+fn parse_numstat(output: &[u8]) -> Result<Vec<NumstatEntry>, GitError> {
+    diff::parse_numstat(output).map_err(|e| GitError::InvalidOutput(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_numstat_output() {
+        let output = b"1\t2\tsrc/main.rs\x003\t0\tdocs/new file.md\x000\t0\t\0old.rs\0new.rs\0\
+-\t-\timage.png\x000\t4\tcaf\xe9\0";
+        let entries = parse_numstat(output).unwrap();
+        let paths: Vec<_> = entries
+            .iter()
+            .map(|e| (e.old_path.as_deref(), &e.path[..]))
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                (None, &b"src/main.rs"[..]),
+                (None, b"docs/new file.md"),
+                (Some(&b"old.rs"[..]), b"new.rs"),
+                (None, b"image.png"),
+                (None, b"caf\xe9")
+            ]
+        );
+        let stats = |e: &NumstatEntry| (e.stats.added, e.stats.deleted);
+        assert_eq!(stats(&entries[0]), (1, 2));
+        assert_eq!(stats(&entries[2]), (0, 0));
+        assert_eq!(stats(&entries[3]), (0, 0));
+        assert!(parse_numstat(b"").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_numstat_invalid() {
+        assert!(parse_numstat(b"M\0path\0").is_err());
+        assert!(parse_numstat(b"0\t0\t\0only-one\0").is_err());
+    }
+
+    #[test]
+    fn diff_range_args() {
+        let range = DiffRange::Commits {
+            base: "main".to_string(),
+            head: "HEAD".to_string(),
+        };
+        assert_eq!(range.diff_args(), vec!["main", "HEAD"]);
+        let range = DiffRange::Staged { base: None };
+        assert_eq!(range.diff_args(), vec!["--cached"]);
+        let range = DiffRange::WorkingTree {
+            base: Some("v1.0".to_string()),
+        };
+        assert_eq!(range.diff_args(), vec!["v1.0"]);
+    }
+
+    #[test]
+    fn diff_range_merge_base() {
+        let range = DiffRange::Commits {
+            base: "main".to_string(),
+            head: "topic".to_string(),
+        };
+        assert_eq!(range.base_and_head(), Some(("main", "topic")));
+        assert_eq!(
+            range.with_base("abc123".to_string()).diff_args(),
+            vec!["abc123", "topic"]
+        );
+
+        let range = DiffRange::Staged {
+            base: Some("main".to_string()),
+        };
+        assert_eq!(range.base_and_head(), Some(("main", "HEAD")));
+        assert_eq!(DiffRange::WorkingTree { base: None }.base_and_head(), None);
+    }
+
+    #[test]
+    fn version_reader_command() {
+        let dir = std::env::temp_dir().join(format!(
+            "detect-changed-files-{}-{}",
+            "version-reader",
+            std::process::id()
+        ));
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .args(["-c", "user.name=A", "-c", "user.email=a@example.com"])
+                .args(args)
+                .current_dir(&dir)
+                .output()
+                .is_ok_and(|output| output.status.success())
+        };
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("src/main.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(dir.join("line\nbreak"), "old").unwrap();
+        if !git(&["init", "-q"]) {
+            std::fs::remove_dir_all(&dir).unwrap();
+            return;
+        }
+        assert!(git(&["add", "."]) && git(&["commit", "-q", "-m", "1"]));
+        std::fs::write(dir.join("line\nbreak"), "new").unwrap();
+        assert!(git(&["add", "."]));
+
+        let read = |range: DiffRange, paths: &[(&[u8], &[u8])]| {
+            let mut reader =
+                VersionReader::open(Some(&dir), &range, DiffMode::TwoDot, Backend::Command)?;
+            paths
+                .iter()
+                .map(|(old, new)| reader.file_versions(old, new))
+                .collect::<Result<Vec<_>, GitError>>()
+        };
+        let staged = read(
+            DiffRange::Staged { base: None },
+            &[
+                (b"src/main.rs", b"src/main.rs"),
+                (b"line\nbreak", b"line\nbreak"),
+                (b"src", b"src"),
+                (b"missing", b"src/main.rs"),
+            ],
+        );
+        let unknown = read(
+            DiffRange::Commits {
+                base: "HEAD".to_string(),
+                head: "nope".to_string(),
+            },
+            &[],
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let main = Some(b"fn main() {}\n".to_vec());
+        assert_eq!(
+            staged.unwrap(),
+            [
+                (main.clone(), main.clone()),
+                (Some(b"old".to_vec()), Some(b"new".to_vec())),
+                (None, None),
+                (None, main)
+            ]
+        );
+        assert!(matches!(unknown, Err(GitError::UnknownRef { .. })));
+    }
+
+    #[test]
+    fn auto_backend_missing_dir() {
+        // With git installed, a missing directory is not a reason to read
+        // the repository without it
+        if Command::new("git").arg("--version").output().is_err() {
+            return;
+        }
+        let dir = Path::new("/nonexistent/detect-changed-files");
+        let range = DiffRange::WorkingTree { base: None };
+        let result = changed_files(Some(dir), &range, DiffMode::TwoDot, Backend::Auto);
+        assert!(matches!(result, Err(GitError::Spawn(_))), "{:?}", result);
+    }
+}
@@ -0,0 +1,214 @@
+// Read the git index (versions 2 to 4)
+
+use super::GitError;
+use super::objects::ObjectId;
+use std::fs;
+use std::path::Path;
+
+/// An entry of the index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub path: Vec<u8>,
+    pub id: ObjectId,
+    pub mode: u32,
+    /// Modification time, seconds and nanoseconds
+    pub mtime: (u32, u32),
+    /// File size, truncated to 32 bits
+    pub size: u32,
+    /// Merge stage, non-zero for unresolved conflicts
+    pub stage: u8,
+}
+
+fn invalid(message: &str) -> GitError {
+    GitError::Repository(format!("invalid index: {}", message))
+}
+
+/// Read the entries of an index file. A missing index has no entries.
+pub fn read_index(path: &Path) -> Result<Vec<IndexEntry>, GitError> {
+    match fs::read(path) {
+        Ok(data) => parse_index(&data),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(GitError::Repository(format!(
+            "cannot read '{}': {}",
+            path.display(),
+            e
+        ))),
+    }
+}
+
+pub fn parse_index(data: &[u8]) -> Result<Vec<IndexEntry>, GitError> {
+    let be32 = |pos: usize| -> Result<u32, GitError> {
+        data.get(pos..pos + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(|| invalid("truncated"))
+    };
+
+    if data.len() < 12 || &data[..4] != b"DIRC" {
+        return Err(invalid("bad signature"));
+    }
+    let version = be32(4)?;
+    if !(2..=4).contains(&version) {
+        return Err(invalid("unsupported version"));
+    }
+    let count = be32(8)? as usize;
+
+    let mut entries: Vec<IndexEntry> = Vec::with_capacity(count);
+    let mut pos = 12;
+    for _ in 0..count {
+        let start = pos;
+        let mtime = (be32(pos + 8)?, be32(pos + 12)?);
+        let mode = be32(pos + 24)?;
+        let size = be32(pos + 36)?;
+        let id = ObjectId::from_bytes(data.get(pos + 40..).unwrap_or_default())
+            .ok_or_else(|| invalid("truncated"))?;
+        let flags = data
+            .get(pos + 60..pos + 62)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .ok_or_else(|| invalid("truncated"))?;
+        pos += 62;
+        if version >= 3 && flags & 0x4000 != 0 {
+            // Extended flags
+            pos += 2;
+        }
+
+        let path_bytes = if version == 4 {
+            // The path is stored as a number of bytes to remove from the
+            // previous path, followed by the suffix to append
+            let mut strip = 0usize;
+            loop {
+                let byte = *data.get(pos).ok_or_else(|| invalid("truncated"))?;
+                pos += 1;
+                strip = (strip << 7) | (byte & 0x7f) as usize;
+                if byte & 0x80 == 0 {
+                    break;
+                }
+                strip += 1;
+            }
+            let previous = entries
+                .last()
+                .map(|e| e.path.as_slice())
+                .unwrap_or_default();
+            let keep = previous
+                .len()
+                .checked_sub(strip)
+                .ok_or_else(|| invalid("bad path compression"))?;
+            let end = data[pos..]
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or_else(|| invalid("truncated"))?;
+            let mut path = previous[..keep].to_vec();
+            path.extend_from_slice(&data[pos..pos + end]);
+            pos += end + 1;
+            path
+        } else {
+            let end = data[pos..]
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or_else(|| invalid("truncated"))?;
+            let path = data[pos..pos + end].to_vec();
+            // Entries are padded with NULs to a multiple of 8 bytes
+            pos = start + (pos + end - start + 8) / 8 * 8;
+            path
+        };
+
+        entries.push(IndexEntry {
+            path: path_bytes,
+            id,
+            mode,
+            mtime,
+            size,
+            stage: ((flags >> 12) & 3) as u8,
+        });
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry_bytes(path: &str, strip: Option<u8>, stage: u16) -> Vec<u8> {
+        let mut entry = Vec::new();
+        // ctime, mtime
+        entry.extend(1u32.to_be_bytes());
+        entry.extend(0u32.to_be_bytes());
+        entry.extend(1_700_000_000u32.to_be_bytes());
+        entry.extend(42u32.to_be_bytes());
+        // dev, ino
+        entry.extend([0; 8]);
+        entry.extend(0o100644u32.to_be_bytes());
+        // uid, gid
+        entry.extend([0; 8]);
+        entry.extend(6u32.to_be_bytes());
+        entry.extend([0xab; 20]);
+        entry.extend(((stage << 12) | path.len().min(0xfff) as u16).to_be_bytes());
+        match strip {
+            Some(strip) => {
+                entry.push(strip);
+                entry.extend(path.as_bytes());
+                entry.push(0);
+            }
+            None => {
+                entry.extend(path.as_bytes());
+                let padded = (entry.len() + 8) / 8 * 8;
+                entry.resize(padded, 0);
+            }
+        }
+        entry
+    }
+
+    fn index_bytes(version: u32, entries: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = b"DIRC".to_vec();
+        data.extend(version.to_be_bytes());
+        data.extend((entries.len() as u32).to_be_bytes());
+        for entry in entries {
+            data.extend(entry);
+        }
+        // Trailing checksum, not verified
+        data.extend([0; 20]);
+        data
+    }
+
+    #[test]
+    fn parse_index_v2() {
+        let data = index_bytes(
+            2,
+            &[
+                entry_bytes("README.md", None, 0),
+                entry_bytes("src/main.rs", None, 2),
+            ],
+        );
+        let entries = parse_index(&data).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].path, b"README.md");
+        assert_eq!(entries[0].mode, 0o100644);
+        assert_eq!(entries[0].mtime, (1_700_000_000, 42));
+        assert_eq!(entries[0].size, 6);
+        assert_eq!(entries[1].path, b"src/main.rs");
+        assert_eq!(entries[1].stage, 2);
+    }
+
+    #[test]
+    fn parse_index_v4() {
+        let data = index_bytes(
+            4,
+            &[
+                entry_bytes("src/lib.rs", Some(0), 0),
+                entry_bytes("main.rs", Some(6), 0),
+            ],
+        );
+        let entries = parse_index(&data).unwrap();
+        let paths: Vec<&[u8]> = entries.iter().map(|e| e.path.as_slice()).collect();
+        assert_eq!(paths, [&b"src/lib.rs"[..], b"src/main.rs"]);
+    }
+
+    #[test]
+    fn parse_invalid_index() {
+        assert!(parse_index(b"DIRX").is_err());
+        let mut data = index_bytes(2, &[entry_bytes("a", None, 0)]);
+        data[4..8].copy_from_slice(&5u32.to_be_bytes());
+        assert!(parse_index(&data).is_err());
+        let data = index_bytes(2, &[entry_bytes("a", None, 0)]);
+        assert!(parse_index(&data[..40]).is_err());
+    }
+}
@@ -0,0 +1,363 @@
+// DEFLATE (RFC 1951) and zlib (RFC 1950) decompression
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InflateError(pub &'static str);
+
+impl fmt::Display for InflateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid compressed data: {}", self.0)
+    }
+}
+
+impl std::error::Error for InflateError {}
+
+const MAX_BITS: usize = 15;
+
+/// Base lengths and extra bits for length codes 257..285
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+/// Base distances and extra bits for distance codes 0..29
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order in which code length code lengths are stored
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Canonical Huffman code, decoded one bit at a time
+struct Huffman {
+    /// Number of codes of each length
+    counts: [u16; MAX_BITS + 1],
+    /// Symbols ordered by code
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, InflateError> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+
+        // Reject over-subscribed codes, incomplete ones are allowed
+        let mut left: i32 = 1;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err(InflateError("over-subscribed Huffman code"));
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for len in 1..=MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        counts[0] = 0;
+        Ok(Huffman { counts, symbols })
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit_buf: u32,
+    bit_count: u32,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, need: u32) -> Result<u32, InflateError> {
+        while self.bit_count < need {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or(InflateError("unexpected end of data"))?;
+            self.pos += 1;
+            self.bit_buf |= (byte as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buf & ((1u64 << need) - 1) as u32;
+        self.bit_buf >>= need;
+        self.bit_count -= need;
+        Ok(value)
+    }
+
+    fn decode(&mut self, huffman: &Huffman) -> Result<u16, InflateError> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for len in 1..=MAX_BITS {
+            code |= self.bits(1)? as i32;
+            let count = huffman.counts[len] as i32;
+            if code - count < first {
+                return Ok(huffman.symbols[(index + (code - first)) as usize]);
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+        Err(InflateError("invalid Huffman code"))
+    }
+}
+
+fn fixed_tables() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    let literal = Huffman::new(&lengths).expect("valid fixed code");
+    let distance = Huffman::new(&[5u8; 30]).expect("valid fixed code");
+    (literal, distance)
+}
+
+fn dynamic_tables(reader: &mut BitReader) -> Result<(Huffman, Huffman), InflateError> {
+    let nlen = reader.bits(5)? as usize + 257;
+    let ndist = reader.bits(5)? as usize + 1;
+    let ncode = reader.bits(4)? as usize + 4;
+    if nlen > 286 || ndist > 30 {
+        return Err(InflateError("bad counts"));
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &idx in &CODE_LENGTH_ORDER[..ncode] {
+        code_lengths[idx] = reader.bits(3)? as u8;
+    }
+    let code_huffman = Huffman::new(&code_lengths)?;
+
+    let mut lengths = vec![0u8; nlen + ndist];
+    let mut i = 0;
+    while i < nlen + ndist {
+        let symbol = reader.decode(&code_huffman)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                if i == 0 {
+                    return Err(InflateError("repeat with no previous length"));
+                }
+                (lengths[i - 1], 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if i + repeat > nlen + ndist {
+            return Err(InflateError("too many lengths"));
+        }
+        lengths[i..i + repeat].fill(value);
+        i += repeat;
+    }
+    if lengths[256] == 0 {
+        return Err(InflateError("missing end-of-block code"));
+    }
+
+    Ok((
+        Huffman::new(&lengths[..nlen])?,
+        Huffman::new(&lengths[nlen..])?,
+    ))
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    out: &mut Vec<u8>,
+    literal: &Huffman,
+    distance: &Huffman,
+) -> Result<(), InflateError> {
+    loop {
+        let symbol = reader.decode(literal)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            257..=285 => {
+                let idx = symbol - 257;
+                let len =
+                    LENGTH_BASE[idx] as usize + reader.bits(LENGTH_EXTRA[idx] as u32)? as usize;
+                let dist_symbol = reader.decode(distance)? as usize;
+                if dist_symbol >= 30 {
+                    return Err(InflateError("invalid distance symbol"));
+                }
+                let dist = DIST_BASE[dist_symbol] as usize
+                    + reader.bits(DIST_EXTRA[dist_symbol] as u32)? as usize;
+                if dist > out.len() {
+                    return Err(InflateError("distance too far back"));
+                }
+                let start = out.len() - dist;
+                for i in 0..len {
+                    out.push(out[start + i]);
+                }
+            }
+            _ => return Err(InflateError("invalid literal/length symbol")),
+        }
+    }
+}
+
+/// Decompress raw DEFLATE data. Returns the decompressed data and the number
+/// of input bytes consumed.
+pub fn inflate(data: &[u8]) -> Result<(Vec<u8>, usize), InflateError> {
+    let mut reader = BitReader {
+        data,
+        pos: 0,
+        bit_buf: 0,
+        bit_count: 0,
+    };
+    let mut out = Vec::new();
+
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                // Stored block, aligned on a byte boundary
+                reader.bit_buf = 0;
+                reader.bit_count = 0;
+                let header = data
+                    .get(reader.pos..reader.pos + 4)
+                    .ok_or(InflateError("unexpected end of data"))?;
+                let len = u16::from_le_bytes([header[0], header[1]]) as usize;
+                let nlen = u16::from_le_bytes([header[2], header[3]]) as usize;
+                if len != !nlen & 0xffff {
+                    return Err(InflateError("stored block length mismatch"));
+                }
+                reader.pos += 4;
+                let bytes = data
+                    .get(reader.pos..reader.pos + len)
+                    .ok_or(InflateError("unexpected end of data"))?;
+                out.extend_from_slice(bytes);
+                reader.pos += len;
+            }
+            1 => {
+                let (literal, distance) = fixed_tables();
+                inflate_block(&mut reader, &mut out, &literal, &distance)?;
+            }
+            2 => {
+                let (literal, distance) = dynamic_tables(&mut reader)?;
+                inflate_block(&mut reader, &mut out, &literal, &distance)?;
+            }
+            _ => return Err(InflateError("invalid block type")),
+        }
+        if last {
+            break;
+        }
+    }
+
+    Ok((out, reader.pos))
+}
+
+/// Decompress zlib data. Returns the decompressed data and the number of
+/// input bytes consumed, including the header and checksum.
+pub fn zlib_decompress(data: &[u8]) -> Result<(Vec<u8>, usize), InflateError> {
+    if data.len() < 2 {
+        return Err(InflateError("unexpected end of data"));
+    }
+    let (cmf, flg) = (data[0], data[1]);
+    if cmf & 0x0f != 8 || !(((cmf as u16) << 8) | flg as u16).is_multiple_of(31) {
+        return Err(InflateError("invalid zlib header"));
+    }
+    if flg & 0x20 != 0 {
+        return Err(InflateError("preset dictionaries are not supported"));
+    }
+
+    let (out, consumed) = inflate(&data[2..])?;
+    let checksum = data
+        .get(2 + consumed..2 + consumed + 4)
+        .ok_or(InflateError("missing checksum"))?;
+    if u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]) != adler32(&out) {
+        return Err(InflateError("checksum mismatch"));
+    }
+    Ok((out, 2 + consumed + 4))
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zlib_fixed_huffman() {
+        // zlib.compress(b"hello hello hello hello")
+        let data = [
+            0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x27, 0x01, 0x68, 0x03,
+            0x08, 0xb1,
+        ];
+        let (out, consumed) = zlib_decompress(&data).unwrap();
+        assert_eq!(out, b"hello hello hello hello");
+        assert_eq!(consumed, data.len());
+    }
+
+    #[test]
+    fn zlib_dynamic_huffman() {
+        let text = b"A DEFLATE stream with a dynamic Huffman block: the quick brown fox \
+                     jumps over the lazy dog, again and again. "
+            .repeat(4);
+        // zlib.compress(text, 9)
+        let data = [
+            0x78, 0xda, 0xed, 0xcd, 0xbb, 0x0d, 0x83, 0x30, 0x14, 0x46, 0xe1, 0x55, 0xfe, 0x01,
+            0xa2, 0x0c, 0x40, 0x67, 0x29, 0x20, 0x0a, 0xca, 0x2c, 0x70, 0xf1, 0x03, 0x0c, 0xf8,
+            0xde, 0xc4, 0xd8, 0xbc, 0xa6, 0x47, 0x22, 0x2b, 0x50, 0xa6, 0x3b, 0xd2, 0x29, 0x3e,
+            0x85, 0x57, 0x59, 0x35, 0xea, 0x5d, 0x62, 0x4e, 0xd1, 0x52, 0xc0, 0xea, 0x53, 0x0f,
+            0x82, 0xd9, 0x99, 0x82, 0xd7, 0xa8, 0xb3, 0x73, 0x81, 0x18, 0xed, 0x24, 0x7a, 0x2c,
+            0x90, 0x7a, 0x8b, 0x6f, 0xf6, 0x7a, 0x44, 0x1b, 0x65, 0x65, 0x38, 0xd9, 0x30, 0xe4,
+            0xf0, 0x99, 0x21, 0x8b, 0x8d, 0xd7, 0x9e, 0xe8, 0xd8, 0x61, 0xa4, 0x7b, 0x80, 0x3a,
+            0xf2, 0x0c, 0x62, 0xf3, 0xab, 0x27, 0xd4, 0x1f, 0xbb, 0x03, 0x3b, 0x01, 0xe8, 0xe4,
+            0x97, 0x51,
+        ];
+        let (out, _) = zlib_decompress(&data).unwrap();
+        assert_eq!(out, text);
+    }
+
+    #[test]
+    fn zlib_stored_block() {
+        // zlib.compress(b"stored", 0)
+        let data = [
+            0x78, 0x01, 0x01, 0x06, 0x00, 0xf9, 0xff, 0x73, 0x74, 0x6f, 0x72, 0x65, 0x64, 0x09,
+            0x3c, 0x02, 0x92,
+        ];
+        let (out, _) = zlib_decompress(&data).unwrap();
+        assert_eq!(out, b"stored");
+    }
+
+    #[test]
+    fn zlib_corrupt() {
+        assert!(zlib_decompress(&[0x78, 0x9c, 0xff]).is_err());
+        assert!(zlib_decompress(&[0x00, 0x00]).is_err());
+        let mut data = [
+            0x78, 0x01, 0x01, 0x06, 0x00, 0xf9, 0xff, 0x73, 0x74, 0x6f, 0x72, 0x65, 0x64, 0x09,
+            0x3c, 0x02, 0x92,
+        ];
+        data[16] = 0;
+        assert_eq!(
+            zlib_decompress(&data),
+            Err(InflateError("checksum mismatch"))
+        );
+    }
+}
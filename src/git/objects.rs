@@ -0,0 +1,474 @@
+// Read loose and packed git objects
+
+use super::GitError;
+use super::inflate::zlib_decompress;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// SHA-1 object name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObjectId(pub [u8; 20]);
+
+impl ObjectId {
+    /// Parse 40 hexadecimal digits
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        if hex.len() != 40 {
+            return None;
+        }
+        let mut id = [0u8; 20];
+        for (i, byte) in id.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(hex.get(2 * i..2 * i + 2)?, 16).ok()?;
+        }
+        Some(ObjectId(id))
+    }
+
+    /// Read the first 20 bytes of a binary object name
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(ObjectId(bytes.get(..20)?.try_into().ok()?))
+    }
+
+    /// The object name in 40 lowercase hexadecimal digits
+    pub fn to_hex(self) -> String {
+        self.0.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_hex())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectKind {
+    Commit,
+    Tree,
+    Blob,
+    Tag,
+}
+
+impl ObjectKind {
+    fn from_name(name: &[u8]) -> Option<Self> {
+        match name {
+            b"commit" => Some(ObjectKind::Commit),
+            b"tree" => Some(ObjectKind::Tree),
+            b"blob" => Some(ObjectKind::Blob),
+            b"tag" => Some(ObjectKind::Tag),
+            _ => None,
+        }
+    }
+
+    fn from_pack_type(pack_type: u8) -> Option<Self> {
+        match pack_type {
+            1 => Some(ObjectKind::Commit),
+            2 => Some(ObjectKind::Tree),
+            3 => Some(ObjectKind::Blob),
+            4 => Some(ObjectKind::Tag),
+            _ => None,
+        }
+    }
+}
+
+pub struct Object {
+    pub kind: ObjectKind,
+    pub data: Vec<u8>,
+}
+
+fn corrupt(message: String) -> GitError {
+    GitError::Repository(message)
+}
+
+/// A pack file and its version 2 index
+struct Pack {
+    path: PathBuf,
+    /// Cumulative object counts by first byte of the name
+    fanout: [u32; 256],
+    names: Vec<ObjectId>,
+    offsets: Vec<u64>,
+    /// All object offsets, sorted, to find where each object ends
+    sorted_offsets: Vec<u64>,
+    /// Offset of the trailing checksum, where the last object ends
+    end: u64,
+}
+
+impl Pack {
+    fn open(idx_path: &Path) -> Result<Self, GitError> {
+        let idx = fs::read(idx_path)
+            .map_err(|e| corrupt(format!("cannot read '{}': {}", idx_path.display(), e)))?;
+        let invalid = || corrupt(format!("invalid pack index '{}'", idx_path.display()));
+
+        if idx.len() < 8 + 256 * 4 || idx[..4] != [0xff, b't', b'O', b'c'] {
+            return Err(invalid());
+        }
+        if u32::from_be_bytes([idx[4], idx[5], idx[6], idx[7]]) != 2 {
+            return Err(corrupt(format!(
+                "unsupported pack index version in '{}'",
+                idx_path.display()
+            )));
+        }
+        let be32 = |pos: usize| -> Result<u32, GitError> {
+            idx.get(pos..pos + 4)
+                .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+                .ok_or_else(invalid)
+        };
+
+        let mut fanout = [0u32; 256];
+        for (i, count) in fanout.iter_mut().enumerate() {
+            *count = be32(8 + i * 4)?;
+        }
+        let count = fanout[255] as usize;
+        let names_pos = 8 + 256 * 4;
+        let offsets_pos = names_pos + count * 20 + count * 4;
+        let large_pos = offsets_pos + count * 4;
+
+        let mut names = Vec::with_capacity(count);
+        for i in 0..count {
+            let pos = names_pos + i * 20;
+            names.push(ObjectId::from_bytes(idx.get(pos..pos + 20).ok_or_else(invalid)?).unwrap());
+        }
+        let mut offsets = Vec::with_capacity(count);
+        for i in 0..count {
+            let offset = be32(offsets_pos + i * 4)?;
+            if offset & 0x8000_0000 != 0 {
+                let pos = large_pos + (offset & 0x7fff_ffff) as usize * 8;
+                let high = be32(pos)? as u64;
+                let low = be32(pos + 4)? as u64;
+                offsets.push((high << 32) | low);
+            } else {
+                offsets.push(offset as u64);
+            }
+        }
+
+        let path = idx_path.with_extension("pack");
+        let size = fs::metadata(&path)
+            .map_err(|e| corrupt(format!("cannot read '{}': {}", path.display(), e)))?
+            .len();
+        let mut sorted_offsets = offsets.clone();
+        sorted_offsets.sort_unstable();
+
+        Ok(Pack {
+            path,
+            fanout,
+            names,
+            offsets,
+            sorted_offsets,
+            end: size.saturating_sub(20),
+        })
+    }
+
+    /// Range of indexes of the names starting with the given byte
+    fn bucket(&self, first: u8) -> std::ops::Range<usize> {
+        let start = if first == 0 {
+            0
+        } else {
+            self.fanout[first as usize - 1] as usize
+        };
+        start..self.fanout[first as usize] as usize
+    }
+
+    fn find(&self, id: &ObjectId) -> Option<u64> {
+        let bucket = self.bucket(id.0[0]);
+        let idx = self.names[bucket.clone()].binary_search(id).ok()?;
+        Some(self.offsets[bucket.start + idx])
+    }
+
+    /// Read the raw bytes of the entry at `offset`, up to the next entry
+    fn read_entry(&self, offset: u64) -> Result<Vec<u8>, GitError> {
+        let next = match self.sorted_offsets.binary_search(&offset) {
+            Ok(idx) => self
+                .sorted_offsets
+                .get(idx + 1)
+                .copied()
+                .unwrap_or(self.end),
+            Err(_) => return Err(corrupt(format!("no object at offset {} in pack", offset))),
+        };
+        let mut file = File::open(&self.path)
+            .map_err(|e| corrupt(format!("cannot read '{}': {}", self.path.display(), e)))?;
+        let mut buf = vec![0u8; (next - offset) as usize];
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| file.read_exact(&mut buf))
+            .map_err(|e| corrupt(format!("cannot read '{}': {}", self.path.display(), e)))?;
+        Ok(buf)
+    }
+
+    /// Read and resolve the object at `offset`
+    fn read(&self, odb: &ObjectDatabase, offset: u64) -> Result<Object, GitError> {
+        let entry = self.read_entry(offset)?;
+        let invalid = || corrupt(format!("invalid entry at offset {} in pack", offset));
+
+        // Type and size header
+        let mut pos = 0;
+        let mut byte = *entry.first().ok_or_else(invalid)?;
+        let pack_type = (byte >> 4) & 7;
+        while byte & 0x80 != 0 {
+            pos += 1;
+            byte = *entry.get(pos).ok_or_else(invalid)?;
+        }
+        pos += 1;
+
+        match pack_type {
+            6 => {
+                // Delta against the object at a relative offset
+                let mut byte = *entry.get(pos).ok_or_else(invalid)?;
+                pos += 1;
+                let mut distance = (byte & 0x7f) as u64;
+                while byte & 0x80 != 0 {
+                    byte = *entry.get(pos).ok_or_else(invalid)?;
+                    pos += 1;
+                    distance = ((distance + 1) << 7) | (byte & 0x7f) as u64;
+                }
+                let base_offset = offset.checked_sub(distance).ok_or_else(invalid)?;
+                let base = self.read(odb, base_offset)?;
+                let (delta, _) =
+                    zlib_decompress(&entry[pos..]).map_err(|e| corrupt(e.to_string()))?;
+                Ok(Object {
+                    kind: base.kind,
+                    data: apply_delta(&base.data, &delta).ok_or_else(invalid)?,
+                })
+            }
+            7 => {
+                // Delta against an object given by name
+                let base_id = ObjectId::from_bytes(entry.get(pos..).ok_or_else(invalid)?)
+                    .ok_or_else(invalid)?;
+                pos += 20;
+                let base = odb.read(&base_id)?;
+                let (delta, _) =
+                    zlib_decompress(&entry[pos..]).map_err(|e| corrupt(e.to_string()))?;
+                Ok(Object {
+                    kind: base.kind,
+                    data: apply_delta(&base.data, &delta).ok_or_else(invalid)?,
+                })
+            }
+            _ => {
+                let kind = ObjectKind::from_pack_type(pack_type).ok_or_else(invalid)?;
+                let (data, _) =
+                    zlib_decompress(&entry[pos..]).map_err(|e| corrupt(e.to_string()))?;
+                Ok(Object { kind, data })
+            }
+        }
+    }
+}
+
+/// Apply a git delta to a base object
+fn apply_delta(base: &[u8], delta: &[u8]) -> Option<Vec<u8>> {
+    let mut pos = 0;
+    let varint = |pos: &mut usize| -> Option<usize> {
+        let mut value = 0usize;
+        let mut shift = 0;
+        loop {
+            let byte = *delta.get(*pos)?;
+            *pos += 1;
+            value |= ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+    };
+    let base_size = varint(&mut pos)?;
+    let result_size = varint(&mut pos)?;
+    if base_size != base.len() {
+        return None;
+    }
+
+    let mut result = Vec::with_capacity(result_size);
+    while pos < delta.len() {
+        let op = delta[pos];
+        pos += 1;
+        if op & 0x80 != 0 {
+            // Copy from the base
+            let mut offset = 0usize;
+            let mut size = 0usize;
+            for i in 0..4 {
+                if op & (1 << i) != 0 {
+                    offset |= (*delta.get(pos)? as usize) << (8 * i);
+                    pos += 1;
+                }
+            }
+            for i in 0..3 {
+                if op & (0x10 << i) != 0 {
+                    size |= (*delta.get(pos)? as usize) << (8 * i);
+                    pos += 1;
+                }
+            }
+            if size == 0 {
+                size = 0x10000;
+            }
+            result.extend_from_slice(base.get(offset..offset.checked_add(size)?)?);
+        } else if op != 0 {
+            // Insert new data
+            let size = op as usize;
+            result.extend_from_slice(delta.get(pos..pos + size)?);
+            pos += size;
+        } else {
+            return None;
+        }
+    }
+
+    if result.len() == result_size {
+        Some(result)
+    } else {
+        None
+    }
+}
+
+/// The object database of a repository: loose objects and packs, including
+/// those of alternate object directories
+pub struct ObjectDatabase {
+    dirs: Vec<PathBuf>,
+    packs: Vec<Pack>,
+}
+
+impl ObjectDatabase {
+    pub fn open(objects_dir: &Path) -> Result<Self, GitError> {
+        let mut dirs = vec![objects_dir.to_path_buf()];
+
+        // Alternates, one object directory per line, relative to this one
+        let alternates = objects_dir.join("info").join("alternates");
+        if let Ok(content) = fs::read_to_string(&alternates) {
+            for line in content.lines() {
+                let line = line.trim();
+                if !line.is_empty() && !line.starts_with('#') {
+                    dirs.push(objects_dir.join(line));
+                }
+            }
+        }
+
+        let mut packs = Vec::new();
+        for dir in &dirs {
+            let Ok(entries) = fs::read_dir(dir.join("pack")) else {
+                continue;
+            };
+            let mut idx_paths: Vec<PathBuf> = entries
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|ext| ext == "idx"))
+                .collect();
+            idx_paths.sort();
+            for idx_path in idx_paths {
+                packs.push(Pack::open(&idx_path)?);
+            }
+        }
+
+        Ok(ObjectDatabase { dirs, packs })
+    }
+
+    fn loose_path(dir: &Path, id: &ObjectId) -> PathBuf {
+        let hex = id.to_hex();
+        dir.join(&hex[..2]).join(&hex[2..])
+    }
+
+    pub fn contains(&self, id: &ObjectId) -> bool {
+        self.dirs
+            .iter()
+            .any(|dir| Self::loose_path(dir, id).is_file())
+            || self.packs.iter().any(|pack| pack.find(id).is_some())
+    }
+
+    pub fn read(&self, id: &ObjectId) -> Result<Object, GitError> {
+        for dir in &self.dirs {
+            let path = Self::loose_path(dir, id);
+            let Ok(compressed) = fs::read(&path) else {
+                continue;
+            };
+            let (raw, _) = zlib_decompress(&compressed)
+                .map_err(|e| corrupt(format!("object {}: {}", id, e)))?;
+            let invalid = || corrupt(format!("invalid loose object {}", id));
+            let header_end = raw.iter().position(|&b| b == 0).ok_or_else(invalid)?;
+            let kind_end = raw[..header_end]
+                .iter()
+                .position(|&b| b == b' ')
+                .ok_or_else(invalid)?;
+            let kind = ObjectKind::from_name(&raw[..kind_end]).ok_or_else(invalid)?;
+            return Ok(Object {
+                kind,
+                data: raw[header_end + 1..].to_vec(),
+            });
+        }
+        for pack in &self.packs {
+            if let Some(offset) = pack.find(id) {
+                return pack.read(self, offset);
+            }
+        }
+        Err(GitError::MissingObject(*id))
+    }
+
+    /// Read an object, checking its kind
+    pub fn read_kind(&self, id: &ObjectId, kind: ObjectKind) -> Result<Vec<u8>, GitError> {
+        let object = self.read(id)?;
+        if object.kind != kind {
+            return Err(corrupt(format!(
+                "object {} is a {:?}, expected a {:?}",
+                id, object.kind, kind
+            )));
+        }
+        Ok(object.data)
+    }
+
+    /// Find the objects whose name starts with the given hexadecimal prefix
+    pub fn find_prefix(&self, prefix: &str) -> Vec<ObjectId> {
+        let prefix = prefix.to_ascii_lowercase();
+        let mut found = Vec::new();
+        if prefix.len() < 2 || !prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+            return found;
+        }
+
+        for dir in &self.dirs {
+            let Ok(entries) = fs::read_dir(dir.join(&prefix[..2])) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let name = format!("{}{}", &prefix[..2], entry.file_name().to_string_lossy());
+                if name.starts_with(&prefix)
+                    && let Some(id) = ObjectId::from_hex(&name)
+                {
+                    found.push(id);
+                }
+            }
+        }
+        let first = u8::from_str_radix(&prefix[..2], 16).unwrap_or(0);
+        for pack in &self.packs {
+            found.extend(
+                pack.names[pack.bucket(first)]
+                    .iter()
+                    .filter(|id| id.to_hex().starts_with(&prefix)),
+            );
+        }
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn object_id_hex() {
+        let hex = "ce013625030ba8dba906f756967f9e9ca394464a";
+        let id = ObjectId::from_hex(hex).unwrap();
+        assert_eq!(id.to_hex(), hex);
+        assert!(ObjectId::from_hex("ce0136").is_none());
+        assert!(ObjectId::from_hex(&"g".repeat(40)).is_none());
+    }
+
+    #[test]
+    fn delta_application() {
+        let base = b"hello world, hello git";
+        // Base size 22, result size 17
+        let mut delta = vec![22, 17];
+        // Copy 6 bytes from offset 0
+        delta.extend([0x80 | 0x10, 6]);
+        // Insert "rust"
+        delta.extend([4, b'r', b'u', b's', b't']);
+        // Copy 7 bytes from offset 11
+        delta.extend([0x80 | 0x01 | 0x10, 11, 7]);
+        assert_eq!(apply_delta(base, &delta).unwrap(), b"hello rust, hello");
+
+        // Wrong base size
+        delta[0] = 21;
+        assert!(apply_delta(base, &delta).is_none());
+    }
+}
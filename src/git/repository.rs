@@ -0,0 +1,1281 @@
+// List changed files by reading the repository directly, without git
+
+use super::index::{IndexEntry, read_index};
+use super::objects::{ObjectDatabase, ObjectId, ObjectKind};
+use super::sha1::Sha1;
+use super::{DiffMode, DiffRange, FileVersions, GitError, read_worktree_file};
+use crate::diff::{self, LineStats, NumstatEntry};
+use crate::paths;
+use std::cell::{OnceCell, RefCell};
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MODE_TREE: u32 = 0o040000;
+const MODE_FILE: u32 = 0o100644;
+const MODE_EXECUTABLE: u32 = 0o100755;
+const MODE_SYMLINK: u32 = 0o120000;
+const MODE_GITLINK: u32 = 0o160000;
+
+/// Percentage of their content two files must share for an added file to
+/// be a deleted one renamed, like for `git diff -M`
+const RENAME_SIMILARITY: usize = 50;
+
+/// Most deleted or added files among which renames are searched by
+/// content, like the default `diff.renameLimit` of git
+const RENAME_LIMIT: usize = 1000;
+
+/// Mode and object of a path in a tree, the index or the working tree
+type PathState = (u32, ObjectId);
+
+/// Base and head commits of a range, None where there is no commit
+pub type Endpoints = (Option<ObjectId>, Option<ObjectId>);
+
+/// A changed path, with its state before and after the change, None where
+/// it does not exist
+type PathChange = (Vec<u8>, Option<PathState>, Option<PathState>);
+
+/// Percentage of the larger of two files made of the lines they share
+fn similarity(old: &[u8], new: &[u8]) -> usize {
+    let old_lines = diff::split_lines(old);
+    let removed: usize = diff::diff_lines(&old_lines, &diff::split_lines(new))
+        .iter()
+        .flat_map(|change| change.old.clone())
+        .map(|line| old_lines[line - 1].len() + 1)
+        .sum();
+    old.len().saturating_sub(removed) * 100 / old.len().max(new.len()).max(1)
+}
+
+/// Reduce the modes of regular files to the two git tracks
+fn canonical_mode(mode: u32) -> u32 {
+    match mode & 0o170000 {
+        0o100000 if mode & 0o111 != 0 => MODE_EXECUTABLE,
+        0o100000 => MODE_FILE,
+        _ => mode,
+    }
+}
+
+struct TreeEntry {
+    name: Vec<u8>,
+    mode: u32,
+    id: ObjectId,
+}
+
+struct Commit {
+    tree: ObjectId,
+    parents: Vec<ObjectId>,
+    time: i64,
+}
+
+pub struct Repository {
+    git_dir: PathBuf,
+    /// Directory shared by all worktrees, holding objects and most refs
+    common_dir: PathBuf,
+    work_tree: PathBuf,
+    odb: ObjectDatabase,
+    /// Commits whose parents are not available in a shallow clone
+    shallow: HashSet<ObjectId>,
+    /// Entries of the index, read once
+    index: OnceCell<Vec<IndexEntry>>,
+    /// Trees read to find files, by name of their entries
+    trees: RefCell<HashMap<ObjectId, HashMap<Vec<u8>, PathState>>>,
+}
+
+fn repository_error(message: String) -> GitError {
+    GitError::Repository(message)
+}
+
+impl Repository {
+    /// Find the repository containing `dir`
+    pub fn discover(dir: &Path) -> Result<Self, GitError> {
+        let start = fs::canonicalize(dir)
+            .map_err(|e| repository_error(format!("cannot access '{}': {}", dir.display(), e)))?;
+
+        for work_tree in start.ancestors() {
+            let dot_git = work_tree.join(".git");
+            let git_dir = if dot_git.is_dir() {
+                dot_git
+            } else if dot_git.is_file() {
+                // Worktrees and submodules use a file pointing to the git dir
+                let content = fs::read_to_string(&dot_git).unwrap_or_default();
+                match content.trim().strip_prefix("gitdir:") {
+                    Some(path) => work_tree.join(path.trim()),
+                    None => continue,
+                }
+            } else {
+                continue;
+            };
+            return Self::open(&git_dir, work_tree);
+        }
+        Err(repository_error(format!(
+            "'{}' is not in a git repository",
+            dir.display()
+        )))
+    }
+
+    fn open(git_dir: &Path, work_tree: &Path) -> Result<Self, GitError> {
+        let common_dir = match fs::read_to_string(git_dir.join("commondir")) {
+            Ok(content) => git_dir.join(content.trim()),
+            Err(_) => git_dir.to_path_buf(),
+        };
+
+        let config = fs::read_to_string(common_dir.join("config")).unwrap_or_default();
+        if config
+            .lines()
+            .any(|l| l.trim().replace(' ', "") == "objectformat=sha256")
+        {
+            return Err(repository_error(
+                "SHA-256 repositories are not supported".to_string(),
+            ));
+        }
+
+        let shallow = fs::read_to_string(common_dir.join("shallow"))
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|l| ObjectId::from_hex(l.trim()))
+            .collect();
+
+        Ok(Repository {
+            odb: ObjectDatabase::open(&common_dir.join("objects"))?,
+            git_dir: git_dir.to_path_buf(),
+            common_dir,
+            work_tree: work_tree.to_path_buf(),
+            shallow,
+            index: OnceCell::new(),
+            trees: RefCell::new(HashMap::new()),
+        })
+    }
+
+    pub fn is_shallow(&self) -> bool {
+        !self.shallow.is_empty()
+    }
+
+    /// Read a reference, following symbolic references
+    fn read_ref(&self, name: &str, depth: usize) -> Option<ObjectId> {
+        if depth > 10 {
+            return None;
+        }
+        // HEAD and the like are specific to each worktree
+        let dir = if name.contains('/') {
+            &self.common_dir
+        } else {
+            &self.git_dir
+        };
+        if let Ok(content) = fs::read_to_string(dir.join(name)) {
+            let content = content.trim();
+            return match content.strip_prefix("ref:") {
+                Some(target) => self.read_ref(target.trim(), depth + 1),
+                None => ObjectId::from_hex(content),
+            };
+        }
+
+        let packed = fs::read_to_string(self.common_dir.join("packed-refs")).ok()?;
+        packed
+            .lines()
+            .filter(|l| !l.starts_with('#') && !l.starts_with('^'))
+            .filter_map(|l| l.split_once(' '))
+            .find(|(_, ref_name)| *ref_name == name)
+            .and_then(|(hex, _)| ObjectId::from_hex(hex))
+    }
+
+    /// Resolve a name without suffix: object name, abbreviation or reference
+    fn resolve_name(&self, name: &str) -> Option<ObjectId> {
+        if let Some(id) = ObjectId::from_hex(name) {
+            return Some(id);
+        }
+        let is_all_caps = name.chars().all(|c| c.is_ascii_uppercase() || c == '_');
+        if is_all_caps && let Some(id) = self.read_ref(name, 0) {
+            return Some(id);
+        }
+        let candidates = [
+            name.to_string(),
+            format!("refs/{}", name),
+            format!("refs/tags/{}", name),
+            format!("refs/heads/{}", name),
+            format!("refs/remotes/{}", name),
+            format!("refs/remotes/{}/HEAD", name),
+        ];
+        for candidate in &candidates {
+            if candidate.starts_with("refs/")
+                && let Some(id) = self.read_ref(candidate, 0)
+            {
+                return Some(id);
+            }
+        }
+        if name.len() >= 4
+            && let [id] = self.odb.find_prefix(name)[..]
+        {
+            return Some(id);
+        }
+        None
+    }
+
+    /// Follow annotated tags to the object they point to
+    fn peel_to_commit(&self, mut id: ObjectId) -> Result<ObjectId, GitError> {
+        loop {
+            let object = self.odb.read(&id)?;
+            match object.kind {
+                ObjectKind::Commit => return Ok(id),
+                ObjectKind::Tag => {
+                    let target = object
+                        .data
+                        .split(|&b| b == b'\n')
+                        .find_map(|l| l.strip_prefix(b"object "))
+                        .and_then(|hex| ObjectId::from_hex(&String::from_utf8_lossy(hex)))
+                        .ok_or_else(|| repository_error(format!("invalid tag {}", id)))?;
+                    id = target;
+                }
+                _ => {
+                    return Err(repository_error(format!("{} is not a commit", id)));
+                }
+            }
+        }
+    }
+
+    /// Resolve a revision like "main", "HEAD~2", "v1.0^{commit}" or an
+    /// abbreviated object name to a commit
+    pub fn resolve(&self, revision: &str) -> Result<ObjectId, GitError> {
+        let unknown = || GitError::UnknownRef {
+            reference: revision.to_string(),
+            shallow: self.is_shallow(),
+        };
+        let split = revision.find(['^', '~']).unwrap_or(revision.len());
+        let (name, mut suffix) = revision.split_at(split);
+
+        let id = self.resolve_name(name).ok_or_else(unknown)?;
+        if !self.odb.contains(&id) {
+            return Err(unknown());
+        }
+        let mut id = self.peel_to_commit(id)?;
+
+        while !suffix.is_empty() {
+            if let Some(rest) = suffix.strip_prefix("^{commit}") {
+                suffix = rest;
+                continue;
+            }
+            if let Some(rest) = suffix.strip_prefix("^{}") {
+                suffix = rest;
+                continue;
+            }
+            // Other operators, like "^{tree}" or "@{1}", are not supported
+            let (op, rest) = if let Some(rest) = suffix.strip_prefix('~') {
+                ('~', rest)
+            } else if let Some(rest) = suffix.strip_prefix('^') {
+                ('^', rest)
+            } else {
+                return Err(unknown());
+            };
+            if rest.starts_with('{') {
+                return Err(unknown());
+            }
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let count: usize = if digits > 0 {
+                rest[..digits].parse().map_err(|_| unknown())?
+            } else {
+                1
+            };
+            suffix = &rest[digits..];
+
+            if op == '^' {
+                if count > 0 {
+                    let parents = self.read_commit(&id)?.parents;
+                    id = *parents.get(count - 1).ok_or_else(unknown)?;
+                }
+            } else {
+                for _ in 0..count {
+                    let parents = self.read_commit(&id)?.parents;
+                    id = *parents.first().ok_or_else(unknown)?;
+                }
+            }
+            if !self.odb.contains(&id) {
+                return Err(unknown());
+            }
+        }
+        Ok(id)
+    }
+
+    fn read_commit(&self, id: &ObjectId) -> Result<Commit, GitError> {
+        let data = self.odb.read_kind(id, ObjectKind::Commit)?;
+        let invalid = || repository_error(format!("invalid commit {}", id));
+        let mut tree = None;
+        let mut parents = Vec::new();
+        let mut time = 0;
+
+        for line in data.split(|&b| b == b'\n') {
+            if line.is_empty() {
+                break;
+            }
+            let line = String::from_utf8_lossy(line);
+            if let Some(hex) = line.strip_prefix("tree ") {
+                tree = ObjectId::from_hex(hex);
+            } else if let Some(hex) = line.strip_prefix("parent ") {
+                parents.push(ObjectId::from_hex(hex).ok_or_else(invalid)?);
+            } else if let Some(committer) = line.strip_prefix("committer ") {
+                // "Name <email> timestamp timezone"
+                time = committer
+                    .rsplit(' ')
+                    .nth(1)
+                    .and_then(|t| t.parse().ok())
+                    .unwrap_or(0);
+            }
+        }
+        if self.shallow.contains(id) {
+            parents.clear();
+        }
+        Ok(Commit {
+            tree: tree.ok_or_else(invalid)?,
+            parents,
+            time,
+        })
+    }
+
+    fn read_tree(&self, id: &ObjectId) -> Result<Vec<TreeEntry>, GitError> {
+        let data = self.odb.read_kind(id, ObjectKind::Tree)?;
+        let invalid = || repository_error(format!("invalid tree {}", id));
+        let mut entries = Vec::new();
+        let mut pos = 0;
+        while pos < data.len() {
+            let space = data[pos..]
+                .iter()
+                .position(|&b| b == b' ')
+                .ok_or_else(invalid)?;
+            let nul = data[pos..]
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or_else(invalid)?;
+            // The mode and name, then the 20 bytes of the object id
+            if space > nul || data.len() < pos + nul + 21 {
+                return Err(invalid());
+            }
+            let mode = u32::from_str_radix(&String::from_utf8_lossy(&data[pos..pos + space]), 8)
+                .map_err(|_| invalid())?;
+            let name = data[pos + space + 1..pos + nul].to_vec();
+            let id = ObjectId::from_bytes(&data[pos + nul + 1..]).ok_or_else(invalid)?;
+            entries.push(TreeEntry {
+                name,
+                mode: canonical_mode(mode),
+                id,
+            });
+            pos += nul + 21;
+        }
+        Ok(entries)
+    }
+
+    /// List every non-tree path of a tree, with its mode and object
+    fn flatten_tree(
+        &self,
+        id: &ObjectId,
+        prefix: &[u8],
+        out: &mut BTreeMap<Vec<u8>, PathState>,
+    ) -> Result<(), GitError> {
+        for entry in self.read_tree(id)? {
+            let path = [prefix, &entry.name].concat();
+            if entry.mode == MODE_TREE {
+                self.flatten_tree(&entry.id, &[&path[..], b"/"].concat(), out)?;
+            } else {
+                out.insert(path, (entry.mode, entry.id));
+            }
+        }
+        Ok(())
+    }
+
+    /// List the paths differing between two trees, skipping identical subtrees
+    fn diff_trees(
+        &self,
+        old: Option<&ObjectId>,
+        new: Option<&ObjectId>,
+        prefix: &[u8],
+        out: &mut Vec<PathChange>,
+    ) -> Result<(), GitError> {
+        let mut entries: BTreeMap<Vec<u8>, (Option<TreeEntry>, Option<TreeEntry>)> =
+            BTreeMap::new();
+        if let Some(id) = old {
+            for entry in self.read_tree(id)? {
+                let name = entry.name.clone();
+                entries.entry(name).or_default().0 = Some(entry);
+            }
+        }
+        if let Some(id) = new {
+            for entry in self.read_tree(id)? {
+                let name = entry.name.clone();
+                entries.entry(name).or_default().1 = Some(entry);
+            }
+        }
+
+        for (name, (old, new)) in entries {
+            let path = [prefix, &name].concat();
+            if let (Some(o), Some(n)) = (&old, &new)
+                && o.id == n.id
+                && o.mode == n.mode
+            {
+                continue;
+            }
+            let subtree =
+                |e: &Option<TreeEntry>| e.as_ref().filter(|e| e.mode == MODE_TREE).map(|e| e.id);
+            let blob = |e: &Option<TreeEntry>| {
+                e.as_ref()
+                    .filter(|e| e.mode != MODE_TREE)
+                    .map(|e| (e.mode, e.id))
+            };
+
+            if blob(&old).is_some() || blob(&new).is_some() {
+                out.push((path.clone(), blob(&old), blob(&new)));
+            }
+            let (old_tree, new_tree) = (subtree(&old), subtree(&new));
+            if old_tree.is_some() || new_tree.is_some() {
+                self.diff_trees(
+                    old_tree.as_ref(),
+                    new_tree.as_ref(),
+                    &[&path[..], b"/"].concat(),
+                    out,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Find the best common ancestor of two commits
+    pub fn merge_base(&self, a: &ObjectId, b: &ObjectId) -> Result<ObjectId, GitError> {
+        const PARENT1: u8 = 1;
+        const PARENT2: u8 = 2;
+        const STALE: u8 = 4;
+        if a == b {
+            return Ok(*a);
+        }
+
+        let mut flags: HashMap<ObjectId, u8> = HashMap::new();
+        let mut queue = BinaryHeap::new();
+        for (id, flag) in [(a, PARENT1), (b, PARENT2)] {
+            flags.insert(*id, flag);
+            queue.push((self.read_commit(id)?.time, *id));
+        }
+
+        // Walk the history from the most recent commits, painting commits
+        // reachable from each side, until every commit left in the queue is
+        // reachable from a common ancestor
+        while queue
+            .iter()
+            .any(|(_, id)| flags.get(id).is_some_and(|f| f & STALE == 0))
+        {
+            let Some((_, id)) = queue.pop() else { break };
+            let mut flag = flags[&id];
+            if flag & (PARENT1 | PARENT2) == PARENT1 | PARENT2 {
+                if flag & STALE == 0 {
+                    return Ok(id);
+                }
+                flag |= STALE;
+            }
+            for parent in self.read_commit(&id)?.parents {
+                let parent_flags = flags.entry(parent).or_insert(0);
+                if *parent_flags & flag == flag {
+                    continue;
+                }
+                *parent_flags |= flag;
+                queue.push((self.read_commit(&parent)?.time, parent));
+            }
+        }
+
+        Err(GitError::NoMergeBase {
+            base: a.to_hex(),
+            head: b.to_hex(),
+            shallow: self.is_shallow(),
+        })
+    }
+
+    /// Hash a file of the working tree as git would store it
+    fn hash_worktree_file(&self, path: &Path, is_symlink: bool) -> Result<ObjectId, GitError> {
+        let content = if is_symlink {
+            fs::read_link(path).map(|target| paths::from_os_str(target.as_os_str()))
+        } else {
+            fs::read(path)
+        }
+        .map_err(|e| repository_error(format!("cannot read '{}': {}", path.display(), e)))?;
+
+        let mut sha1 = Sha1::new();
+        sha1.update(format!("blob {}\0", content.len()).as_bytes());
+        sha1.update(&content);
+        Ok(ObjectId(sha1.finish()))
+    }
+
+    /// State of a tracked path in the working tree, None if it is missing
+    fn worktree_state(&self, entry: &IndexEntry) -> Result<Option<PathState>, GitError> {
+        if entry.mode == MODE_GITLINK {
+            // Submodules are not inspected
+            return Ok(Some((entry.mode, entry.id)));
+        }
+        let path = self.work_tree.join(paths::to_path(&entry.path));
+        let Ok(metadata) = fs::symlink_metadata(&path) else {
+            return Ok(None);
+        };
+        let is_symlink = metadata.file_type().is_symlink();
+        if !is_symlink && !metadata.is_file() {
+            return Ok(None);
+        }
+
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            if is_symlink {
+                MODE_SYMLINK
+            } else {
+                canonical_mode(0o100000 | metadata.permissions().mode())
+            }
+        };
+        #[cfg(not(unix))]
+        let mode = if is_symlink { MODE_SYMLINK } else { entry.mode };
+
+        // Trust the index when the file looks untouched since it was staged
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| (d.as_secs() as u32, d.subsec_nanos()));
+        let same_mtime = mtime.is_some_and(|(secs, nanos)| {
+            secs == entry.mtime.0 && (entry.mtime.1 == 0 || nanos == entry.mtime.1)
+        });
+        if same_mtime && metadata.len() as u32 == entry.size && mode == entry.mode {
+            return Ok(Some((entry.mode, entry.id)));
+        }
+
+        Ok(Some((mode, self.hash_worktree_file(&path, is_symlink)?)))
+    }
+
+    fn read_index(&self) -> Result<&[IndexEntry], GitError> {
+        if let Some(entries) = self.index.get() {
+            return Ok(entries);
+        }
+        let entries = read_index(&self.git_dir.join("index"))?;
+        Ok(self.index.get_or_init(|| entries))
+    }
+
+    /// Paths of the index and their state, conflicted paths having no state
+    fn index_states(&self) -> Result<BTreeMap<Vec<u8>, Option<PathState>>, GitError> {
+        let mut states = BTreeMap::new();
+        for entry in self.read_index()? {
+            let state = if entry.stage == 0 {
+                Some((entry.mode, entry.id))
+            } else {
+                None
+            };
+            states.insert(entry.path.clone(), state);
+        }
+        Ok(states)
+    }
+
+    /// Paths of the working tree tracked in the index and their state,
+    /// missing files being left out
+    fn worktree_states(&self) -> Result<BTreeMap<Vec<u8>, Option<PathState>>, GitError> {
+        let mut states = BTreeMap::new();
+        for entry in self.read_index()? {
+            if let Some(state) = self.worktree_state(entry)? {
+                states.insert(entry.path.clone(), Some(state));
+            }
+        }
+        Ok(states)
+    }
+
+    fn tree_states(
+        &self,
+        commit: Option<&ObjectId>,
+    ) -> Result<BTreeMap<Vec<u8>, Option<PathState>>, GitError> {
+        let mut flat = BTreeMap::new();
+        if let Some(commit) = commit {
+            self.flatten_tree(&self.read_commit(commit)?.tree, b"", &mut flat)?;
+        }
+        Ok(flat.into_iter().map(|(k, v)| (k, Some(v))).collect())
+    }
+
+    /// HEAD, or None in a repository without commits
+    fn head(&self) -> Option<ObjectId> {
+        self.resolve("HEAD").ok()
+    }
+
+    /// Resolve the base and head commits of a range. In three-dot mode, the
+    /// base is replaced with the merge base.
+    pub fn endpoints(&self, range: &DiffRange, mode: DiffMode) -> Result<Endpoints, GitError> {
+        let mut base = match range {
+            DiffRange::Commits { base, .. } => Some(self.resolve(base)?),
+            DiffRange::Staged { base } | DiffRange::WorkingTree { base } => {
+                base.as_deref().map(|b| self.resolve(b)).transpose()?
+            }
+        };
+        let head = match range {
+            DiffRange::Commits { head, .. } => Some(self.resolve(head)?),
+            _ => self.head(),
+        };
+        if mode == DiffMode::ThreeDot
+            && let (Some(b), Some(h)) = (base, head)
+        {
+            base = Some(self.merge_base(&b, &h)?);
+        }
+        Ok((base, head))
+    }
+
+    /// List the files changed in the given range, with renames, and the
+    /// lines added and deleted in each
+    pub fn changed_files(
+        &self,
+        range: &DiffRange,
+        mode: DiffMode,
+    ) -> Result<Vec<NumstatEntry>, GitError> {
+        let (base, head) = self.endpoints(range, mode)?;
+        let (old, new) = match range {
+            DiffRange::Commits { .. } => {
+                let old_tree = base
+                    .map(|b| self.read_commit(&b))
+                    .transpose()?
+                    .map(|c| c.tree);
+                let new_tree = head
+                    .map(|h| self.read_commit(&h))
+                    .transpose()?
+                    .map(|c| c.tree);
+                let mut changes = Vec::new();
+                self.diff_trees(old_tree.as_ref(), new_tree.as_ref(), b"", &mut changes)?;
+                return self.numstat(changes, Vec::new());
+            }
+            DiffRange::Staged { .. } => (
+                self.tree_states(base.or(head).as_ref())?,
+                self.index_states()?,
+            ),
+            DiffRange::WorkingTree { base: None } => {
+                (self.index_states()?, self.worktree_states()?)
+            }
+            DiffRange::WorkingTree { base: Some(_) } => {
+                (self.tree_states(base.as_ref())?, self.worktree_states()?)
+            }
+        };
+
+        let mut changes = Vec::new();
+        let mut conflicted = Vec::new();
+        let paths: BTreeSet<&Vec<u8>> = old.keys().chain(new.keys()).collect();
+        for path in paths {
+            match (old.get(path), new.get(path)) {
+                (Some(Some(o)), Some(Some(n))) if o == n => {}
+                (Some(None), _) | (_, Some(None)) => conflicted.push(path.clone()),
+                (o, n) => changes.push((path.clone(), o.copied().flatten(), n.copied().flatten())),
+            }
+        }
+        self.numstat(changes, conflicted)
+    }
+
+    /// Content of a path in a state: its blob, or the file of the working
+    /// tree when the blob is not stored, like for a file changed since it
+    /// was staged
+    fn read_state(&self, path: &[u8], (mode, id): &PathState) -> Result<Vec<u8>, GitError> {
+        if *mode == MODE_GITLINK {
+            // Like git, show a submodule as the commit it points to
+            return Ok(format!("Subproject commit {}\n", id).into_bytes());
+        }
+        if self.odb.contains(id) {
+            return self.odb.read_kind(id, ObjectKind::Blob);
+        }
+        read_worktree_file(&self.work_tree.join(paths::to_path(path)))?
+            .ok_or(GitError::MissingObject(*id))
+    }
+
+    /// Pair deleted files with added ones into renames, like `git diff -M`:
+    /// files with the same content first, then files sharing enough of
+    /// their lines. Returns the indices of the pairs in `changes`.
+    fn find_renames(
+        changes: &[PathChange],
+        contents: &[(Vec<u8>, Vec<u8>)],
+    ) -> Vec<(usize, usize)> {
+        // Like for git, submodules and empty files are not renamed
+        let is_file =
+            |state: &PathState, content: &[u8]| state.0 != MODE_GITLINK && !content.is_empty();
+        let mut deleted: Vec<usize> = (0..changes.len())
+            .filter(
+                |&i| matches!(&changes[i], (_, Some(old), None) if is_file(old, &contents[i].0)),
+            )
+            .collect();
+        let mut added: Vec<usize> = (0..changes.len())
+            .filter(
+                |&i| matches!(&changes[i], (_, None, Some(new)) if is_file(new, &contents[i].1)),
+            )
+            .collect();
+
+        let mut renames = Vec::new();
+        let mut by_id: HashMap<ObjectId, Vec<usize>> = HashMap::new();
+        for &d in deleted.iter().rev() {
+            if let (_, Some((_, id)), _) = &changes[d] {
+                by_id.entry(*id).or_default().push(d);
+            }
+        }
+        added.retain(|&a| {
+            let (_, _, Some((_, id))) = &changes[a] else {
+                return true;
+            };
+            match by_id.get_mut(id).and_then(Vec::pop) {
+                Some(d) => {
+                    renames.push((d, a));
+                    false
+                }
+                None => true,
+            }
+        });
+        deleted.retain(|d| !renames.iter().any(|(r, _)| r == d));
+
+        if deleted.len() > RENAME_LIMIT || added.len() > RENAME_LIMIT {
+            return renames;
+        }
+        let mut scores = Vec::new();
+        for &d in &deleted {
+            for &a in &added {
+                let (old, new) = (&contents[d].0, &contents[a].1);
+                if diff::is_binary(old) || diff::is_binary(new) {
+                    continue;
+                }
+                let score = similarity(old, new);
+                if score >= RENAME_SIMILARITY {
+                    scores.push((score, d, a));
+                }
+            }
+        }
+        // The most similar files are paired first
+        scores.sort_by_key(|&(score, _, _)| std::cmp::Reverse(score));
+        for (_, d, a) in scores {
+            if deleted.contains(&d) && added.contains(&a) {
+                deleted.retain(|&i| i != d);
+                added.retain(|&i| i != a);
+                renames.push((d, a));
+            }
+        }
+        renames
+    }
+
+    /// Count the lines added and deleted by the changes, like `git diff
+    /// --numstat -M`. Conflicted paths have no counts.
+    fn numstat(
+        &self,
+        changes: Vec<PathChange>,
+        conflicted: Vec<Vec<u8>>,
+    ) -> Result<Vec<NumstatEntry>, GitError> {
+        let read = |path: &[u8], state: &Option<PathState>| match state {
+            Some(state) => self.read_state(path, state),
+            None => Ok(Vec::new()),
+        };
+        let contents = changes
+            .iter()
+            .map(|(path, old, new)| Ok((read(path, old)?, read(path, new)?)))
+            .collect::<Result<Vec<_>, GitError>>()?;
+
+        let renames = Self::find_renames(&changes, &contents);
+        let mut entries: Vec<NumstatEntry> = conflicted
+            .into_iter()
+            .map(|path| NumstatEntry {
+                old_path: None,
+                path,
+                stats: LineStats::default(),
+            })
+            .collect();
+        for &(d, a) in &renames {
+            entries.push(NumstatEntry {
+                old_path: Some(changes[d].0.clone()),
+                path: changes[a].0.clone(),
+                stats: LineStats::from_versions(&contents[d].0, &contents[a].1),
+            });
+        }
+        let renamed: HashSet<usize> = renames.iter().flat_map(|&(d, a)| [d, a]).collect();
+        for (i, ((path, _, _), (old, new))) in changes.into_iter().zip(&contents).enumerate() {
+            if !renamed.contains(&i) {
+                entries.push(NumstatEntry {
+                    old_path: None,
+                    path,
+                    stats: LineStats::from_versions(old, new),
+                });
+            }
+        }
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(entries)
+    }
+
+    /// Read a file from a commit, None if it does not exist there
+    fn read_commit_file(
+        &self,
+        commit: &ObjectId,
+        path: &[u8],
+    ) -> Result<Option<Vec<u8>>, GitError> {
+        let mut tree = self.read_commit(commit)?.tree;
+        let mut components = path.split(|&b| b == b'/').peekable();
+        while let Some(name) = components.next() {
+            let Some((mode, id)) = self.tree_entry(&tree, name)? else {
+                return Ok(None);
+            };
+            match (components.peek(), mode) {
+                (Some(_), MODE_TREE) => tree = id,
+                (Some(_), _) | (None, MODE_TREE | MODE_GITLINK) => return Ok(None),
+                (None, _) => return self.odb.read_kind(&id, ObjectKind::Blob).map(Some),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Find an entry of a tree by name. The tree is kept for the next
+    /// lookups, since the files of a range share their directories.
+    fn tree_entry(&self, tree: &ObjectId, name: &[u8]) -> Result<Option<PathState>, GitError> {
+        if let Some(entries) = self.trees.borrow().get(tree) {
+            return Ok(entries.get(name).copied());
+        }
+        let entries: HashMap<Vec<u8>, PathState> = self
+            .read_tree(tree)?
+            .into_iter()
+            .map(|entry| (entry.name, (entry.mode, entry.id)))
+            .collect();
+        let entry = entries.get(name).copied();
+        self.trees.borrow_mut().insert(*tree, entries);
+        Ok(entry)
+    }
+
+    /// Read a file from the index, None if it is missing or conflicted
+    fn read_index_file(&self, path: &[u8]) -> Result<Option<Vec<u8>>, GitError> {
+        match self
+            .read_index()?
+            .iter()
+            .find(|e| e.path == path && e.stage == 0)
+        {
+            Some(entry) if entry.mode != MODE_GITLINK => {
+                self.odb.read_kind(&entry.id, ObjectKind::Blob).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Read the versions of a file before and after the changes of a range,
+    /// under its old and new path, given the endpoints of the range
+    pub fn file_versions(
+        &self,
+        range: &DiffRange,
+        (base, head): Endpoints,
+        old_path: &[u8],
+        new_path: &[u8],
+    ) -> Result<FileVersions, GitError> {
+        let commit = |commit: Option<ObjectId>, path: &[u8]| match commit {
+            Some(commit) => self.read_commit_file(&commit, path),
+            None => Ok(None),
+        };
+        let worktree = || read_worktree_file(&self.work_tree.join(paths::to_path(new_path)));
+        Ok(match range {
+            DiffRange::Commits { .. } => (commit(base, old_path)?, commit(head, new_path)?),
+            DiffRange::Staged { .. } => (
+                commit(base.or(head), old_path)?,
+                self.read_index_file(new_path)?,
+            ),
+            DiffRange::WorkingTree { base: None } => (self.read_index_file(old_path)?, worktree()?),
+            DiffRange::WorkingTree { base: Some(_) } => (commit(base, old_path)?, worktree()?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::inflate::adler32;
+    use super::*;
+
+    /// Repository written object by object in a temporary directory
+    struct TestRepo {
+        dir: PathBuf,
+        time: i64,
+    }
+
+    /// Compress with zlib using stored blocks
+    fn zlib_store(data: &[u8]) -> Vec<u8> {
+        let mut out = vec![0x78, 0x01];
+        let chunks: Vec<&[u8]> = data.chunks(0xffff).collect();
+        if chunks.is_empty() {
+            out.extend([1, 0, 0, 0xff, 0xff]);
+        }
+        for (i, chunk) in chunks.iter().enumerate() {
+            out.push((i + 1 == chunks.len()) as u8);
+            out.extend((chunk.len() as u16).to_le_bytes());
+            out.extend((!(chunk.len() as u16)).to_le_bytes());
+            out.extend(*chunk);
+        }
+        out.extend(adler32(data).to_be_bytes());
+        out
+    }
+
+    impl TestRepo {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "detect-changed-files-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(dir.join(".git/objects")).unwrap();
+            fs::create_dir_all(dir.join(".git/refs/heads")).unwrap();
+            fs::write(dir.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+            TestRepo {
+                dir,
+                time: 1_700_000_000,
+            }
+        }
+
+        fn write_object(&self, kind: &str, data: &[u8]) -> ObjectId {
+            let mut raw = format!("{} {}\0", kind, data.len()).into_bytes();
+            raw.extend(data);
+            let mut sha1 = Sha1::new();
+            sha1.update(&raw);
+            let id = ObjectId(sha1.finish());
+            let hex = id.to_hex();
+            let dir = self.dir.join(".git/objects").join(&hex[..2]);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join(&hex[2..]), zlib_store(&raw)).unwrap();
+            id
+        }
+
+        /// Write a tree from paths and contents, creating subtrees
+        fn write_tree(&self, files: &[(&str, &str)]) -> ObjectId {
+            let mut blobs: BTreeMap<String, ObjectId> = BTreeMap::new();
+            let mut dirs: BTreeMap<String, Vec<(&str, &str)>> = BTreeMap::new();
+            for (path, content) in files {
+                match path.split_once('/') {
+                    Some((dir, rest)) => dirs
+                        .entry(dir.to_string())
+                        .or_default()
+                        .push((rest, content)),
+                    None => {
+                        blobs.insert(
+                            path.to_string(),
+                            self.write_object("blob", content.as_bytes()),
+                        );
+                    }
+                }
+            }
+            // git sorts tree entries by name, directories as if ending with "/"
+            let mut entries: Vec<(String, &str, ObjectId)> = Vec::new();
+            for (name, id) in blobs {
+                entries.push((name, "100644", id));
+            }
+            for (name, files) in dirs {
+                entries.push((format!("{}/", name), "40000", self.write_tree(&files)));
+            }
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+            let mut data = Vec::new();
+            for (name, mode, id) in entries {
+                data.extend(format!("{} {}\0", mode, name.trim_end_matches('/')).as_bytes());
+                data.extend(id.0);
+            }
+            self.write_object("tree", &data)
+        }
+
+        fn commit(&mut self, files: &[(&str, &str)], parents: &[ObjectId]) -> ObjectId {
+            self.time += 60;
+            let mut data = format!("tree {}\n", self.write_tree(files));
+            for parent in parents {
+                data.push_str(&format!("parent {}\n", parent));
+            }
+            data.push_str(&format!(
+                "author A <a@example.com> {} +0000\ncommitter A <a@example.com> {} +0000\n\nmessage\n",
+                self.time, self.time
+            ));
+            self.write_object("commit", data.as_bytes())
+        }
+
+        fn set_ref(&self, name: &str, id: &ObjectId) {
+            let path = self.dir.join(".git").join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, format!("{}\n", id)).unwrap();
+        }
+
+        fn open(&self) -> Repository {
+            Repository::discover(&self.dir.join("sub")).unwrap()
+        }
+    }
+
+    impl Drop for TestRepo {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn commits(base: &str, head: &str) -> DiffRange {
+        DiffRange::Commits {
+            base: base.to_string(),
+            head: head.to_string(),
+        }
+    }
+
+    /// History: c1 - c2 - c3 on main, c1 - t1 on topic
+    fn history(name: &str) -> (TestRepo, [ObjectId; 4]) {
+        let mut repo = TestRepo::new(name);
+        fs::create_dir_all(repo.dir.join("sub")).unwrap();
+        let c1 = repo.commit(
+            &[("README.md", "hello"), ("src/main.rs", "fn main() {}")],
+            &[],
+        );
+        let c2 = repo.commit(
+            &[
+                ("README.md", "hello world"),
+                ("src/main.rs", "fn main() {}"),
+            ],
+            &[c1],
+        );
+        let c3 = repo.commit(
+            &[
+                ("README.md", "hello world"),
+                ("src/main.rs", "fn main() {}"),
+                ("src/lib/mod.rs", "// lib"),
+            ],
+            &[c2],
+        );
+        let t1 = repo.commit(
+            &[
+                ("README.md", "hello"),
+                ("src/main.rs", "fn main() { run() }"),
+            ],
+            &[c1],
+        );
+        repo.set_ref("refs/heads/main", &c3);
+        repo.set_ref("refs/heads/topic", &t1);
+        (repo, [c1, c2, c3, t1])
+    }
+
+    fn versions(
+        repository: &Repository,
+        range: &DiffRange,
+        mode: DiffMode,
+        old_path: &[u8],
+        new_path: &[u8],
+    ) -> FileVersions {
+        let endpoints = repository.endpoints(range, mode).unwrap();
+        repository
+            .file_versions(range, endpoints, old_path, new_path)
+            .unwrap()
+    }
+
+    fn changed_paths(repository: &Repository, range: &DiffRange, mode: DiffMode) -> Vec<Vec<u8>> {
+        let entries = repository.changed_files(range, mode).unwrap();
+        entries.into_iter().map(|entry| entry.path).collect()
+    }
+
+    #[test]
+    fn resolve_revisions() {
+        let (repo, [c1, c2, c3, t1]) = history("resolve");
+        let repository = repo.open();
+        assert_eq!(repository.resolve("HEAD").unwrap(), c3);
+        assert_eq!(repository.resolve("main~2").unwrap(), c1);
+        assert_eq!(repository.resolve("HEAD^").unwrap(), c2);
+        assert_eq!(repository.resolve("HEAD^^{commit}").unwrap(), c2);
+        assert_eq!(repository.resolve("refs/heads/topic").unwrap(), t1);
+        assert_eq!(repository.resolve(&t1.to_hex()[..8]).unwrap(), t1);
+        assert!(matches!(
+            repository.resolve("nope"),
+            Err(GitError::UnknownRef { shallow: false, .. })
+        ));
+        assert!(repository.resolve("main~3").is_err());
+        for revision in ["HEAD~é", "HEAD^é", "HEAD^{tree}", "HEAD@{1}", "HEAD~1x"] {
+            assert!(
+                matches!(
+                    repository.resolve(revision),
+                    Err(GitError::UnknownRef { .. })
+                ),
+                "{}",
+                revision
+            );
+        }
+    }
+
+    #[test]
+    fn corrupt_tree() {
+        let repo = TestRepo::new("corrupt-tree");
+        fs::create_dir_all(repo.dir.join("sub")).unwrap();
+        let repository = repo.open();
+        // The NUL before the space, and an object id cut short
+        for data in [&b"100644\0a b"[..], b"100644 a\0\x01\x02"] {
+            let id = repo.write_object("tree", data);
+            assert!(matches!(
+                repository.read_tree(&id),
+                Err(GitError::Repository(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn packed_refs_and_tags() {
+        let (repo, [c1, _, c3, _]) = history("packed");
+        let tag = repo.write_object(
+            "tag",
+            format!(
+                "object {}\ntype commit\ntag v1\ntagger A <a@example.com> 0 +0000\n\nv1\n",
+                c1
+            )
+            .as_bytes(),
+        );
+        fs::write(
+            repo.dir.join(".git/packed-refs"),
+            format!(
+                "# pack-refs with: peeled fully-peeled sorted\n{} refs/remotes/origin/main\n{} refs/tags/v1\n^{}\n",
+                c3, tag, c1
+            ),
+        )
+        .unwrap();
+        let repository = repo.open();
+        assert_eq!(repository.resolve("origin/main").unwrap(), c3);
+        assert_eq!(repository.resolve("v1").unwrap(), c1);
+    }
+
+    #[test]
+    fn changed_files_between_commits() {
+        let (repo, _) = history("commits");
+        let repository = repo.open();
+        assert_eq!(
+            changed_paths(&repository, &commits("main~2", "main"), DiffMode::TwoDot),
+            [&b"README.md"[..], b"src/lib/mod.rs"]
+        );
+        assert_eq!(
+            changed_paths(&repository, &commits("main", "main~2"), DiffMode::TwoDot),
+            [&b"README.md"[..], b"src/lib/mod.rs"]
+        );
+    }
+
+    #[test]
+    fn changed_files_three_dot() {
+        let (repo, [c1, _, c3, t1]) = history("three-dot");
+        let repository = repo.open();
+        assert_eq!(repository.merge_base(&c3, &t1).unwrap(), c1);
+        assert_eq!(
+            changed_paths(&repository, &commits("main", "topic"), DiffMode::TwoDot),
+            [&b"README.md"[..], b"src/lib/mod.rs", b"src/main.rs"]
+        );
+        assert_eq!(
+            changed_paths(&repository, &commits("main", "topic"), DiffMode::ThreeDot),
+            [b"src/main.rs"]
+        );
+    }
+
+    #[test]
+    fn changed_files_with_renames() {
+        let mut repo = TestRepo::new("renames");
+        fs::create_dir_all(repo.dir.join("sub")).unwrap();
+        let long = "fn main() {\n    let a = 1;\n    let b = 2;\n    println!(\"{}\", a + b);\n}\n";
+        let c1 = repo.commit(
+            &[
+                ("docs/guide.md", "line 1\nline 2\nline 3\n"),
+                ("src/main.rs", long),
+                ("empty", ""),
+            ],
+            &[],
+        );
+        let c2 = repo.commit(
+            &[
+                ("guide.md", "line 1\nline 2\nline 3\n"),
+                ("src/bin/main.rs", &long.replace("2;", "3;")),
+                ("src/other.rs", "// other\n"),
+                ("empty2", ""),
+            ],
+            &[c1],
+        );
+        repo.set_ref("refs/heads/main", &c2);
+        let repository = repo.open();
+        let entries = repository
+            .changed_files(&commits("main~1", "main"), DiffMode::TwoDot)
+            .unwrap();
+        let summary: Vec<_> = entries
+            .iter()
+            .map(|e| {
+                let old = e.old_path.as_deref().map(String::from_utf8_lossy);
+                let path = String::from_utf8_lossy(&e.path);
+                (old, path, e.stats.added, e.stats.deleted)
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (None, "empty".into(), 0, 0),
+                (None, "empty2".into(), 0, 0),
+                (Some("docs/guide.md".into()), "guide.md".into(), 0, 0),
+                (Some("src/main.rs".into()), "src/bin/main.rs".into(), 1, 1),
+                (None, "src/other.rs".into(), 1, 0),
+            ]
+        );
+        assert_eq!(
+            versions(
+                &repository,
+                &commits("main~1", "main"),
+                DiffMode::TwoDot,
+                b"docs/guide.md",
+                b"guide.md"
+            ),
+            (
+                Some(b"line 1\nline 2\nline 3\n".to_vec()),
+                Some(b"line 1\nline 2\nline 3\n".to_vec())
+            )
+        );
+    }
+
+    #[test]
+    fn file_versions_between_commits() {
+        let (repo, _) = history("versions");
+        let repository = repo.open();
+        assert_eq!(
+            versions(
+                &repository,
+                &commits("main~2", "main"),
+                DiffMode::TwoDot,
+                b"README.md",
+                b"README.md"
+            ),
+            (Some(b"hello".to_vec()), Some(b"hello world".to_vec()))
+        );
+        assert_eq!(
+            versions(
+                &repository,
+                &commits("main", "topic"),
+                DiffMode::ThreeDot,
+                b"src/lib/mod.rs",
+                b"src/lib/mod.rs"
+            ),
+            (None, None)
+        );
+        assert_eq!(
+            versions(
+                &repository,
+                &commits("topic", "main"),
+                DiffMode::TwoDot,
+                b"src/lib/mod.rs",
+                b"src/lib/mod.rs"
+            ),
+            (None, Some(b"// lib".to_vec()))
+        );
+        assert_eq!(
+            versions(
+                &repository,
+                &commits("main", "topic"),
+                DiffMode::TwoDot,
+                b"src",
+                b"src"
+            ),
+            (None, None)
+        );
+    }
+
+    #[test]
+    fn unrelated_histories() {
+        let (mut repo, [_, _, c3, _]) = history("unrelated");
+        let orphan = repo.commit(&[("other", "x")], &[]);
+        let repository = repo.open();
+        assert!(matches!(
+            repository.merge_base(&c3, &orphan),
+            Err(GitError::NoMergeBase { shallow: false, .. })
+        ));
+    }
+
+    #[test]
+    fn shallow_history() {
+        let (repo, [_, c2, _, _]) = history("shallow");
+        fs::write(repo.dir.join(".git/shallow"), format!("{}\n", c2)).unwrap();
+        let repository = repo.open();
+        assert!(matches!(
+            repository.resolve("main~2"),
+            Err(GitError::UnknownRef { shallow: true, .. })
+        ));
+        assert!(matches!(
+            repository.changed_files(&commits("topic", "main"), DiffMode::ThreeDot),
+            Err(GitError::NoMergeBase { shallow: true, .. })
+        ));
+    }
+}
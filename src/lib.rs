@@ -0,0 +1,50 @@
+//! Detect which groups of files are affected by a set of changes.
+//!
+//! A [`Config`] declares groups of glob patterns, each a [`PatternSet`].
+//! The [`ChangedFiles`] are read from a list of paths, a patch, a numstat
+//! listing or git, and [`detect`] tells which groups they affect:
+//!
+//! ```
+//! use detect_changed_files::{ChangedFiles, Config, DetectOptions, PathNormalization, detect};
+//!
+//! let config = Config::parse("[docs]\ndocs/**\n[code]\nsrc/**\n").unwrap();
+//! let changed_files =
+//!     ChangedFiles::from_paths(["docs/index.md"], &PathNormalization::default()).unwrap();
+//! let results = detect(&config, &changed_files, &DetectOptions::default()).unwrap();
+//! assert!(results.matched("docs"));
+//! assert!(!results.matched("code"));
+//! ```
+
+mod ci;
+mod comments;
+mod config;
+mod detect;
+mod diff;
+mod ffi;
+mod git;
+mod json;
+mod matching;
+mod paths;
+mod regex;
+mod region;
+mod template;
+mod toml;
+mod unicode;
+#[cfg(any(target_arch = "wasm32", test))]
+mod wasm;
+mod workspace;
+
+pub use ci::{CiError, DetectedRange, detect_range};
+pub use config::{Config, Group, ParseError, ParseErrorKind, PatternSet, Rule, parse_config};
+pub use detect::{DetectOptions, Error, GroupResult, OutputOrder, Results, detect};
+pub use diff::{
+    ChangedFile, ChangedFiles, InputFormat, LineStats, NumstatError, PatchError, PathError,
+    PathNormalization,
+};
+pub use git::{Backend, DiffMode, DiffRange, GitError, ObjectId};
+pub use json::JsonError;
+pub use matching::{MatchPath, Pattern, PatternError, PatternErrorKind};
+pub use regex::{Regex, RegexError};
+pub use region::{Region, RegionError};
+pub use unicode::NormalizationForm;
+pub use workspace::{Ecosystem, Package, Workspace, WorkspaceError};
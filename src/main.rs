@@ -1,11 +1,11 @@
-mod config;
-mod diff;
-mod matching;
-
-use std::collections::HashMap;
+use detect_changed_files::{
+    Backend, ChangedFiles, Config, DetectOptions, DiffMode, DiffRange, Ecosystem, InputFormat,
+    NormalizationForm, OutputOrder, PathNormalization, Workspace, detect, detect_range,
+};
 use std::env;
 use std::fs;
 use std::io::{self, Write};
+use std::path::Path;
 use std::process;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -16,18 +16,85 @@ fn print_help() {
 
 USAGE:
     detect_changed_files [OPTIONS] <config.conf>
+    detect_changed_files [OPTIONS] --base <ref> [--head <ref>] <config.conf>
+    detect_changed_files [OPTIONS] [--base <ref>] --staged <config.conf>
+    detect_changed_files [OPTIONS] [--base <ref>] --working-tree <config.conf>
+    detect_changed_files [OPTIONS] --auto <config.conf>
 
 ARGS:
     <config.conf>    Path to the configuration file
 
 OPTIONS:
-    -h, --help       Print this help message
-    -V, --version    Print version information
+    -h, --help              Print this help message
+    -V, --version           Print version information
+    --order <config|alpha>  Order of the groups in the output: as declared in
+                            the configuration file, or sorted alphabetically
+                            (default: alpha)
+    -i, --case-insensitive  Ignore case differences when matching patterns,
+                            unless a group sets 'case-insensitive = false'
+    --normalize-unicode <nfc|nfd>
+                            Convert both patterns and input paths to the given
+                            Unicode normalization form before matching, so
+                            that composed and decomposed characters match
+    --root <dir>            Repository root: absolute input paths are made
+                            relative to it, and paths outside of it are
+                            rejected. git is run in that directory.
+    --base <ref>            Run git to list the files changed since <ref>,
+                            instead of reading them from stdin
+    --head <ref>            Commit to compare --base with (default: HEAD)
+    --staged                Run git to list the staged changes, compared to
+                            --base if given or to HEAD
+    --working-tree          Run git to list the changes in the working tree,
+                            compared to --base if given or to the index
+    --input-format <auto|names|patch|numstat>
+                            Format of stdin: 'names' reads one path per line,
+                            'patch' reads a unified diff such as the output
+                            of 'git diff' or 'git format-patch', 'numstat'
+                            reads the output of 'git diff --numstat', and
+                            'auto' detects it (default: auto)
+    --stats                 Output the numbers of lines added and deleted in
+                            the files of each group
+    --workspace <cargo|npm|go>
+                            Also report the packages of the workspace at the
+                            repository root as groups named like
+                            'cargo:<name>' or 'npm:<name>', changed when files
+                            of their directory change or when packages they
+                            depend on do. May be repeated. 'npm' also reads
+                            yarn and pnpm workspaces, and 'go' reads go.work
+                            or every go.mod file.
+    --explain               Tell whether the files of each group changed, or
+                            only the groups it depends on
+    --output <object|names|matrix>
+                            Shape of the output: 'object' maps every group to
+                            whether it matched, 'names' is an array of the
+                            groups that matched, and 'matrix' an array of
+                            objects giving the name, the files and the
+                            metadata of each group that matched, for CI job
+                            matrices (default: object)
+    --diff-mode <three-dot|two-dot>
+                            How --base is compared: 'three-dot' compares the
+                            merge base of --base and the head, so that only
+                            the changes introduced by the head are listed;
+                            'two-dot' compares --base directly
+                            (default: three-dot)
+    --git-backend <auto|command|builtin>
+                            How changes are listed: 'command' runs git,
+                            'builtin' reads the repository directly and
+                            'auto' runs git if it is installed, reading the
+                            repository directly otherwise (default: auto)
+    --auto                  Run git on the range of changes detected from the
+                            CI environment (GitHub Actions event payload,
+                            GitLab CI or Bitbucket Pipelines variables),
+                            falling back to the last commit. The chosen range
+                            is reported on stderr.
 
 DESCRIPTION:
     This tool reads changed file paths from stdin (typically the output of
-    'git diff --name-only') and categorizes them based on patterns defined
-    in the configuration file.
+    'git diff --name-only', or a patch) and categorizes them based on
+    patterns defined in the configuration file.
+
+    Input paths are normalized lexically: './', '..' and duplicate slashes
+    are resolved, and paths escaping the repository root are rejected.
 
     The tool outputs JSON to stdout with boolean values indicating which
     groups have matching files.
@@ -42,6 +109,9 @@ EXAMPLES:
     # Check changes between commits
     git diff --name-only HEAD~1 HEAD | detect_changed_files config.conf
 
+    # Same, letting the tool run git
+    detect_changed_files --base HEAD~1 --head HEAD config.conf
+
 CONFIGURATION:
     The configuration file uses section-based format where each section name
     (in square brackets) represents a group, followed by file patterns on
@@ -50,10 +120,40 @@ CONFIGURATION:
     - * matches any sequence of characters except /
     - ? matches any single character except /
     - ** matches zero or more path components (directories)
+    - [abc] and [a-z] match one character of a set, [!abc] negates it
+    - {{a,b}} matches either alternative
+
+    A pattern may be followed by ':' and a region, to only match when lines
+    of that region changed: 'N-M' for line numbers, '/regex/' for matching
+    lines, or '/start/,/end/' for blocks from a line matching 'start' to the
+    line before the next one matching 'end'.
+
+    Sections may also set options with 'name = value' lines:
+
+    - case-insensitive = true|false   Ignore case differences for this group
+    - ignore-whitespace = true|false  Skip files with whitespace-only changes
+    - ignore-comments = true|false    Skip files with comment-only changes
+    - changed-lines-above = N         Only match when more than N lines were
+                                      added or deleted in the group's files
+    - added-lines-above = N           Same, counting added lines
+    - deleted-lines-above = N         Same, counting deleted lines
+    - depends-on = group, ...         Changed when one of these groups is
+    - meta.<key> = value              Field added to the entry of the group
+                                      with --output=matrix
 
 OUTPUT:
     JSON object with group names as keys and boolean values indicating
-    whether any files matched that group's patterns.
+    whether any files matched that group's patterns. With --stats, the values
+    are objects like {{\"changed\": true, \"added\": 12, \"deleted\": 3}}.
+    With --explain, the values are objects like
+    {{\"changed\": true, \"direct\": false, \"via\": [\"core\"]}}, \"direct\" telling
+    whether files of the group changed and \"via\" which of the groups it
+    depends on changed.
+    With --output=names, a JSON array like [\"api\", \"web\"]. With
+    --output=matrix, a JSON array like
+    [{{\"group\": \"api\", \"files\": [\"api/main.go\"], \"runner\": \"linux\"}}],
+    to use with fromJson() in a GitHub Actions strategy.matrix.
+    Options can also be given as --option=value.
 ",
         VERSION
     );
@@ -64,85 +164,239 @@ fn print_version() {
     println!("{}", VERSION);
 }
 
-fn check_patterns(
-    config: &HashMap<String, Vec<String>>,
-    diff_files: &diff::DiffFiles,
-) -> HashMap<String, bool> {
-    let mut results = HashMap::new();
+/// Shape of the JSON output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Output {
+    /// Object mapping every group to whether it matched
+    Object,
+    /// Array of the names of the groups that matched
+    Names,
+    /// Array of objects describing the groups that matched
+    Matrix,
+}
 
-    // Initialize all groups to false
-    for group_name in config.keys() {
-        results.insert(group_name.clone(), false);
-    }
+/// Command line options
+#[derive(Debug)]
+struct Options {
+    config_path: String,
+    order: OutputOrder,
+    case_insensitive: bool,
+    normalization: PathNormalization,
+    /// Range to list the changes of with git, read stdin if None
+    range: Option<DiffRange>,
+    /// Detect the range to list the changes of from the CI environment
+    auto: bool,
+    diff_mode: DiffMode,
+    git_backend: Backend,
+    input_format: InputFormat,
+    /// Output the numbers of changed lines of each group
+    stats: bool,
+    /// Output why each group changed
+    explain: bool,
+    /// Workspaces whose packages are reported along with the groups
+    workspaces: Vec<Ecosystem>,
+    output: Output,
+}
 
-    // Check each changed file against all patterns
-    for file_path in &diff_files.files {
-        for (group_name, patterns) in config.iter() {
-            // Skip if already matched
-            if *results.get(group_name).unwrap() {
-                continue;
-            }
+/// Outcome of the command line parsing
+enum Command {
+    Help,
+    Version,
+    Run(Options),
+}
 
-            // Check if any pattern matches this file
-            for pattern_str in patterns {
-                let pattern = matching::MatchPath::from_str(pattern_str);
-                if pattern.is_match(file_path) {
-                    results.insert(group_name.clone(), true);
-                    break;
+fn parse_args(args: &[String]) -> Result<Command, String> {
+    let mut config_path = None;
+    let mut order = OutputOrder::Alpha;
+    let mut case_insensitive = false;
+    let mut normalization = PathNormalization::default();
+    let mut base = None;
+    let mut head = None;
+    let mut staged = false;
+    let mut working_tree = false;
+    let mut auto = false;
+    let mut diff_mode = DiffMode::ThreeDot;
+    let mut git_backend = Backend::Auto;
+    let mut input_format = InputFormat::Auto;
+    let mut stats = false;
+    let mut explain = false;
+    let mut workspaces = Vec::new();
+    let mut output = Output::Object;
+
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        // Options may be given as "--name=value" or "--name value"
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = |name: &str| {
+            inline_value
+                .clone()
+                .or_else(|| iter.next().cloned())
+                .ok_or_else(|| format!("Missing value for option '{}'", name))
+        };
+
+        match name {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "-i" | "--case-insensitive" => case_insensitive = true,
+            "--order" => {
+                order = match value(name)?.as_str() {
+                    "config" => OutputOrder::Config,
+                    "alpha" => OutputOrder::Alpha,
+                    other => return Err(format!("Invalid value for --order: '{}'", other)),
+                };
+            }
+            "--normalize-unicode" => {
+                normalization.unicode = match value(name)?.as_str() {
+                    "nfc" | "NFC" => Some(NormalizationForm::Nfc),
+                    "nfd" | "NFD" => Some(NormalizationForm::Nfd),
+                    other => {
+                        return Err(format!(
+                            "Invalid value for --normalize-unicode: '{}'",
+                            other
+                        ));
+                    }
+                };
+            }
+            "--base" => base = Some(value(name)?),
+            "--head" => head = Some(value(name)?),
+            "--staged" => staged = true,
+            "--working-tree" => working_tree = true,
+            "--auto" => auto = true,
+            "--stats" => stats = true,
+            "--explain" => explain = true,
+            "--workspace" => {
+                let ecosystem = match value(name)?.as_str() {
+                    "cargo" => Ecosystem::Cargo,
+                    "npm" => Ecosystem::Npm,
+                    "go" => Ecosystem::Go,
+                    other => return Err(format!("Invalid value for --workspace: '{}'", other)),
+                };
+                if !workspaces.contains(&ecosystem) {
+                    workspaces.push(ecosystem);
                 }
             }
+            "--output" => {
+                output = match value(name)?.as_str() {
+                    "object" => Output::Object,
+                    "names" => Output::Names,
+                    "matrix" => Output::Matrix,
+                    other => return Err(format!("Invalid value for --output: '{}'", other)),
+                };
+            }
+            "--git-backend" => {
+                git_backend = match value(name)?.as_str() {
+                    "auto" => Backend::Auto,
+                    "command" => Backend::Command,
+                    "builtin" => Backend::Builtin,
+                    other => return Err(format!("Invalid value for --git-backend: '{}'", other)),
+                };
+            }
+            "--input-format" => {
+                input_format = match value(name)?.as_str() {
+                    "auto" => InputFormat::Auto,
+                    "names" => InputFormat::Names,
+                    "patch" => InputFormat::Patch,
+                    "numstat" => InputFormat::Numstat,
+                    other => return Err(format!("Invalid value for --input-format: '{}'", other)),
+                };
+            }
+            "--diff-mode" => {
+                diff_mode = match value(name)?.as_str() {
+                    "three-dot" => DiffMode::ThreeDot,
+                    "two-dot" => DiffMode::TwoDot,
+                    other => return Err(format!("Invalid value for --diff-mode: '{}'", other)),
+                };
+            }
+            "--root" => {
+                let root = value(name)?;
+                let cwd = env::current_dir()
+                    .map_err(|e| format!("Cannot get current directory: {}", e))?;
+                normalization
+                    .set_root(Path::new(&root), &cwd)
+                    .map_err(|e| format!("Invalid value for --root: {}", e))?;
+            }
+            _ if name.starts_with('-') && name.len() > 1 => {
+                return Err(format!("Unknown option '{}'", name));
+            }
+            _ => {
+                if config_path.is_some() {
+                    return Err("Too many arguments".to_string());
+                }
+                config_path = Some(arg.clone());
+            }
         }
     }
 
-    results
-}
-
-fn generate_json(results: &HashMap<String, bool>) -> String {
-    let mut json = String::from("{");
-
-    let mut entries: Vec<_> = results.iter().collect();
-    entries.sort_by_key(|(k, _)| *k);
+    if auto && (base.is_some() || head.is_some() || staged || working_tree) {
+        return Err(
+            "--auto cannot be used with --base, --head, --staged or --working-tree".to_string(),
+        );
+    }
 
-    for (i, (key, value)) in entries.iter().enumerate() {
-        let value_str = if **value { "true" } else { "false" };
-        json.push_str(&format!("\"{}\": {}", key, value_str));
+    if (stats || explain) && output == Output::Names {
+        return Err("--stats and --explain cannot be used with --output=names".to_string());
+    }
 
-        if i < entries.len() - 1 {
-            json.push(',');
+    let range = match (base, head, staged, working_tree) {
+        (_, _, true, true) => {
+            return Err("--staged and --working-tree are mutually exclusive".to_string());
         }
-    }
+        (_, Some(_), true, _) | (_, Some(_), _, true) => {
+            return Err("--head cannot be used with --staged or --working-tree".to_string());
+        }
+        (None, Some(_), _, _) => return Err("--head requires --base".to_string()),
+        (base, _, true, _) => Some(DiffRange::Staged { base }),
+        (base, _, _, true) => Some(DiffRange::WorkingTree { base }),
+        (Some(base), head, _, _) => Some(DiffRange::Commits {
+            base,
+            head: head.unwrap_or_else(|| "HEAD".to_string()),
+        }),
+        (None, None, false, false) => None,
+    };
 
-    json.push('}');
-    json
+    match config_path {
+        Some(config_path) => Ok(Command::Run(Options {
+            config_path,
+            order,
+            case_insensitive,
+            normalization,
+            range,
+            auto,
+            diff_mode,
+            git_backend,
+            input_format,
+            stats,
+            explain,
+            workspaces,
+            output,
+        })),
+        None => Err("No configuration file specified".to_string()),
+    }
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    // Handle help and version flags
-    if args.len() == 2 {
-        match args[1].as_str() {
-            "-h" | "--help" => {
-                print_help();
-                return;
-            }
-            "-V" | "--version" => {
-                print_version();
-                return;
-            }
-            _ => {}
+    let options = match parse_args(&args) {
+        Ok(Command::Help) => {
+            print_help();
+            return;
         }
-    } else if args.len() < 2 {
-        eprintln!("Error: No configuration file specified");
-        eprintln!("Use -h or --help for usage information");
-        process::exit(1);
-    } else if args.len() > 2 {
-        eprintln!("Error: Too many arguments");
-        eprintln!("Use -h or --help for usage information");
-        process::exit(1);
-    }
-
-    let config_path = &args[1];
+        Ok(Command::Version) => {
+            print_version();
+            return;
+        }
+        Ok(Command::Run(options)) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprintln!("Use -h or --help for usage information");
+            process::exit(1);
+        }
+    };
+    let config_path = &options.config_path;
 
     // Read and parse configuration file
     let config_content = match fs::read_to_string(config_path) {
@@ -153,31 +407,222 @@ fn main() {
         }
     };
 
-    let config = match config::parse_config(&config_content) {
+    let mut config = match Config::parse(&config_content) {
         Ok(cfg) => cfg,
-        Err(e) => {
-            eprintln!("Error parsing config file: {}", e);
+        Err(errors) => {
+            for e in &errors {
+                eprintln!("{}", e.render(&config_content, config_path));
+            }
+            eprintln!(
+                "Error parsing config file '{}': {} error(s) found",
+                config_path,
+                errors.len()
+            );
             process::exit(1);
         }
     };
+    // Patterns are normalized the same way as input paths
+    if let Some(form) = options.normalization.unicode {
+        config.normalize_unicode(form);
+    }
 
-    // Read changed files from stdin
-    let diff_files = match diff::DiffFiles::from_stdin() {
+    let mut range = options.range.clone();
+    if options.auto {
+        match detect_range(&|name| env::var(name).ok()) {
+            Ok(detected) => {
+                eprintln!("Using range {} ({})", detected.range, detected.source);
+                range = Some(detected.range);
+            }
+            Err(e) => {
+                eprintln!("Error detecting the range of changes: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    // Read changed files from git or stdin
+    let changed_files = match &range {
+        Some(range) => ChangedFiles::from_git(
+            range,
+            options.diff_mode,
+            options.git_backend,
+            &options.normalization,
+        )
+        .map_err(|e| match e {
+            detect_changed_files::Error::Path(e) => {
+                format!("Error reading changed files from git: {}", e)
+            }
+            e => format!("Error listing changed files with git: {}", e),
+        }),
+        None => ChangedFiles::from_stdin(options.input_format, &options.normalization)
+            .map_err(|e| format!("Error reading from stdin: {}", e)),
+    };
+    let changed_files = match changed_files {
         Ok(files) => files,
         Err(e) => {
-            eprintln!("Error reading from stdin: {}", e);
+            eprintln!("{}", e);
             process::exit(1);
         }
     };
 
+    // Expand template sections for the directories of the changes and of
+    // the repository
+    config.expand_templates(
+        &changed_files,
+        Some(&options.normalization.root_dir()),
+        options.case_insensitive,
+    );
+
     // Check patterns and generate results
-    let results = check_patterns(&config, &diff_files);
+    let detect_options = DetectOptions {
+        case_insensitive: options.case_insensitive,
+        count_lines: options.stats,
+        list_files: options.output == Output::Matrix,
+    };
+    let mut results = match detect(&config, &changed_files, &detect_options) {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("Error evaluating the groups: {}", e);
+            process::exit(1);
+        }
+    };
+
+    // Report the packages of the workspaces along with the groups
+    for &ecosystem in &options.workspaces {
+        match Workspace::load(ecosystem, &options.normalization) {
+            Ok(workspace) => results
+                .groups
+                .extend(workspace.detect(&changed_files).groups),
+            Err(e) => {
+                eprintln!("Error reading the {} workspace: {}", ecosystem.prefix(), e);
+                process::exit(1);
+            }
+        }
+    }
 
     // Generate and output JSON
-    let json_output = generate_json(&results);
+    let json_output = match options.output {
+        Output::Object => results.to_json(options.order, options.stats, options.explain),
+        Output::Names => results.to_json_names(options.order),
+        Output::Matrix => {
+            results.to_json_matrix(&config, options.order, options.stats, options.explain)
+        }
+    };
 
     if let Err(e) = io::stdout().write_all(json_output.as_bytes()) {
         eprintln!("Error writing output: {}", e);
         process::exit(1);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        std::iter::once("detect_changed_files")
+            .chain(list.iter().copied())
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn parse_args_order() {
+        for (list, expected) in [
+            (&["a.conf"][..], OutputOrder::Alpha),
+            (&["--order=config", "a.conf"][..], OutputOrder::Config),
+            (&["a.conf", "--order", "alpha"][..], OutputOrder::Alpha),
+        ] {
+            match parse_args(&args(list)) {
+                Ok(Command::Run(options)) => {
+                    assert_eq!(options.config_path, "a.conf");
+                    assert_eq!(options.order, expected);
+                }
+                _ => panic!("unexpected parse result for {:?}", list),
+            }
+        }
+        assert!(parse_args(&args(&["--order=random", "a.conf"])).is_err());
+        assert!(parse_args(&args(&["a.conf", "--order"])).is_err());
+        assert!(parse_args(&args(&["a.conf", "b.conf"])).is_err());
+    }
+
+    #[test]
+    fn parse_args_output() {
+        for (list, expected) in [
+            (&["a.conf"][..], Output::Object),
+            (&["--output=names", "a.conf"][..], Output::Names),
+            (
+                &["--output", "matrix", "--stats", "a.conf"][..],
+                Output::Matrix,
+            ),
+        ] {
+            match parse_args(&args(list)) {
+                Ok(Command::Run(options)) => assert_eq!(options.output, expected),
+                _ => panic!("unexpected parse result for {:?}", list),
+            }
+        }
+        assert!(parse_args(&args(&["--output=yaml", "a.conf"])).is_err());
+        assert!(parse_args(&args(&["--output=names", "--stats", "a.conf"])).is_err());
+        assert!(parse_args(&args(&["--output=names", "--explain", "a.conf"])).is_err());
+    }
+
+    #[test]
+    fn parse_args_workspaces() {
+        let list = [
+            "--workspace=cargo",
+            "--workspace",
+            "npm",
+            "--workspace=cargo",
+            "--workspace=go",
+            "a.conf",
+        ];
+        match parse_args(&args(&list)) {
+            Ok(Command::Run(options)) => assert_eq!(
+                options.workspaces,
+                [Ecosystem::Cargo, Ecosystem::Npm, Ecosystem::Go]
+            ),
+            _ => panic!("unexpected parse result"),
+        }
+        assert!(parse_args(&args(&["--workspace=maven", "a.conf"])).is_err());
+    }
+
+    fn parse_range(list: &[&str]) -> Result<Option<DiffRange>, String> {
+        match parse_args(&args(list))? {
+            Command::Run(options) => Ok(options.range),
+            _ => panic!("unexpected parse result for {:?}", list),
+        }
+    }
+
+    #[test]
+    fn parse_args_git_range() {
+        assert_eq!(parse_range(&["a.conf"]), Ok(None));
+        assert_eq!(
+            parse_range(&["--base", "main", "a.conf"]),
+            Ok(Some(DiffRange::Commits {
+                base: "main".to_string(),
+                head: "HEAD".to_string()
+            }))
+        );
+        assert_eq!(
+            parse_range(&["--base=v1", "--head=v2", "a.conf"]),
+            Ok(Some(DiffRange::Commits {
+                base: "v1".to_string(),
+                head: "v2".to_string()
+            }))
+        );
+        assert_eq!(
+            parse_range(&["--staged", "a.conf"]),
+            Ok(Some(DiffRange::Staged { base: None }))
+        );
+        assert_eq!(
+            parse_range(&["--working-tree", "--base", "main", "a.conf"]),
+            Ok(Some(DiffRange::WorkingTree {
+                base: Some("main".to_string())
+            }))
+        );
+        assert!(parse_range(&["--head", "v2", "a.conf"]).is_err());
+        assert!(parse_range(&["--staged", "--working-tree", "a.conf"]).is_err());
+        assert!(parse_range(&["--staged", "--head", "v2", "a.conf"]).is_err());
+        assert!(parse_range(&["--auto", "--base", "main", "a.conf"]).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 // Do pattern matching on strings
 
-use std::collections::HashMap;
+use crate::unicode::{self, NormalizationForm, fold_case};
+use std::fmt;
 
 // PATTERN FORMAT
 //  - The slash "/" is used as the directory separator.
@@ -13,10 +14,53 @@ use std::collections::HashMap;
 //    as if it had a trailing "**".
 //  - An asterisk "*" matches anything except a slash. The character "?"
 //    matches any one character except "/".
+//  - "[...]" matches one character from a set of characters or ranges, like
+//    "[a-z]". The set is negated when it starts with "!" or "^".
+//  - "{a,b}" matches either of the comma-separated alternatives. Alternatives
+//    may contain slashes and can be nested.
+//  - Matching can be made case-insensitive, using full Unicode case folding.
+//  - Paths are sequences of bytes. Bytes that are not valid UTF-8 only match
+//    wildcards and negated character classes.
+//  - "**" must be a whole path component and empty components ("a//b") are
+//    not allowed.
+
+/// A character of a path, or a byte of it that is not valid UTF-8.
+/// Such bytes sort after every character, so that they fall in no range of
+/// characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PathChar {
+    Char(char),
+    Byte(u8),
+}
+
+impl PartialEq<char> for PathChar {
+    fn eq(&self, other: &char) -> bool {
+        *self == PathChar::Char(*other)
+    }
+}
+
+/// Decode a path, keeping the bytes that are not valid UTF-8
+fn decode_path(path: &[u8]) -> Vec<PathChar> {
+    let mut chars = Vec::with_capacity(path.len());
+    for chunk in path.utf8_chunks() {
+        chars.extend(chunk.valid().chars().map(PathChar::Char));
+        chars.extend(chunk.invalid().iter().map(|&b| PathChar::Byte(b)));
+    }
+    chars
+}
+
+/// Fold the case of a character of a path, which may give several
+/// characters. Bytes that are not valid UTF-8 are left as they are.
+fn fold_path_char(c: PathChar) -> Vec<PathChar> {
+    match c {
+        PathChar::Char(c) => fold_case(&[c]).into_iter().map(PathChar::Char).collect(),
+        byte => vec![byte],
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct PathComponent {
-    str: Vec<char>,
+    str: Vec<PathChar>,
 }
 
 impl PathComponent {
@@ -24,12 +68,13 @@ impl PathComponent {
         self.str.len() == 2 && self.str[0] == '*' && self.str[1] == '*'
     }
 
-    fn new(chars: Vec<char>) -> Self {
+    fn new(chars: Vec<PathChar>) -> Self {
         PathComponent { str: chars }
     }
 }
 
-#[derive(Debug)]
+/// A path split into components, ready to be matched against patterns
+#[derive(Debug, Clone)]
 pub struct MatchPath {
     components: Vec<PathComponent>,
     is_absolute: bool,
@@ -37,7 +82,7 @@ pub struct MatchPath {
 }
 
 impl MatchPath {
-    pub fn new(path: &[char]) -> Self {
+    pub(crate) fn new(path: &[PathChar]) -> Self {
         if path.is_empty() {
             return MatchPath {
                 components: Vec::new(),
@@ -57,14 +102,23 @@ impl MatchPath {
         }
     }
 
-    pub fn from_str(path: &str) -> Self {
-        let path_chars: Vec<char> = path.chars().collect();
-        Self::new(&path_chars)
+    /// Build a path from its bytes, which need not be valid UTF-8
+    pub fn from_bytes(path: &[u8]) -> Self {
+        Self::new(&decode_path(path))
     }
 
     /// Check if the path matches the given text
     /// self is the pattern, text is the string to match against
     pub fn is_match(&self, text: &MatchPath) -> bool {
+        self.matches(text, false)
+    }
+
+    /// Same as `is_match`, ignoring case differences
+    pub fn is_match_ignore_case(&self, text: &MatchPath) -> bool {
+        self.matches(text, true)
+    }
+
+    fn matches(&self, text: &MatchPath, case_insensitive: bool) -> bool {
         // If the pattern has no components, it matches only if text is empty
         if self.components.is_empty() {
             return text.components.is_empty();
@@ -94,7 +148,7 @@ impl MatchPath {
                 continue;
             }
 
-            if !match_pattern_component(&pattern_comp.str, &text_comp.str) {
+            if !match_pattern_component(&pattern_comp.str, &text_comp.str, case_insensitive) {
                 // If the current components do not match, check if we had a double star
                 if is_double_star {
                     // If we had a double star, we can skip this text component
@@ -128,8 +182,382 @@ impl MatchPath {
     }
 }
 
+/// What is wrong with a pattern
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternErrorKind {
+    /// A "[" without its closing "]"
+    UnclosedBracket,
+    /// A "{" without its closing "}"
+    UnclosedBrace,
+    /// A "}" without its opening "{"
+    UnmatchedBrace,
+    /// Three or more consecutive asterisks
+    TripleStar,
+    /// "**" sharing a path component with other characters, like "a**b"
+    DoubleStarNotAlone,
+    /// Two consecutive slashes
+    EmptyComponent,
+    /// A "[]" or "[!]" class without any character
+    EmptyClass,
+    /// A "{}" without any alternative
+    EmptyBraces,
+    /// An empty alternative between braces, like in "{a,}"
+    EmptyAlternative,
+    /// A backslash ending a path component, with nothing to escape
+    DanglingEscape,
+}
+
+/// Error found while compiling a pattern. `start` and `end` are character
+/// offsets in the pattern string delimiting the offending part.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternError {
+    /// What is wrong
+    pub kind: PatternErrorKind,
+    /// Offset of the first character of the offending part
+    pub start: usize,
+    /// Offset following the offending part
+    pub end: usize,
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self.kind {
+            PatternErrorKind::UnclosedBracket => "unclosed '[' in pattern",
+            PatternErrorKind::UnclosedBrace => "unclosed '{' in pattern",
+            PatternErrorKind::UnmatchedBrace => "unmatched '}' in pattern",
+            PatternErrorKind::TripleStar => "'***' is not a valid wildcard",
+            PatternErrorKind::DoubleStarNotAlone => "'**' must be a whole path component",
+            PatternErrorKind::EmptyComponent => "empty path component in pattern",
+            PatternErrorKind::EmptyClass => "empty character class in pattern",
+            PatternErrorKind::EmptyBraces => "empty '{}' in pattern",
+            PatternErrorKind::EmptyAlternative => "empty alternative in pattern",
+            PatternErrorKind::DanglingEscape => "'\\' with nothing to escape in pattern",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+/// A compiled pattern, as found in the configuration file
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    source: String,
+    /// One path per alternative once braces are expanded
+    alternatives: Vec<MatchPath>,
+}
+
+impl Pattern {
+    /// Validate the pattern syntax and compile it
+    pub fn compile(pattern: &str) -> Result<Self, PatternError> {
+        let chars: Vec<char> = pattern.chars().collect();
+        validate_pattern(&chars, false)?;
+
+        let expanded = expand_braces(&chars);
+        if let Some(open) = chars.iter().position(|&c| c == '{') {
+            // Errors in an alternative are reported on the path components
+            // holding the braces
+            let close = chars.iter().rposition(|&c| c == '}').unwrap_or(open);
+            let start = component_bounds(&chars, open).0;
+            let end = component_bounds(&chars, close).1;
+            for alternative in &expanded {
+                validate_pattern(alternative, true).map_err(|e| PatternError {
+                    start,
+                    end,
+                    ..e
+                })?;
+            }
+        }
+        let alternatives = expanded
+            .into_iter()
+            .map(|alternative| {
+                let alternative: Vec<PathChar> =
+                    alternative.into_iter().map(PathChar::Char).collect();
+                MatchPath::new(&alternative)
+            })
+            .collect();
+
+        Ok(Pattern {
+            source: pattern.to_string(),
+            alternatives,
+        })
+    }
+
+    /// The pattern as written in the configuration
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// The pattern with its characters converted to a Unicode normalization
+    /// form, to match paths converted the same way
+    pub fn normalize_unicode(&self, form: NormalizationForm) -> Pattern {
+        // Syntax characters are ASCII, which normalization leaves as they
+        // are, so the converted pattern is valid too
+        Pattern::compile(&unicode::normalize(&self.source, form)).unwrap_or_else(|_| self.clone())
+    }
+
+    /// Check if any alternative of the pattern matches the given text
+    pub fn is_match(&self, text: &MatchPath) -> bool {
+        self.alternatives.iter().any(|alt| alt.is_match(text))
+    }
+
+    /// Same as `is_match`, ignoring case differences
+    pub fn is_match_ignore_case(&self, text: &MatchPath) -> bool {
+        self.alternatives
+            .iter()
+            .any(|alt| alt.is_match_ignore_case(text))
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Pattern {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Find the index of the "]" closing the character class opened at `start`.
+/// Returns None if the class is not closed within the current component.
+fn find_class_end<C: PartialEq<char>>(pattern: &[C], start: usize) -> Option<usize> {
+    let mut i = start + 1;
+    if i < pattern.len() && (pattern[i] == '!' || pattern[i] == '^') {
+        i += 1;
+    }
+    // A "]" right after the opening bracket is taken literally
+    if i < pattern.len() && pattern[i] == ']' {
+        i += 1;
+    }
+    while i < pattern.len() {
+        if pattern[i] == ']' {
+            return Some(i);
+        }
+        if pattern[i] == '/' {
+            return None;
+        }
+        if is_escape(pattern, i) {
+            i += 1;
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Whether the character at `i` escapes the next one
+fn is_escape<C: PartialEq<char>>(pattern: &[C], i: usize) -> bool {
+    pattern[i] == '\\' && i + 1 < pattern.len() && pattern[i + 1] != '/'
+}
+
+/// Offsets of the start and the end of the path component around `i`
+fn component_bounds(pattern: &[char], i: usize) -> (usize, usize) {
+    let start = pattern[..i]
+        .iter()
+        .rposition(|&c| c == '/')
+        .map_or(0, |p| p + 1);
+    let end = pattern[i..]
+        .iter()
+        .position(|&c| c == '/')
+        .map_or(pattern.len(), |p| i + p);
+    (start, end)
+}
+
+/// Check the syntax of a pattern, returning the first error found. Unless
+/// the braces were `expanded`, stars in a path component with braces are
+/// left to the check of the alternatives, like "{**,x}/b".
+fn validate_pattern(pattern: &[char], expanded: bool) -> Result<(), PatternError> {
+    let error = |kind, start, end| Err(PatternError { kind, start, end });
+    // Opening brace and last separator, "{" or ",", of each open group
+    let mut open_braces: Vec<(usize, usize)> = Vec::new();
+    let mut i = 0;
+
+    while i < pattern.len() {
+        match pattern[i] {
+            '\\' if is_escape(pattern, i) => {
+                i += 2;
+                continue;
+            }
+            '\\' => return error(PatternErrorKind::DanglingEscape, i, i + 1),
+            '[' => match find_class_end(pattern, i) {
+                Some(end) => {
+                    i = end + 1;
+                    continue;
+                }
+                None => {
+                    let mut end = i + 1;
+                    if matches!(pattern.get(end), Some('!' | '^')) {
+                        end += 1;
+                    }
+                    if pattern.get(end) == Some(&']') {
+                        return error(PatternErrorKind::EmptyClass, i, end + 1);
+                    }
+                    return error(PatternErrorKind::UnclosedBracket, i, i + 1);
+                }
+            },
+            '{' => open_braces.push((i, i)),
+            ',' if let Some((_, separator)) = open_braces.last_mut() => {
+                if *separator + 1 == i {
+                    return error(PatternErrorKind::EmptyAlternative, *separator, i + 1);
+                }
+                *separator = i;
+            }
+            '}' => match open_braces.pop() {
+                None => return error(PatternErrorKind::UnmatchedBrace, i, i + 1),
+                Some((open, separator)) if separator + 1 == i => {
+                    let kind = if separator == open {
+                        PatternErrorKind::EmptyBraces
+                    } else {
+                        PatternErrorKind::EmptyAlternative
+                    };
+                    return error(kind, separator, i + 1);
+                }
+                Some(_) => {}
+            },
+            '*' => {
+                let run = pattern[i..].iter().take_while(|&&c| c == '*').count();
+                let (comp_start, comp_end) = component_bounds(pattern, i);
+                let component = &pattern[comp_start..comp_end];
+                if !expanded && component.iter().any(|&c| c == '{' || c == '}') {
+                    i += run;
+                    continue;
+                }
+                if run >= 3 {
+                    return error(PatternErrorKind::TripleStar, i, i + run);
+                }
+                if run == 2 && component.len() != 2 {
+                    return error(PatternErrorKind::DoubleStarNotAlone, comp_start, comp_end);
+                }
+                i += run;
+                continue;
+            }
+            '/' if i > 0 && pattern[i - 1] == '/' => {
+                return error(PatternErrorKind::EmptyComponent, i - 1, i + 1);
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    match open_braces.first() {
+        Some(&(start, _)) => error(PatternErrorKind::UnclosedBrace, start, start + 1),
+        None => Ok(()),
+    }
+}
+
+/// Expand the "{a,b}" alternatives of a validated pattern
+fn expand_braces(pattern: &[char]) -> Vec<Vec<char>> {
+    // Find the first top-level brace group and the commas splitting it
+    let mut open = None;
+    let mut commas = Vec::new();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < pattern.len() {
+        match pattern[i] {
+            '\\' => i += 1,
+            '[' => {
+                if let Some(end) = find_class_end(pattern, i) {
+                    i = end;
+                }
+            }
+            '{' => {
+                if depth == 0 {
+                    open = Some(i);
+                }
+                depth += 1;
+            }
+            ',' if depth == 1 => commas.push(i),
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    let open = open.unwrap_or(0);
+                    let prefix = &pattern[..open];
+                    let suffix = &pattern[i + 1..];
+                    let mut bounds = vec![open];
+                    bounds.extend(&commas);
+                    bounds.push(i);
+
+                    let mut expanded = Vec::new();
+                    for window in bounds.windows(2) {
+                        let mut alternative = prefix.to_vec();
+                        alternative.extend_from_slice(&pattern[window[0] + 1..window[1]]);
+                        alternative.extend_from_slice(suffix);
+                        expanded.extend(expand_braces(&alternative));
+                    }
+                    return expanded;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    vec![pattern.to_vec()]
+}
+
+/// Match a single character against the character class starting at
+/// `start`, returning the index just after the class on success. Ignoring
+/// case, the folded character is also compared with the folded members.
+fn match_class(
+    pattern: &[PathChar],
+    start: usize,
+    c: PathChar,
+    case_insensitive: bool,
+) -> Option<usize> {
+    let end = find_class_end(pattern, start)?;
+    let mut i = start + 1;
+    let negated = pattern[i] == '!' || pattern[i] == '^';
+    if negated {
+        i += 1;
+    }
+
+    let folded = case_insensitive.then(|| fold_path_char(c));
+    // Ranges compare characters that fold to a single one
+    let single = |c: PathChar| match fold_path_char(c)[..] {
+        [folded] => Some(folded),
+        _ => None,
+    };
+    // A member of the class at `i`, unescaped, and the index after it
+    let member = |i: usize| {
+        if is_escape(pattern, i) {
+            (pattern[i + 1], i + 2)
+        } else {
+            (pattern[i], i + 1)
+        }
+    };
+    let mut found = false;
+    while i < end {
+        let (low, next) = member(i);
+        let range = next + 1 < end && pattern[next] == '-';
+        let (high, next) = if range { member(next + 1) } else { (low, next) };
+        i = next;
+        if range {
+            if low <= c && c <= high {
+                found = true;
+            }
+            if case_insensitive
+                && let (Some(low), Some(high), Some(c)) = (single(low), single(high), single(c))
+                && low <= c
+                && c <= high
+            {
+                found = true;
+            }
+        } else if low == c || folded.as_ref() == Some(&fold_path_char(low)) {
+            found = true;
+        }
+    }
+
+    if found != negated {
+        Some(end + 1)
+    } else {
+        None
+    }
+}
+
 /// Split a string into path components
-fn split_path_components(path: &[char]) -> Vec<PathComponent> {
+fn split_path_components(path: &[PathChar]) -> Vec<PathComponent> {
     let mut components = Vec::new();
     let mut start: usize = 0;
 
@@ -152,68 +580,115 @@ fn split_path_components(path: &[char]) -> Vec<PathComponent> {
     components
 }
 
-fn match_pattern_component(pattern: &[char], text: &[char]) -> bool {
-    let mut memo = HashMap::new();
-    match_recursive_memo(pattern, text, 0, 0, &mut memo)
+/// A unit of a pattern component
+enum Token {
+    /// "*"
+    Star,
+    /// "?"
+    Any,
+    /// A character class, starting at that index of the component
+    Class(usize),
+    /// Characters matched as they are, folded when ignoring case
+    Literal(Vec<PathChar>),
 }
 
-fn match_recursive_memo(
-    pattern: &[char],
-    text: &[char],
-    p_idx: usize,
-    t_idx: usize,
-    memo: &mut HashMap<usize, bool>,
-) -> bool {
-    // Create unique key for this state
-    let key = p_idx * 10000 + t_idx;
-
-    // Check memoization
-    if let Some(&result) = memo.get(&key) {
-        return result;
-    }
-
-    // Base cases
-    if p_idx == pattern.len() {
-        let result = t_idx == text.len();
-        memo.insert(key, result);
-        return result;
+/// Split a pattern component into tokens, folding the literal characters
+/// when ignoring case
+fn tokenize(pattern: &[PathChar], case_insensitive: bool) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < pattern.len() {
+        match pattern[i] {
+            PathChar::Char('*') => tokens.push(Token::Star),
+            PathChar::Char('?') => tokens.push(Token::Any),
+            PathChar::Char('[') if let Some(end) = find_class_end(pattern, i) => {
+                tokens.push(Token::Class(i));
+                i = end + 1;
+                continue;
+            }
+            c => {
+                // An escaped character is matched as it is
+                let c = if is_escape(pattern, i) {
+                    i += 1;
+                    pattern[i]
+                } else {
+                    c
+                };
+                let folded = if case_insensitive {
+                    fold_path_char(c)
+                } else {
+                    vec![c]
+                };
+                match tokens.last_mut() {
+                    Some(Token::Literal(literal)) => literal.extend(folded),
+                    _ => tokens.push(Token::Literal(folded)),
+                }
+            }
+        }
+        i += 1;
     }
+    tokens
+}
 
-    if t_idx == text.len() {
-        // If we're at end of text, pattern must be all * from here
-        let result = pattern[p_idx..].iter().all(|&c| c == '*');
-        memo.insert(key, result);
-        return result;
+/// Match literal characters at `start` of the text, returning the index
+/// just after them. Ignoring case, `folded` gives the folded characters of
+/// the text, which must each be matched whole.
+fn match_literal(
+    literal: &[PathChar],
+    text: &[PathChar],
+    folded: Option<&[Vec<PathChar>]>,
+    start: usize,
+) -> Option<usize> {
+    let Some(folded) = folded else {
+        return text[start..]
+            .starts_with(literal)
+            .then_some(start + literal.len());
+    };
+    let (mut i, mut t) = (0, start);
+    while i < literal.len() {
+        let c = folded.get(t)?;
+        if !literal[i..].starts_with(c) {
+            return None;
+        }
+        i += c.len();
+        t += 1;
     }
+    Some(t)
+}
 
-    let match_result = match pattern[p_idx] {
-        '*' => {
-            // Try matching 0, 1, 2, ... characters
-            let mut result = false;
-            for i in 0..=(text.len() - t_idx) {
-                if match_recursive_memo(pattern, text, p_idx + 1, t_idx + i, memo) {
-                    result = true;
-                    break;
+fn match_pattern_component(
+    pattern: &[PathChar],
+    text: &[PathChar],
+    case_insensitive: bool,
+) -> bool {
+    let tokens = tokenize(pattern, case_insensitive);
+    let folded: Option<Vec<Vec<PathChar>>> =
+        case_insensitive.then(|| text.iter().map(|&c| fold_path_char(c)).collect());
+
+    // Whether the tokens after the current one match the text from each
+    // index, computed from the last token
+    let n = text.len();
+    let mut next = vec![false; n + 1];
+    next[n] = true;
+    for token in tokens.iter().rev() {
+        let mut current = vec![false; n + 1];
+        for t in (0..=n).rev() {
+            current[t] = match token {
+                Token::Star => next[t] || (t < n && current[t + 1]),
+                Token::Any => t < n && next[t + 1],
+                Token::Class(start) => {
+                    t < n
+                        && next[t + 1]
+                        && match_class(pattern, *start, text[t], case_insensitive).is_some()
                 }
-            }
-            result
-        }
-        '?' => {
-            // Match exactly one character
-            match_recursive_memo(pattern, text, p_idx + 1, t_idx + 1, memo)
-        }
-        _ => {
-            // Exact character match
-            if t_idx < text.len() && pattern[p_idx] == text[t_idx] {
-                match_recursive_memo(pattern, text, p_idx + 1, t_idx + 1, memo)
-            } else {
-                false
-            }
+                Token::Literal(literal) => {
+                    match_literal(literal, text, folded.as_deref(), t).is_some_and(|end| next[end])
+                }
+            };
         }
-    };
-
-    memo.insert(key, match_result);
-    match_result
+        next = current;
+    }
+    next[0]
 }
 
 #[cfg(test)]
@@ -221,9 +696,15 @@ mod tests {
     use super::*;
 
     fn test_match_pattern_component(pattern: &str, text: &str) -> bool {
-        let pattern_chars: Vec<char> = pattern.chars().collect();
-        let text_chars: Vec<char> = text.chars().collect();
-        match_pattern_component(&pattern_chars, &text_chars)
+        let pattern_chars = decode_path(pattern.as_bytes());
+        let text_chars = decode_path(text.as_bytes());
+        match_pattern_component(&pattern_chars, &text_chars, false)
+    }
+
+    fn test_match_ignore_case(pattern: &str, text: &str) -> bool {
+        let pattern_chars = decode_path(pattern.as_bytes());
+        let text_chars = decode_path(text.as_bytes());
+        match_pattern_component(&pattern_chars, &text_chars, true)
     }
 
     #[test]
@@ -273,109 +754,286 @@ mod tests {
 
     #[test]
     fn split_path_components_test() {
-        let path: Vec<char> = "ab/cd/ef/gh/ij".chars().collect();
+        let path = decode_path(b"ab/cd/ef/gh/ij");
         let components = split_path_components(&path);
         assert_eq!(components.len(), 5);
     }
 
     #[test]
     fn match_path_absolute_pattern_eq_text() {
-        let pattern = MatchPath::from_str("/ab/cd/ef.zig");
-        let text = MatchPath::from_str("ab/cd/ef.zig");
+        let pattern = MatchPath::from_bytes(b"/ab/cd/ef.zig");
+        let text = MatchPath::from_bytes(b"ab/cd/ef.zig");
         assert!(pattern.is_match(&text));
 
-        let rel = MatchPath::from_str("foo/bar/ab/cd/ef.ghi");
+        let rel = MatchPath::from_bytes(b"foo/bar/ab/cd/ef.ghi");
         assert!(!pattern.is_match(&rel));
     }
 
     #[test]
     fn match_path_relative_pattern_eq_text() {
-        let pattern = MatchPath::from_str("ab/cd/ef.zig");
-        let text = MatchPath::from_str("ab/cd/ef.zig");
+        let pattern = MatchPath::from_bytes(b"ab/cd/ef.zig");
+        let text = MatchPath::from_bytes(b"ab/cd/ef.zig");
         assert!(pattern.is_match(&text));
 
-        let deep = MatchPath::from_str("foo/bar/ab/cd/ef.zig");
+        let deep = MatchPath::from_bytes(b"foo/bar/ab/cd/ef.zig");
         assert!(pattern.is_match(&deep));
 
-        let nope = MatchPath::from_str("ab/cd/ef.ghi");
+        let nope = MatchPath::from_bytes(b"ab/cd/ef.ghi");
         assert!(!pattern.is_match(&nope));
 
-        let nope2 = MatchPath::from_str("aaaaaab/cd/ef.ghi");
+        let nope2 = MatchPath::from_bytes(b"aaaaaab/cd/ef.ghi");
         assert!(!pattern.is_match(&nope2));
     }
 
     #[test]
     fn match_path_leading_double_star() {
-        let pattern = MatchPath::from_str("**/ef.zig");
+        let pattern = MatchPath::from_bytes(b"**/ef.zig");
 
-        let single = MatchPath::from_str("foo/ef.zig");
+        let single = MatchPath::from_bytes(b"foo/ef.zig");
         assert!(pattern.is_match(&single));
 
-        let multiple = MatchPath::from_str("ab/cd/ef.zig");
+        let multiple = MatchPath::from_bytes(b"ab/cd/ef.zig");
         assert!(pattern.is_match(&multiple));
 
-        let nope = MatchPath::from_str("ef.zig");
+        let nope = MatchPath::from_bytes(b"ef.zig");
         assert!(!pattern.is_match(&nope));
     }
 
     #[test]
     fn match_path_trailing_double_star() {
-        let pattern = MatchPath::from_str("ab/cd/**");
+        let pattern = MatchPath::from_bytes(b"ab/cd/**");
 
-        let single = MatchPath::from_str("ab/cd/ef.zig");
+        let single = MatchPath::from_bytes(b"ab/cd/ef.zig");
         assert!(pattern.is_match(&single));
 
-        let multiple = MatchPath::from_str("ab/cd/ef/gh.zig");
+        let multiple = MatchPath::from_bytes(b"ab/cd/ef/gh.zig");
         assert!(pattern.is_match(&multiple));
 
-        let nope = MatchPath::from_str("ab/cd");
+        let nope = MatchPath::from_bytes(b"ab/cd");
         assert!(!pattern.is_match(&nope));
     }
 
     #[test]
     fn match_path_double_star_in_middle() {
-        let pattern = MatchPath::from_str("ab/**/cd/ef.zig");
+        let pattern = MatchPath::from_bytes(b"ab/**/cd/ef.zig");
 
-        let single = MatchPath::from_str("ab/foo/cd/ef.zig");
+        let single = MatchPath::from_bytes(b"ab/foo/cd/ef.zig");
         assert!(pattern.is_match(&single));
 
-        let multiple = MatchPath::from_str("ab/foo/bar/cd/ef.zig");
+        let multiple = MatchPath::from_bytes(b"ab/foo/bar/cd/ef.zig");
         assert!(pattern.is_match(&multiple));
 
-        let nope = MatchPath::from_str("ab/cd/ef.zig");
+        let nope = MatchPath::from_bytes(b"ab/cd/ef.zig");
         assert!(!pattern.is_match(&nope));
     }
 
     #[test]
     fn match_path_complex_pattern_with_double_star_and_question() {
-        let pattern = MatchPath::from_str("ab/cd/**/e?f/gh.zig");
+        let pattern = MatchPath::from_bytes(b"ab/cd/**/e?f/gh.zig");
 
-        let single = MatchPath::from_str("ab/cd/foo/e3f/gh.zig");
+        let single = MatchPath::from_bytes(b"ab/cd/foo/e3f/gh.zig");
         assert!(pattern.is_match(&single));
 
-        let nope = MatchPath::from_str("ab/cd/foo/e33f/gh.zig");
+        let nope = MatchPath::from_bytes(b"ab/cd/foo/e33f/gh.zig");
         assert!(!pattern.is_match(&nope));
     }
 
     #[test]
     fn match_path_double_double_star() {
-        let pattern = MatchPath::from_str("ab/**/cd/**/ef.zig");
+        let pattern = MatchPath::from_bytes(b"ab/**/cd/**/ef.zig");
 
-        let single = MatchPath::from_str("ab/foo/cd/bar/ef.zig");
+        let single = MatchPath::from_bytes(b"ab/foo/cd/bar/ef.zig");
         assert!(pattern.is_match(&single));
 
-        let multiple = MatchPath::from_str("ab/foo/cd/bar/baz/ef.zig");
+        let multiple = MatchPath::from_bytes(b"ab/foo/cd/bar/baz/ef.zig");
         assert!(pattern.is_match(&multiple));
 
-        let nope = MatchPath::from_str("ab/cd/ef.zig");
+        let nope = MatchPath::from_bytes(b"ab/cd/ef.zig");
         assert!(!pattern.is_match(&nope));
     }
 
     #[test]
     fn match_path_with_utf8_strings() {
         // Test with UTF-8 strings
-        let pattern = MatchPath::from_str("ab/**/e⚡f/g?h/ij.zig");
-        let text = MatchPath::from_str("ab/⚡/e⚡f/g⚡h/ij.zig");
+        let pattern = MatchPath::from_bytes("ab/**/e⚡f/g?h/ij.zig".as_bytes());
+        let text = MatchPath::from_bytes("ab/⚡/e⚡f/g⚡h/ij.zig".as_bytes());
         assert!(pattern.is_match(&text));
     }
+
+    fn compile_error(pattern: &str) -> (PatternErrorKind, usize, usize) {
+        let err = Pattern::compile(pattern).unwrap_err();
+        (err.kind, err.start, err.end)
+    }
+
+    #[test]
+    fn pattern_syntax_errors() {
+        use PatternErrorKind::*;
+        assert_eq!(compile_error("src/[ab"), (UnclosedBracket, 4, 5));
+        assert_eq!(compile_error("[ab/c]"), (UnclosedBracket, 0, 1));
+        assert_eq!(compile_error("{a,b"), (UnclosedBrace, 0, 1));
+        assert_eq!(compile_error("a,b}"), (UnmatchedBrace, 3, 4));
+        assert_eq!(compile_error("src/***"), (TripleStar, 4, 7));
+        assert_eq!(compile_error("x/a**b/y"), (DoubleStarNotAlone, 2, 6));
+        assert_eq!(compile_error("**.rs"), (DoubleStarNotAlone, 0, 5));
+        assert_eq!(compile_error("a//b"), (EmptyComponent, 1, 3));
+        assert!(Pattern::compile("/src/**/").is_ok());
+        assert!(Pattern::compile("[]]{a,[,]}").is_ok());
+        // Stars next to braces are checked in each alternative
+        assert_eq!(compile_error("x/{a**,b}/y"), (DoubleStarNotAlone, 2, 9));
+        assert_eq!(compile_error("x/{*,a}**/y"), (TripleStar, 2, 9));
+    }
+
+    #[test]
+    fn pattern_empty_classes_and_alternatives() {
+        use PatternErrorKind::*;
+        assert_eq!(compile_error("a/[]"), (EmptyClass, 2, 4));
+        assert_eq!(compile_error("[!]x"), (EmptyClass, 0, 3));
+        assert_eq!(compile_error("src/{}.rs"), (EmptyBraces, 4, 6));
+        assert_eq!(compile_error("{a,}.rs"), (EmptyAlternative, 2, 4));
+        assert_eq!(compile_error("{,a}.rs"), (EmptyAlternative, 0, 2));
+        assert_eq!(compile_error("{a,,b}"), (EmptyAlternative, 2, 4));
+        assert_eq!(compile_error("{a,{}}"), (EmptyBraces, 3, 5));
+        // Classes and escaped characters are not separators
+        assert!(Pattern::compile("{[,],a}").is_ok());
+        assert!(Pattern::compile("{\\,,a}").is_ok());
+        // Commas outside of braces are literal
+        assert!(Pattern::compile(",a,,").is_ok());
+    }
+
+    #[test]
+    fn pattern_escapes() {
+        let matches = |pattern: &str, path: &[u8]| {
+            Pattern::compile(pattern)
+                .unwrap()
+                .is_match(&MatchPath::from_bytes(path))
+        };
+        assert!(matches("/docs/\\[draft\\].md", b"docs/[draft].md"));
+        assert!(!matches("/docs/\\[draft\\].md", b"docs/d.md"));
+        assert!(matches("\\{a,b\\}", b"{a,b}"));
+        assert!(!matches("\\{a,b\\}", b"a"));
+        assert!(matches("{\\,,b}", b","));
+        assert!(matches("\\*.rs", b"*.rs"));
+        assert!(!matches("\\*.rs", b"main.rs"));
+        assert!(matches("a\\\\b", b"a\\b"));
+        assert!(matches("logs/12\\:30", b"logs/12:30"));
+        // Escapes inside character classes
+        assert!(matches("[\\]x]", b"]"));
+        assert!(matches("[\\!a]", b"!"));
+        assert!(matches("[a\\-c]", b"-"));
+        assert!(!matches("[a\\-c]", b"b"));
+
+        assert_eq!(
+            compile_error("src\\"),
+            (PatternErrorKind::DanglingEscape, 3, 4)
+        );
+        assert_eq!(
+            compile_error("a\\/b"),
+            (PatternErrorKind::DanglingEscape, 1, 2)
+        );
+    }
+
+    #[test]
+    fn pattern_character_classes() {
+        let pattern = Pattern::compile("/src/[a-c]?.[!h]").unwrap();
+        assert!(pattern.is_match(&MatchPath::from_bytes(b"src/b1.c")));
+        assert!(!pattern.is_match(&MatchPath::from_bytes(b"src/d1.c")));
+        assert!(!pattern.is_match(&MatchPath::from_bytes(b"src/a1.h")));
+
+        let literal = Pattern::compile("[]x]").unwrap();
+        assert!(literal.is_match(&MatchPath::from_bytes(b"]")));
+        assert!(literal.is_match(&MatchPath::from_bytes(b"x")));
+
+        // Brackets and braces are matched literally inside classes
+        let literal = Pattern::compile("/docs/[[]draft[]]-[{]a,b[}].md").unwrap();
+        assert!(literal.is_match(&MatchPath::from_bytes(b"docs/[draft]-{a,b}.md")));
+    }
+
+    #[test]
+    fn pattern_brace_alternatives() {
+        let pattern = Pattern::compile("/{src,tests/unit}/*.{rs,to{ml,ml.in}}").unwrap();
+        assert_eq!(pattern.alternatives.len(), 6);
+        assert!(pattern.is_match(&MatchPath::from_bytes(b"src/main.rs")));
+        assert!(pattern.is_match(&MatchPath::from_bytes(b"tests/unit/a.toml.in")));
+        assert!(!pattern.is_match(&MatchPath::from_bytes(b"tests/a.rs")));
+        assert!(!pattern.is_match(&MatchPath::from_bytes(b"src/a.c")));
+
+        let pattern = Pattern::compile("{**,x}/b").unwrap();
+        assert!(pattern.is_match(&MatchPath::from_bytes(b"a/c/b")));
+        assert!(pattern.is_match(&MatchPath::from_bytes(b"x/b")));
+    }
+
+    #[test]
+    fn component_case_insensitive() {
+        assert!(test_match_ignore_case("README.md", "README.MD"));
+        assert!(test_match_ignore_case("makefile", "Makefile"));
+        assert!(test_match_ignore_case("*.RS", "main.rs"));
+        assert!(test_match_ignore_case("[A-C]?.txt", "b1.TXT"));
+        assert!(test_match_ignore_case("Œuvre-*", "œUVRE-1"));
+        assert!(test_match_ignore_case("straße", "STRASSE"));
+        assert!(test_match_ignore_case("STRASSE", "straße"));
+        // Wildcards and classes match whole characters of the text
+        assert!(test_match_ignore_case("stra?e.txt", "straße.txt"));
+        assert!(test_match_ignore_case("STRA?E.txt", "straẞe.txt"));
+        assert!(!test_match_ignore_case("stras?e.txt", "straße.txt"));
+        assert!(test_match_ignore_case("[ß]x.txt", "ßx.txt"));
+        assert!(test_match_ignore_case("[ß]x.txt", "ẞx.txt"));
+        assert!(test_match_ignore_case("[!ß]x.txt", "sx.txt"));
+        assert!(!test_match_ignore_case("[!ß]x.txt", "ẞx.txt"));
+        assert!(!test_match_ignore_case("readme.md", "readme.txt"));
+        assert!(!test_match_pattern_component("README.md", "README.MD"));
+    }
+
+    #[test]
+    fn pattern_case_insensitive() {
+        let pattern = Pattern::compile("/Docs/{README,Guide}.md").unwrap();
+        let text = MatchPath::from_bytes(b"docs/readme.MD");
+        assert!(!pattern.is_match(&text));
+        assert!(pattern.is_match_ignore_case(&text));
+
+        let text = MatchPath::from_bytes("ßx.txt".as_bytes());
+        assert!(
+            Pattern::compile("/[ß]x.txt")
+                .unwrap()
+                .is_match_ignore_case(&text)
+        );
+        let text = MatchPath::from_bytes("docs/straße.txt".as_bytes());
+        assert!(
+            Pattern::compile("stra?e.txt")
+                .unwrap()
+                .is_match_ignore_case(&text)
+        );
+    }
+
+    #[test]
+    fn match_non_utf8_paths() {
+        // "caf\xe9.txt", "café.txt" in Latin-1
+        let latin1 = MatchPath::from_bytes(b"docs/caf\xe9.txt");
+        assert!(Pattern::compile("docs/*.txt").unwrap().is_match(&latin1));
+        assert!(Pattern::compile("docs/caf?.txt").unwrap().is_match(&latin1));
+        assert!(
+            Pattern::compile("docs/caf[!a-z].txt")
+                .unwrap()
+                .is_match(&latin1)
+        );
+        assert!(
+            !Pattern::compile("docs/caf[a-\u{10ffff}].txt")
+                .unwrap()
+                .is_match(&latin1)
+        );
+        assert!(!Pattern::compile("docs/café.txt").unwrap().is_match(&latin1));
+        assert!(
+            Pattern::compile("DOCS/*.TXT")
+                .unwrap()
+                .is_match_ignore_case(&latin1)
+        );
+
+        // Valid UTF-8 around invalid bytes is still decoded
+        let mixed = MatchPath::from_bytes(b"\xff\xfe/\xc3\xa9t\xe9.rs");
+        assert!(Pattern::compile("/*/\u{e9}t?.rs").unwrap().is_match(&mixed));
+        assert!(
+            !Pattern::compile("/*/\u{e9}t??.rs")
+                .unwrap()
+                .is_match(&mixed)
+        );
+    }
 }
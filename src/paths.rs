@@ -0,0 +1,80 @@
+// Paths as sequences of bytes, as git stores them. They need not be valid
+// UTF-8, like Latin-1 names committed from old systems.
+
+use std::ffi::OsStr;
+use std::path::PathBuf;
+
+/// Convert a path to a path of the filesystem. Outside of Unix, bytes that
+/// are not valid UTF-8 cannot be represented and are replaced.
+pub fn to_path(path: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        PathBuf::from(OsStr::from_bytes(path))
+    }
+    #[cfg(not(unix))]
+    {
+        PathBuf::from(String::from_utf8_lossy(path).into_owned())
+    }
+}
+
+/// Get the bytes of a path of the filesystem
+pub fn from_os_str(path: &OsStr) -> Vec<u8> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        path.as_bytes().to_vec()
+    }
+    #[cfg(not(unix))]
+    {
+        path.to_string_lossy().into_owned().into_bytes()
+    }
+}
+
+/// Show a path in messages: bytes that are not valid UTF-8 are written as
+/// `\xNN` escapes
+pub fn display(path: &[u8]) -> String {
+    let mut text = String::new();
+    for chunk in path.utf8_chunks() {
+        text.push_str(chunk.valid());
+        for byte in chunk.invalid() {
+            text.push_str(&format!("\\x{:02x}", byte));
+        }
+    }
+    text
+}
+
+/// Split `text` at the first occurrence of `separator`
+pub fn split_once<'a>(text: &'a [u8], separator: &[u8]) -> Option<(&'a [u8], &'a [u8])> {
+    let start = text
+        .windows(separator.len())
+        .position(|window| window == separator)?;
+    Some((&text[..start], &text[start + separator.len()..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_paths() {
+        assert_eq!(display(b"docs/caf\xc3\xa9.md"), "docs/caf\u{e9}.md");
+        assert_eq!(display(b"docs/caf\xe9.md"), "docs/caf\\xe9.md");
+    }
+
+    #[test]
+    fn split_paths() {
+        assert_eq!(
+            split_once(b"a => b => c", b" => "),
+            Some((&b"a"[..], &b"b => c"[..]))
+        );
+        assert_eq!(split_once(b"a.rs", b" => "), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn round_trip_filesystem_paths() {
+        let path = b"caf\xe9/\xff.txt";
+        assert_eq!(from_os_str(to_path(path).as_os_str()), path);
+    }
+}
@@ -0,0 +1,570 @@
+// Minimal regular expressions, used to select regions of files. Regexes
+// compile to a program run by a Pike VM, which follows every alternative at
+// once: matching takes linear time in the length of the line and constant
+// stack space.
+//
+// Supported syntax:
+//  - literal characters, and "\" to escape special characters
+//  - "." matches any character
+//  - "[abc]", "[a-z]" and "[^abc]" match one character of a set
+//  - "\d", "\w", "\s" and their negations "\D", "\W", "\S"
+//  - "^" and "$" anchor at the start and end of the line
+//  - "*", "+", "?", "{n}", "{n,}" and "{n,m}" repeat the previous item,
+//    as many times as possible
+//  - "(...)" groups items and "|" separates alternatives
+//
+// A regex matches a line if it matches anywhere in it, unless anchored.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ClassItem {
+    Char(char),
+    Range(char, char),
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match *self {
+            ClassItem::Char(x) => c == x,
+            ClassItem::Range(low, high) => low <= c && c <= high,
+            ClassItem::Digit(negated) => c.is_ascii_digit() != negated,
+            ClassItem::Word(negated) => (c.is_alphanumeric() || c == '_') != negated,
+            ClassItem::Space(negated) => c.is_whitespace() != negated,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Any,
+    Class {
+        items: Vec<ClassItem>,
+        negated: bool,
+    },
+    Start,
+    End,
+    /// Alternatives, each a sequence of nodes
+    Group(Vec<Vec<Node>>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+    },
+}
+
+/// Error raised when a regex cannot be compiled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegexError {
+    /// Character offset of the error in the regex
+    pub position: usize,
+    /// What is wrong
+    pub message: String,
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for RegexError {}
+
+/// Instruction of the program a regex compiles to
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Inst {
+    /// Consume a character matching an `Any` or `Class` node
+    Char(Node),
+    /// Continue if at the start of the line
+    Start,
+    /// Continue if at the end of the line
+    End,
+    /// Continue at both instructions
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+/// Largest number of instructions of a program, which counted repetitions
+/// multiply
+const MAX_PROGRAM_LEN: usize = 100_000;
+
+/// A compiled regex
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Regex {
+    source: String,
+    program: Vec<Inst>,
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> RegexError {
+        RegexError {
+            position: self.pos,
+            message: message.to_string(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    /// Parse alternatives until the end of the regex or a closing parenthesis
+    fn alternatives(&mut self) -> Result<Vec<Vec<Node>>, RegexError> {
+        let mut alternatives = vec![self.sequence()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            alternatives.push(self.sequence()?);
+        }
+        Ok(alternatives)
+    }
+
+    fn sequence(&mut self) -> Result<Vec<Node>, RegexError> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let start = self.pos;
+            let node = self.atom()?;
+            let node = self.repetition(node, start)?;
+            nodes.push(node);
+        }
+        Ok(nodes)
+    }
+
+    fn atom(&mut self) -> Result<Node, RegexError> {
+        let c = self.chars[self.pos];
+        self.pos += 1;
+        let node = match c {
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '(' => {
+                let alternatives = self.alternatives()?;
+                if self.peek() != Some(')') {
+                    return Err(self.error("unclosed group"));
+                }
+                self.pos += 1;
+                Node::Group(alternatives)
+            }
+            '[' => self.class()?,
+            '\\' => Node::Class {
+                items: vec![self.escape()?],
+                negated: false,
+            },
+            '*' | '+' | '?' | '{' => {
+                self.pos -= 1;
+                return Err(self.error("nothing to repeat"));
+            }
+            c => Node::Class {
+                items: vec![ClassItem::Char(c)],
+                negated: false,
+            },
+        };
+        Ok(node)
+    }
+
+    /// Parse the character following a backslash
+    fn escape(&mut self) -> Result<ClassItem, RegexError> {
+        let c = self
+            .peek()
+            .ok_or_else(|| self.error("trailing backslash"))?;
+        self.pos += 1;
+        Ok(match c {
+            'd' => ClassItem::Digit(false),
+            'D' => ClassItem::Digit(true),
+            'w' => ClassItem::Word(false),
+            'W' => ClassItem::Word(true),
+            's' => ClassItem::Space(false),
+            'S' => ClassItem::Space(true),
+            't' => ClassItem::Char('\t'),
+            c if c.is_ascii_alphanumeric() => {
+                self.pos -= 1;
+                return Err(self.error("unknown escape sequence"));
+            }
+            c => ClassItem::Char(c),
+        })
+    }
+
+    fn class(&mut self) -> Result<Node, RegexError> {
+        let start = self.pos - 1;
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut items = Vec::new();
+        loop {
+            let Some(c) = self.peek() else {
+                self.pos = start;
+                return Err(self.error("unclosed character class"));
+            };
+            self.pos += 1;
+            // A closing bracket first in the class is literal
+            let item = match c {
+                ']' if !items.is_empty() => break,
+                '\\' => self.escape()?,
+                c => ClassItem::Char(c),
+            };
+            let is_range =
+                self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']');
+            match item {
+                ClassItem::Char(low) if is_range => {
+                    self.pos += 1;
+                    let high = match self.chars[self.pos] {
+                        '\\' => {
+                            self.pos += 1;
+                            match self.escape()? {
+                                ClassItem::Char(c) => c,
+                                _ => return Err(self.error("invalid range in character class")),
+                            }
+                        }
+                        c => {
+                            self.pos += 1;
+                            c
+                        }
+                    };
+                    if high < low {
+                        return Err(self.error("invalid range in character class"));
+                    }
+                    items.push(ClassItem::Range(low, high));
+                }
+                item => items.push(item),
+            }
+        }
+        Ok(Node::Class { items, negated })
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .ok()
+    }
+
+    fn repetition(&mut self, node: Node, start: usize) -> Result<Node, RegexError> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                let brace = self.pos;
+                self.pos += 1;
+                let min = self.number();
+                let max = if self.peek() == Some(',') {
+                    self.pos += 1;
+                    self.number()
+                } else {
+                    min
+                };
+                let Some(min) = min.filter(|_| self.peek() == Some('}')) else {
+                    self.pos = brace;
+                    return Err(self.error("invalid repetition"));
+                };
+                if max.is_some_and(|max| max < min) {
+                    self.pos = brace;
+                    return Err(self.error("invalid repetition"));
+                }
+                (min, max)
+            }
+            _ => return Ok(node),
+        };
+        self.pos += 1;
+        if matches!(node, Node::Start | Node::End) {
+            self.pos = start;
+            return Err(self.error("nothing to repeat"));
+        }
+        if matches!(self.peek(), Some('*' | '+' | '?' | '{')) {
+            return Err(self.error("nested repetition"));
+        }
+        Ok(Node::Repeat {
+            node: Box::new(node),
+            min,
+            max,
+        })
+    }
+}
+
+/// Whether a character matches an `Any` or `Class` node
+fn char_matches(node: &Node, c: char) -> bool {
+    match node {
+        Node::Class { items, negated } => items.iter().any(|item| item.matches(c)) != *negated,
+        _ => true,
+    }
+}
+
+/// Append the instructions matching `node` to `program`. Fails when the
+/// program grows too large.
+fn compile_node(node: &Node, program: &mut Vec<Inst>) -> Result<(), ()> {
+    if program.len() > MAX_PROGRAM_LEN {
+        return Err(());
+    }
+    match node {
+        Node::Any | Node::Class { .. } => program.push(Inst::Char(node.clone())),
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::Group(alternatives) => {
+            // Each alternative but the last is preceded by a split to the
+            // next one, and followed by a jump to the end of the group
+            let mut jumps = Vec::new();
+            for (i, alternative) in alternatives.iter().enumerate() {
+                let last = i + 1 == alternatives.len();
+                let split = program.len();
+                if !last {
+                    program.push(Inst::Split(split + 1, 0));
+                }
+                for node in alternative {
+                    compile_node(node, program)?;
+                }
+                if !last {
+                    jumps.push(program.len());
+                    program.push(Inst::Jump(0));
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+            }
+            let end = program.len();
+            for jump in jumps {
+                program[jump] = Inst::Jump(end);
+            }
+        }
+        Node::Repeat { node, min, max } => {
+            for _ in 0..*min {
+                compile_node(node, program)?;
+            }
+            match max {
+                None => {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile_node(node, program)?;
+                    program.push(Inst::Jump(split));
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+                Some(max) => {
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Split(program.len() + 1, 0));
+                        compile_node(node, program)?;
+                    }
+                    let end = program.len();
+                    for split in splits {
+                        program[split] = Inst::Split(split + 1, end);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The threads of the VM at a position of the text: the instructions
+/// consuming the next character, or matching
+struct Threads {
+    pcs: Vec<usize>,
+    /// Generation in which each instruction was last added
+    seen: Vec<usize>,
+    generation: usize,
+    /// Instructions left to follow while adding a thread
+    stack: Vec<usize>,
+}
+
+impl Threads {
+    fn new(program_len: usize) -> Self {
+        Threads {
+            pcs: Vec::new(),
+            seen: vec![0; program_len],
+            generation: 1,
+            stack: Vec::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.pcs.clear();
+        self.generation += 1;
+    }
+
+    /// Add the thread at `pc`, following the jumps, splits and assertions
+    /// holding at `pos` in a text of `len` characters
+    fn add(&mut self, program: &[Inst], pc: usize, pos: usize, len: usize) {
+        self.stack.push(pc);
+        while let Some(pc) = self.stack.pop() {
+            if self.seen[pc] == self.generation {
+                continue;
+            }
+            self.seen[pc] = self.generation;
+            match program[pc] {
+                Inst::Jump(to) => self.stack.push(to),
+                Inst::Split(first, second) => {
+                    self.stack.push(second);
+                    self.stack.push(first);
+                }
+                Inst::Start if pos == 0 => self.stack.push(pc + 1),
+                Inst::End if pos == len => self.stack.push(pc + 1),
+                Inst::Start | Inst::End => {}
+                Inst::Char(_) | Inst::Match => self.pcs.push(pc),
+            }
+        }
+    }
+}
+
+impl Regex {
+    /// Compile a regex
+    pub fn compile(regex: &str) -> Result<Self, RegexError> {
+        let mut parser = Parser {
+            chars: regex.chars().collect(),
+            pos: 0,
+        };
+        let alternatives = parser.alternatives()?;
+        if parser.pos < parser.chars.len() {
+            return Err(parser.error("unmatched closing parenthesis"));
+        }
+        let mut program = Vec::new();
+        if compile_node(&Node::Group(alternatives), &mut program).is_err() {
+            parser.pos = 0;
+            return Err(parser.error("regex too large"));
+        }
+        program.push(Inst::Match);
+        Ok(Regex {
+            source: regex.to_string(),
+            program,
+        })
+    }
+
+    /// The regex as written
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether the regex matches anywhere in `text`
+    pub fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        let program = &self.program;
+        let mut current = Threads::new(program.len());
+        let mut next = Threads::new(program.len());
+        current.add(program, 0, 0, text.len());
+        for (pos, &c) in text.iter().enumerate() {
+            if current.pcs.iter().any(|&pc| program[pc] == Inst::Match) {
+                return true;
+            }
+            next.clear();
+            for &pc in &current.pcs {
+                if let Inst::Char(node) = &program[pc]
+                    && char_matches(node, c)
+                {
+                    next.add(program, pc + 1, pos + 1, text.len());
+                }
+            }
+            // A match may also start after this character
+            next.add(program, 0, pos + 1, text.len());
+            std::mem::swap(&mut current, &mut next);
+        }
+        current.pcs.iter().any(|&pc| program[pc] == Inst::Match)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(regex: &str, text: &str) -> bool {
+        Regex::compile(regex).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn literals_and_anchors() {
+        assert!(is_match("dep", "[dependencies]"));
+        assert!(is_match(r"^\[dependencies\]", "[dependencies]"));
+        assert!(!is_match(r"^\[dependencies\]", "[dev-dependencies]"));
+        assert!(is_match(r"^\[", "[package]"));
+        assert!(!is_match(r"^\[", " [package]"));
+        assert!(is_match("end$", "the end"));
+        assert!(!is_match("end$", "the end."));
+        assert!(is_match("^$", ""));
+        assert!(is_match("", "anything"));
+        assert!(is_match("a.c", "abc"));
+        assert!(!is_match("a.c", "ac"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(is_match("^[a-c]+$", "abcab"));
+        assert!(!is_match("^[a-c]+$", "abcd"));
+        assert!(is_match("^[^#]", "x = 1"));
+        assert!(!is_match("^[^#]", "# comment"));
+        assert!(is_match(r"^\s*\w+\s*=\s*\d+$", "  version = 12"));
+        assert!(!is_match(r"^\d", "v1"));
+        assert!(is_match("[]]", "]"));
+        assert!(is_match("[a-]", "-"));
+        assert!(is_match(r"[\]x]", "]"));
+    }
+
+    #[test]
+    fn repetitions_and_groups() {
+        assert!(is_match("^a*b$", "b"));
+        assert!(is_match("^a*b$", "aaab"));
+        assert!(is_match("^a+b$", "ab"));
+        assert!(!is_match("^a+b$", "b"));
+        assert!(is_match("^colou?r$", "color"));
+        assert!(is_match("^a{2,3}$", "aaa"));
+        assert!(!is_match("^a{2,3}$", "aaaa"));
+        assert!(is_match("^a{2}$", "aa"));
+        assert!(is_match("^a{2,}$", "aaaaa"));
+        assert!(is_match("^(ab)+$", "ababab"));
+        assert!(!is_match("^(ab)+$", "aba"));
+        assert!(is_match(
+            r"^\[(dev-|build-)?dependencies\]$",
+            "[build-dependencies]"
+        ));
+        assert!(is_match("^(a|ab)c$", "abc"));
+        assert!(is_match("^(a*)*$", "aaa"));
+        assert!(is_match("^.*x.*$", "abxcd"));
+        assert!(is_match("^(a?){3}a{3}$", "aaa"));
+        assert!(is_match("^(|a)b$", "b"));
+    }
+
+    #[test]
+    fn long_lines() {
+        // Matching neither recurses nor backtracks for each character
+        let line = format!("a{}z", "b".repeat(200_000));
+        assert!(is_match("a.*z", &line));
+        assert!(is_match("^a(b|c)*z$", &line));
+        assert!(!is_match("a.*y", &line));
+        assert!(!is_match("(b*)*y", &line));
+    }
+
+    #[test]
+    fn invalid_regexes() {
+        for (regex, position) in [
+            ("a(b", 3),
+            ("a)b", 1),
+            ("[abc", 0),
+            ("*a", 0),
+            ("a{2", 1),
+            ("a{3,2}", 1),
+            ("a**", 2),
+            ("a\\", 2),
+            ("\\q", 1),
+            ("[z-a]", 4),
+            ("((a{1000}){1000}){1000}", 0),
+        ] {
+            assert_eq!(
+                Regex::compile(regex).unwrap_err().position,
+                position,
+                "{}",
+                regex
+            );
+        }
+    }
+}
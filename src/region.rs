@@ -0,0 +1,379 @@
+// Regions of files selected by line-range rules, like
+// "Cargo.toml:/^\[dependencies\]/,/^\[/" or "src/main.rs:10-20"
+
+use crate::diff::LineChanges;
+use crate::regex::Regex;
+use std::fmt;
+use std::ops::Range;
+
+/// A region of a file
+#[derive(Debug, Clone)]
+pub enum Region {
+    /// Lines `start` to `end` included, to the end of the file if `end` is
+    /// None
+    Lines {
+        /// First line, from 1
+        start: usize,
+        /// Last line
+        end: Option<usize>,
+    },
+    /// Lines matching `start`. When `end` is set, each line matching `start`
+    /// begins a block, which ends before the next line matching `end` or at
+    /// the end of the file.
+    Regex {
+        /// Regex matching the lines, or the first lines of the blocks
+        start: Regex,
+        /// Regex matching the lines following the blocks
+        end: Option<Regex>,
+    },
+}
+
+/// Error raised when a region cannot be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionError {
+    /// Character offsets of the offending span in the region
+    pub start: usize,
+    /// Offset following the offending span
+    pub end: usize,
+    /// What is wrong
+    pub message: String,
+}
+
+/// Parse a regex delimited by slashes starting at `start`, where "\/" stands
+/// for a slash. Returns the regex and the offset following it.
+fn parse_regex(chars: &[char], start: usize) -> Result<(Regex, usize), RegionError> {
+    let mut source = String::new();
+    let mut pos = start + 1;
+    let mut in_class = false;
+    loop {
+        let Some(&c) = chars.get(pos) else {
+            return Err(RegionError {
+                start,
+                end: chars.len(),
+                message: "missing closing '/'".to_string(),
+            });
+        };
+        pos += 1;
+        match c {
+            '/' if !in_class => break,
+            '\\' if chars.get(pos) == Some(&'/') => {
+                source.push('/');
+                pos += 1;
+                continue;
+            }
+            '\\' => {
+                source.push(c);
+                if let Some(&next) = chars.get(pos) {
+                    source.push(next);
+                    pos += 1;
+                }
+                continue;
+            }
+            // A closing bracket first in a class is literal
+            '[' if !in_class => in_class = !matches!(chars.get(pos), Some(']')),
+            ']' => in_class = false,
+            _ => {}
+        }
+        source.push(c);
+    }
+    let regex = Regex::compile(&source).map_err(|e| RegionError {
+        start,
+        end: pos,
+        message: format!("invalid regex: {}", e),
+    })?;
+    Ok((regex, pos))
+}
+
+/// Parse a line number, from 1
+fn parse_line(text: &str) -> Option<usize> {
+    text.parse().ok().filter(|&line| line > 0)
+}
+
+/// Whether two ranges of lines overlap
+fn overlaps(a: &Range<usize>, b: &Range<usize>) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+impl Region {
+    /// Parse a region: "N", "N-M", "N-", "/regex/" or "/start/,/end/"
+    pub fn parse(text: &str) -> Result<Self, RegionError> {
+        let chars: Vec<char> = text.chars().collect();
+        let error = |message: &str| RegionError {
+            start: 0,
+            end: chars.len(),
+            message: message.to_string(),
+        };
+
+        if chars.first() != Some(&'/') {
+            let (start, end) = match text.split_once('-') {
+                Some((start, "")) => (parse_line(start), None),
+                Some((start, end)) => (parse_line(start), Some(parse_line(end))),
+                None => (parse_line(text), Some(parse_line(text))),
+            };
+            return match (start, end) {
+                (Some(start), None) => Ok(Region::Lines { start, end: None }),
+                (Some(start), Some(Some(end))) if start <= end => Ok(Region::Lines {
+                    start,
+                    end: Some(end),
+                }),
+                (Some(_), Some(Some(_))) => Err(error("end line is before start line")),
+                _ => Err(error("invalid line range")),
+            };
+        }
+
+        let (start, pos) = parse_regex(&chars, 0)?;
+        let end = match chars.get(pos) {
+            None => None,
+            Some(',') if chars.get(pos + 1) == Some(&'/') => {
+                let (end, end_pos) = parse_regex(&chars, pos + 1)?;
+                if end_pos < chars.len() {
+                    return Err(RegionError {
+                        start: end_pos,
+                        end: chars.len(),
+                        message: "unexpected text after region".to_string(),
+                    });
+                }
+                Some(end)
+            }
+            Some(_) => {
+                return Err(RegionError {
+                    start: pos,
+                    end: chars.len(),
+                    message: "expected ',/regex/' after region start".to_string(),
+                });
+            }
+        };
+        Ok(Region::Regex { start, end })
+    }
+
+    /// Whether the file contents are needed to locate the region
+    pub(crate) fn needs_contents(&self) -> bool {
+        matches!(self, Region::Regex { .. })
+    }
+
+    /// Ranges of 1-based line numbers selected in a version of a file
+    fn select(&self, lines: &[String]) -> Vec<Range<usize>> {
+        match self {
+            Region::Lines { start, end } => {
+                let lines = *start..end.map_or(usize::MAX, |end| end + 1);
+                vec![lines]
+            }
+            Region::Regex { start, end: None } => (1..=lines.len())
+                .filter(|&line| start.is_match(&lines[line - 1]))
+                .map(|line| line..line + 1)
+                .collect(),
+            Region::Regex {
+                start,
+                end: Some(end),
+            } => {
+                let mut ranges = Vec::new();
+                let mut line = 0;
+                while line < lines.len() {
+                    if !start.is_match(&lines[line]) {
+                        line += 1;
+                        continue;
+                    }
+                    let block_end = (line + 1..lines.len())
+                        .find(|&next| end.is_match(&lines[next]))
+                        .unwrap_or(lines.len());
+                    ranges.push(line + 1..block_end + 1);
+                    line = block_end;
+                }
+                ranges
+            }
+        }
+    }
+
+    /// Whether some changed lines lie in the region, in the old version of
+    /// the file for removed lines and in the new one for added lines
+    pub(crate) fn intersects(&self, lines: &LineChanges) -> bool {
+        let (old, new) = match &lines.contents {
+            Some(contents) => (self.select(&contents.old), self.select(&contents.new)),
+            // Line ranges do not depend on the contents
+            None if !self.needs_contents() => (self.select(&[]), self.select(&[])),
+            None => return true,
+        };
+        lines.changes.iter().any(|change| {
+            old.iter().any(|range| overlaps(range, &change.old))
+                || new.iter().any(|range| overlaps(range, &change.new))
+        })
+    }
+}
+
+/// Write a regex between slashes, escaping its slashes
+fn write_regex(f: &mut fmt::Formatter, regex: &Regex) -> fmt::Result {
+    write!(f, "/{}/", regex.as_str().replace('/', "\\/"))
+}
+
+/// Write the region in the syntax it is parsed from
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Region::Lines { start, end: None } => write!(f, "{}-", start),
+            Region::Lines {
+                start,
+                end: Some(end),
+            } if start == end => write!(f, "{}", start),
+            Region::Lines {
+                start,
+                end: Some(end),
+            } => write!(f, "{}-{}", start, end),
+            Region::Regex { start, end } => {
+                write_regex(f, start)?;
+                if let Some(end) = end {
+                    f.write_str(",")?;
+                    write_regex(f, end)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Region {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::{Change, Contents};
+
+    fn contents(old: &str, new: &str) -> Contents {
+        Contents {
+            old: old.lines().map(String::from).collect(),
+            new: new.lines().map(String::from).collect(),
+        }
+    }
+
+    const CARGO: &str = "[package]
+name = \"demo\"
+version = \"1.0.0\"
+
+[dependencies]
+serde = \"1\"
+
+[dev-dependencies]
+criterion = \"0.5\"
+";
+
+    #[test]
+    fn parse_regions() {
+        assert!(matches!(
+            Region::parse("10-20"),
+            Ok(Region::Lines {
+                start: 10,
+                end: Some(20)
+            })
+        ));
+        assert!(matches!(
+            Region::parse("7"),
+            Ok(Region::Lines {
+                start: 7,
+                end: Some(7)
+            })
+        ));
+        assert!(matches!(
+            Region::parse("7-"),
+            Ok(Region::Lines {
+                start: 7,
+                end: None
+            })
+        ));
+        assert!(matches!(
+            Region::parse(r"/^\[dependencies\]/,/^\[/"),
+            Ok(Region::Regex { end: Some(_), .. })
+        ));
+        assert!(matches!(
+            Region::parse(r"/version = /"),
+            Ok(Region::Regex { end: None, .. })
+        ));
+        assert!(matches!(
+            Region::parse(r"/a\/b[/]/"),
+            Ok(Region::Regex { end: None, .. })
+        ));
+
+        for (region, start, end) in [
+            ("0", 0, 1),
+            ("20-10", 0, 5),
+            ("1-x", 0, 3),
+            ("/abc", 0, 4),
+            ("/a(/", 0, 4),
+            ("/a/b", 3, 4),
+            ("/a/,/b/c", 7, 8),
+        ] {
+            let e = Region::parse(region).unwrap_err();
+            assert_eq!((e.start, e.end), (start, end), "{}", region);
+        }
+    }
+
+    #[test]
+    fn display_regions() {
+        for region in [
+            "7",
+            "10-20",
+            "7-",
+            r"/^\[dependencies\]/,/^\[/",
+            r"/a\/b[\/]/",
+        ] {
+            assert_eq!(Region::parse(region).unwrap().to_string(), region);
+        }
+        assert_eq!(Region::parse("3-3").unwrap().to_string(), "3");
+    }
+
+    #[test]
+    fn select_blocks() {
+        let region = Region::parse(r"/^\[(dev-)?dependencies\]/,/^\[/").unwrap();
+        let lines = contents("", CARGO).new;
+        assert_eq!(region.select(&lines), vec![5..8, 8..10]);
+
+        let region = Region::parse(r"/^\[dependencies\]/,/^\[/").unwrap();
+        assert_eq!(region.select(&lines), vec![5..8]);
+        let region = Region::parse("/^version/").unwrap();
+        assert_eq!(region.select(&lines), vec![3..4]);
+    }
+
+    #[test]
+    fn intersect_changes() {
+        let region = Region::parse(r"/^\[dependencies\]/,/^\[/").unwrap();
+        let bumped = CARGO.replace("1.0.0", "1.0.1");
+        let version_bump = LineChanges {
+            changes: vec![Change {
+                old: 3..4,
+                new: 3..4,
+            }],
+            modified: true,
+            contents: Some(contents(CARGO, &bumped)),
+        };
+        assert!(!region.intersects(&version_bump));
+        assert!(Region::parse("1-3").unwrap().intersects(&version_bump));
+        assert!(!Region::parse("4-").unwrap().intersects(&version_bump));
+
+        // Removing the last dependency: the removed line is only in the
+        // region of the old version
+        let removed = CARGO.replace("serde = \"1\"\n", "");
+        let removal = LineChanges {
+            changes: vec![Change {
+                old: 6..7,
+                new: 6..6,
+            }],
+            modified: true,
+            contents: Some(contents(CARGO, &removed)),
+        };
+        assert!(region.intersects(&removal));
+
+        let added = CARGO.replace("[dependencies]\n", "[dependencies]\nrand = \"0.8\"\n");
+        let addition = LineChanges {
+            changes: vec![Change {
+                old: 6..6,
+                new: 6..7,
+            }],
+            modified: true,
+            contents: Some(contents(CARGO, &added)),
+        };
+        assert!(region.intersects(&addition));
+    }
+}
@@ -0,0 +1,321 @@
+// Template sections like "[svc-{name}]", whose patterns use "{name}" for a
+// path component, expanded into one group per directory in its place
+
+use crate::config::{Config, Group, Rule};
+use crate::diff::ChangedFiles;
+use crate::matching::{MatchPath, Pattern};
+use crate::paths;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Characters of directory names that would change the meaning of the
+/// patterns they are substituted in
+const SPECIAL_CHARS: [char; 8] = ['*', '?', '[', ']', '{', '}', ',', '\\'];
+
+/// Where the placeholder stands in a pattern
+struct Position<'a> {
+    /// Components of the pattern before the placeholder
+    prefix: Vec<&'a str>,
+    /// Whether the placeholder is the last component, so that it may also
+    /// stand for a file
+    last: bool,
+}
+
+impl<'a> Position<'a> {
+    fn find(pattern: &'a str, token: &str) -> Option<Self> {
+        let components: Vec<&str> = pattern.split('/').collect();
+        let index = components.iter().position(|c| *c == token)?;
+        Some(Position {
+            prefix: components[..index].to_vec(),
+            last: index + 1 == components.len(),
+        })
+    }
+}
+
+fn is_match(pattern: &Pattern, path: &[u8], ignore_case: bool) -> bool {
+    let path = MatchPath::from_bytes(path);
+    if ignore_case {
+        pattern.is_match_ignore_case(&path)
+    } else {
+        pattern.is_match(&path)
+    }
+}
+
+/// The value of the placeholder for a directory name, unless it is hidden,
+/// not valid UTF-8 or holds pattern syntax
+fn value(name: &[u8]) -> Option<String> {
+    let name = std::str::from_utf8(name).ok()?;
+    let valid = !name.is_empty() && !name.starts_with('.') && !name.contains(SPECIAL_CHARS);
+    valid.then(|| name.to_string())
+}
+
+/// Add the values of the placeholder for the directories of the changed
+/// files
+fn values_in_paths(
+    position: &Position,
+    changed_files: &ChangedFiles,
+    ignore_case: bool,
+    values: &mut BTreeSet<String>,
+) {
+    // The prefix followed by the placeholder, to match the leading
+    // components of the paths like the pattern does
+    let prefix = if position.prefix.is_empty() {
+        None
+    } else {
+        match Pattern::compile(&format!("{}/*", position.prefix.join("/"))) {
+            Ok(prefix) => Some(prefix),
+            Err(_) => return,
+        }
+    };
+    for file in &changed_files.files {
+        let components: Vec<&[u8]> = file.name.split(|&b| b == b'/').collect();
+        for (i, component) in components.iter().enumerate() {
+            if i + 1 == components.len() && !position.last {
+                break;
+            }
+            let matches = match &prefix {
+                Some(prefix) => is_match(prefix, &components[..=i].join(&b'/'), ignore_case),
+                None => i == 0,
+            };
+            if matches && let Some(value) = value(component) {
+                values.insert(value);
+            }
+        }
+    }
+}
+
+/// Subdirectories of a directory, by name
+fn subdirs(dir: &Path) -> Vec<(Vec<u8>, PathBuf)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .map(|entry| (paths::from_os_str(&entry.file_name()), entry.path()))
+        .collect()
+}
+
+/// Add the values of the placeholder for the directories of the tree at
+/// `root`. Prefixes with "**" are not searched.
+fn values_in_tree(
+    position: &Position,
+    root: &Path,
+    ignore_case: bool,
+    values: &mut BTreeSet<String>,
+) {
+    let mut dirs = vec![root.to_path_buf()];
+    // The prefix of an absolute pattern starts with an empty component
+    for component in position.prefix.iter().filter(|c| !c.is_empty()) {
+        if *component == "**" {
+            return;
+        }
+        let Ok(pattern) = Pattern::compile(component) else {
+            return;
+        };
+        dirs = dirs
+            .iter()
+            .flat_map(|dir| subdirs(dir))
+            .filter(|(name, _)| is_match(&pattern, name, ignore_case))
+            .map(|(_, path)| path)
+            .collect();
+    }
+    for dir in dirs {
+        values.extend(subdirs(&dir).iter().filter_map(|(name, _)| value(name)));
+    }
+}
+
+/// The group of a template for a value of its placeholder
+fn instantiate(template: &Group, token: &str, value: &str, name: String) -> Group {
+    let mut group = template.clone();
+    group.name = name;
+    group.placeholder = None;
+    group.patterns.rules = template
+        .patterns
+        .iter()
+        .filter_map(|rule| {
+            let source = rule.pattern.as_str().replace(token, value);
+            let pattern = Pattern::compile(&source);
+            // Values hold no pattern syntax, so the patterns stay valid
+            debug_assert!(pattern.is_ok(), "invalid pattern '{}'", source);
+            Some(Rule {
+                pattern: pattern.ok()?,
+                region: rule.region.clone(),
+            })
+        })
+        .collect();
+    for (_, v) in &mut group.metadata {
+        *v = v.replace(token, value);
+    }
+    for dependency in &mut group.depends_on {
+        *dependency = dependency.replace(token, value);
+    }
+    group
+}
+
+impl Config {
+    /// Replace each template section by one group per value of its
+    /// placeholder, in its place and sorted by value. The values are the
+    /// names of the directories standing where the placeholder does in the
+    /// patterns of the section: among the changed files, and in the tree
+    /// at `root` if given. The placeholder is also replaced in the metadata
+    /// and `depends-on` options of the section.
+    ///
+    /// A section declared with the name of a group of a template takes
+    /// precedence over it, and groups depending on a template depend on
+    /// all of its groups.
+    pub fn expand_templates(
+        &mut self,
+        changed_files: &ChangedFiles,
+        root: Option<&Path>,
+        ignore_case: bool,
+    ) {
+        if self.groups.iter().all(|g| g.placeholder.is_none()) {
+            return;
+        }
+        let mut groups: Vec<Group> = Vec::new();
+        // Names of the groups of each template
+        let mut expanded: Vec<(String, Vec<String>)> = Vec::new();
+        for group in &self.groups {
+            let Some(placeholder) = &group.placeholder else {
+                groups.push(group.clone());
+                continue;
+            };
+            let token = format!("{{{}}}", placeholder);
+            let ignore_case = group.case_insensitive.unwrap_or(ignore_case);
+            let mut values = BTreeSet::new();
+            for rule in &group.patterns {
+                let Some(position) = Position::find(rule.pattern.as_str(), &token) else {
+                    continue;
+                };
+                values_in_paths(&position, changed_files, ignore_case, &mut values);
+                if let Some(root) = root {
+                    values_in_tree(&position, root, ignore_case, &mut values);
+                }
+            }
+            let mut names = Vec::new();
+            for value in values {
+                let name = group.name.replace(&token, &value);
+                if self.contains(&name) || groups.iter().any(|g| g.name == name) {
+                    continue;
+                }
+                groups.push(instantiate(group, &token, &value, name.clone()));
+                names.push(name);
+            }
+            expanded.push((group.name.clone(), names));
+        }
+        for group in &mut groups {
+            let depends_on = std::mem::take(&mut group.depends_on);
+            for dependency in depends_on {
+                match expanded
+                    .iter()
+                    .find(|(template, _)| *template == dependency)
+                {
+                    Some((_, names)) => group.depends_on.extend(names.iter().cloned()),
+                    None => group.depends_on.push(dependency),
+                }
+            }
+        }
+        self.groups = groups;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::PathNormalization;
+
+    fn expand(content: &str, paths: &[&str], root: Option<&Path>) -> Config {
+        let mut config = Config::parse(content).unwrap();
+        let changed_files =
+            ChangedFiles::from_paths(paths.iter().copied(), &PathNormalization::default()).unwrap();
+        config.expand_templates(&changed_files, root, false);
+        config
+    }
+
+    fn names(config: &Config) -> Vec<&str> {
+        config.groups.iter().map(|g| g.name.as_str()).collect()
+    }
+
+    #[test]
+    fn expand_from_paths() {
+        let content = "[docs]\ndocs/**\n\
+                       [svc-{name}]\nmeta.dir = services/{name}\n\
+                       services/{name}/**\nservices/{name}/Cargo.toml:1-10\n\
+                       [svc-legacy]\nlegacy/**\n\
+                       [all]\ndepends-on = svc-{name}\n";
+        let config = expand(
+            content,
+            &[
+                "services/web/index.ts",
+                "services/api/src/main.rs",
+                "services/README.md",
+                "services/.hidden/x",
+                "services/legacy/old.c",
+                "services/a*b/x",
+                "other/services/db/x",
+            ],
+            None,
+        );
+        // Like the patterns, the prefix may match at any depth
+        assert_eq!(
+            names(&config),
+            ["docs", "svc-api", "svc-db", "svc-web", "svc-legacy", "all"]
+        );
+        let api = &config["svc-api"];
+        assert_eq!(api.placeholder, None);
+        assert_eq!(
+            api.metadata,
+            [("dir".to_string(), "services/api".to_string())]
+        );
+        let patterns: Vec<&str> = api.patterns.iter().map(|r| r.pattern.as_str()).collect();
+        assert_eq!(patterns, ["services/api/**", "services/api/Cargo.toml"]);
+        assert!(api.patterns[1].region.is_some());
+        assert_eq!(
+            config["svc-legacy"].patterns[0].pattern.as_str(),
+            "legacy/**"
+        );
+        assert_eq!(config["all"].depends_on, ["svc-api", "svc-db", "svc-web"]);
+
+        // A placeholder ending the pattern also stands for files
+        let config = expand(
+            "[{name}]\ntools/*/{name}\n",
+            &["tools/a/b", "tools/c"],
+            None,
+        );
+        assert_eq!(names(&config), ["b"]);
+        // Templates without values leave no group
+        let config = expand(content, &["docs/index.md"], None);
+        assert_eq!(names(&config), ["docs", "svc-legacy", "all"]);
+        assert!(config["all"].depends_on.is_empty());
+    }
+
+    #[test]
+    fn expand_from_tree() {
+        let root = std::env::temp_dir().join(format!(
+            "detect-changed-files-templates-{}",
+            std::process::id()
+        ));
+        for dir in [
+            "services/api/src",
+            "services/web",
+            "services/.cache",
+            "apps/x/y",
+        ] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        fs::write(root.join("services/README.md"), "").unwrap();
+
+        let content = "[svc-{name}]\nservices/{name}/**\n[deep-{name}]\n**/{name}/Makefile\n";
+        let config = expand(content, &["services/db/schema.sql"], Some(&root));
+        // Prefixes with "**" only take values from the changed files
+        assert_eq!(names(&config), ["svc-api", "svc-db", "svc-web", "deep-db"]);
+        let config = expand("[{app}]\n*/{app}/**\n", &[], Some(&root));
+        assert_eq!(names(&config), ["api", "web", "x"]);
+        let config = expand("[{app}]\n/apps/{app}/**\n", &[], Some(&root));
+        assert_eq!(names(&config), ["x"]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
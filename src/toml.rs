@@ -0,0 +1,581 @@
+// Minimal TOML reader, enough to read Cargo manifests
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Array(Vec<Value>),
+    /// Keys are kept in the order they are written
+    Table(Vec<(String, Value)>),
+    /// Numbers, booleans and dates are kept as written, they are not needed
+    /// as such
+    Other(String),
+}
+
+impl Value {
+    /// Get a key of a table
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Table(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Follow a path of table keys
+    pub fn pointer(&self, keys: &[&str]) -> Option<&Value> {
+        keys.iter().try_fold(self, |value, key| value.get(key))
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Entries of a table, empty for other values
+    pub fn entries(&self) -> &[(String, Value)] {
+        match self {
+            Value::Table(entries) => entries,
+            _ => &[],
+        }
+    }
+
+    /// Strings of an array, other values being skipped
+    pub fn strings(&self) -> Vec<&str> {
+        match self {
+            Value::Array(items) => items.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TomlError {
+    /// 1-based line number of the error
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for TomlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid TOML at line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for TomlError {}
+
+/// Parse a TOML document into its root table
+pub fn parse(input: &str) -> Result<Value, TomlError> {
+    let mut parser = Parser {
+        input: input.as_bytes(),
+        pos: 0,
+    };
+    let mut root = Value::Table(Vec::new());
+    // Keys of the table that key/value pairs go to
+    let mut current: Vec<String> = Vec::new();
+    loop {
+        parser.skip_blank();
+        match parser.peek() {
+            None => return Ok(root),
+            Some(b'[') => {
+                parser.pos += 1;
+                let array = parser.peek() == Some(b'[');
+                if array {
+                    parser.pos += 1;
+                }
+                let (mut keys, last) = parser.keys()?;
+                parser.expect(b']')?;
+                if array {
+                    parser.expect(b']')?;
+                    let parent = parser.table_at(&mut root, &keys)?;
+                    match entry(parent, &last, || Value::Array(Vec::new())) {
+                        Value::Array(items) => items.push(Value::Table(Vec::new())),
+                        _ => return parser.error(&format!("'{}' is not an array", last)),
+                    }
+                }
+                keys.push(last);
+                parser.table_at(&mut root, &keys)?;
+                current = keys;
+            }
+            Some(_) => {
+                let (keys, last) = parser.keys()?;
+                parser.expect(b'=')?;
+                let value = parser.value()?;
+                let path: Vec<String> = current.iter().chain(&keys).cloned().collect();
+                let table = parser.table_at(&mut root, &path)?;
+                *entry(table, &last, || Value::Table(Vec::new())) = value;
+            }
+        }
+        parser.skip_spaces();
+        match parser.peek() {
+            None | Some(b'\n' | b'\r' | b'#') => {}
+            Some(_) => return parser.error("expected the end of the line"),
+        }
+    }
+}
+
+/// Get the value of a key of a table, inserting `default()` if the key is
+/// missing. Other values than tables are returned as they are.
+fn entry<'a>(table: &'a mut Value, key: &str, default: impl FnOnce() -> Value) -> &'a mut Value {
+    let Value::Table(entries) = table else {
+        return table;
+    };
+    let index = match entries.iter().position(|(k, _)| k == key) {
+        Some(index) => index,
+        None => {
+            entries.push((key.to_string(), default()));
+            entries.len() - 1
+        }
+    };
+    &mut entries[index].1
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error<T>(&self, message: &str) -> Result<T, TomlError> {
+        let line = self.input[..self.pos.min(self.input.len())]
+            .iter()
+            .filter(|&&b| b == b'\n')
+            .count()
+            + 1;
+        Err(TomlError {
+            line,
+            message: message.to_string(),
+        })
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    /// Skip spaces and tabs
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t')) {
+            self.pos += 1;
+        }
+    }
+
+    /// Skip whitespace, line breaks and comments
+    fn skip_blank(&mut self) {
+        loop {
+            match self.peek() {
+                Some(b' ' | b'\t' | b'\n' | b'\r') => self.pos += 1,
+                Some(b'#') => {
+                    while !matches!(self.peek(), None | Some(b'\n')) {
+                        self.pos += 1;
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), TomlError> {
+        self.skip_spaces();
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            self.error(&format!("expected '{}'", byte as char))
+        }
+    }
+
+    /// Get the table at a path of keys from the root, creating the missing
+    /// ones. Arrays of tables lead to their last table.
+    fn table_at<'v>(
+        &self,
+        root: &'v mut Value,
+        keys: &[String],
+    ) -> Result<&'v mut Value, TomlError> {
+        let mut table = root;
+        for key in keys {
+            table = match entry(table, key, || Value::Table(Vec::new())) {
+                Value::Array(items) => match items.last_mut() {
+                    Some(last @ Value::Table(_)) => last,
+                    _ => return self.error(&format!("'{}' is not a table", key)),
+                },
+                value @ Value::Table(_) => value,
+                _ => return self.error(&format!("'{}' is not a table", key)),
+            };
+        }
+        Ok(table)
+    }
+
+    /// Read a dotted key, like `a.b."c"`, split into its parent keys and its
+    /// last key
+    fn keys(&mut self) -> Result<(Vec<String>, String), TomlError> {
+        let mut keys = Vec::new();
+        loop {
+            self.skip_spaces();
+            let key = match self.peek() {
+                Some(b'"') => self.basic_string()?,
+                Some(b'\'') => self.literal_string()?,
+                _ => {
+                    let start = self.pos;
+                    while matches!(
+                        self.peek(),
+                        Some(b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-')
+                    ) {
+                        self.pos += 1;
+                    }
+                    if self.pos == start {
+                        return self.error("expected a key");
+                    }
+                    String::from_utf8_lossy(&self.input[start..self.pos]).into_owned()
+                }
+            };
+            self.skip_spaces();
+            if self.peek() != Some(b'.') {
+                return Ok((keys, key));
+            }
+            keys.push(key);
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, TomlError> {
+        self.skip_spaces();
+        match self.peek() {
+            Some(b'"') => self.basic_string().map(Value::String),
+            Some(b'\'') => self.literal_string().map(Value::String),
+            Some(b'[') => self.array(),
+            Some(b'{') => self.inline_table(),
+            Some(b'\n' | b'\r' | b'#') | None => self.error("expected a value"),
+            Some(_) => {
+                let start = self.pos;
+                while !matches!(
+                    self.peek(),
+                    None | Some(b',' | b']' | b'}' | b'\n' | b'\r' | b'#')
+                ) {
+                    self.pos += 1;
+                }
+                let text = String::from_utf8_lossy(&self.input[start..self.pos]);
+                match text.trim_end() {
+                    "" => self.error("expected a value"),
+                    text => Ok(Value::Other(text.to_string())),
+                }
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, TomlError> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank();
+            if self.peek() == Some(b']') {
+                self.pos += 1;
+                return Ok(Value::Array(items));
+            }
+            items.push(self.value()?);
+            self.skip_blank();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {}
+                _ => return self.error("expected ',' or ']'"),
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Value, TomlError> {
+        self.pos += 1;
+        let mut table = Value::Table(Vec::new());
+        self.skip_spaces();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(table);
+        }
+        loop {
+            let (keys, last) = self.keys()?;
+            self.expect(b'=')?;
+            let value = self.value()?;
+            let parent = self.table_at(&mut table, &keys)?;
+            *entry(parent, &last, || Value::Table(Vec::new())) = value;
+            self.skip_spaces();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(table);
+                }
+                _ => return self.error("expected ',' or '}'"),
+            }
+        }
+    }
+
+    /// Read a string between double quotes, with escapes, or between
+    /// triple double quotes, spanning lines
+    fn basic_string(&mut self) -> Result<String, TomlError> {
+        let multiline = self.input[self.pos..].starts_with(b"\"\"\"");
+        self.pos += if multiline { 3 } else { 1 };
+        if multiline {
+            self.skip_newline();
+        }
+        let mut bytes = Vec::new();
+        loop {
+            match self.peek() {
+                None => return self.error("unterminated string"),
+                Some(b'"') if !multiline => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(b'"') if self.input[self.pos..].starts_with(b"\"\"\"") => {
+                    // Up to two quotes may end the content
+                    let mut end = self.pos + 3;
+                    while end < self.input.len() && self.input[end] == b'"' && end < self.pos + 5 {
+                        end += 1;
+                    }
+                    bytes.extend(&self.input[self.pos + 3..end]);
+                    self.pos = end;
+                    break;
+                }
+                Some(b'\n') if !multiline => return self.error("unterminated string"),
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let escaped = self.peek();
+                    self.pos += 1;
+                    match escaped {
+                        Some(b'b') => bytes.push(0x08),
+                        Some(b't') => bytes.push(b'\t'),
+                        Some(b'n') => bytes.push(b'\n'),
+                        Some(b'f') => bytes.push(0x0c),
+                        Some(b'r') => bytes.push(b'\r'),
+                        Some(b'"') => bytes.push(b'"'),
+                        Some(b'\\') => bytes.push(b'\\'),
+                        Some(b'u') => self.unicode_escape(4, &mut bytes)?,
+                        Some(b'U') => self.unicode_escape(8, &mut bytes)?,
+                        Some(b' ' | b'\t' | b'\n' | b'\r') if multiline => {
+                            // A backslash ending a line trims the whitespace
+                            // that follows
+                            self.pos -= 1;
+                            while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+                                self.pos += 1;
+                            }
+                        }
+                        _ => return self.error("invalid escape sequence"),
+                    }
+                }
+                Some(byte) => {
+                    bytes.push(byte);
+                    self.pos += 1;
+                }
+            }
+        }
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// Read the hexadecimal digits of a `\u` or `\U` escape
+    fn unicode_escape(&mut self, digits: usize, bytes: &mut Vec<u8>) -> Result<(), TomlError> {
+        let hex = self
+            .input
+            .get(self.pos..self.pos + digits)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .and_then(char::from_u32);
+        match hex {
+            Some(c) => {
+                self.pos += digits;
+                bytes.extend(c.to_string().as_bytes());
+                Ok(())
+            }
+            None => self.error("invalid unicode escape"),
+        }
+    }
+
+    /// Read a string between single quotes, without escapes, or between
+    /// triple single quotes, spanning lines
+    fn literal_string(&mut self) -> Result<String, TomlError> {
+        let multiline = self.input[self.pos..].starts_with(b"'''");
+        let delimiter: &[u8] = if multiline { b"'''" } else { b"'" };
+        self.pos += delimiter.len();
+        if multiline {
+            self.skip_newline();
+        }
+        let start = self.pos;
+        loop {
+            match self.peek() {
+                None => return self.error("unterminated string"),
+                Some(b'\n') if !multiline => return self.error("unterminated string"),
+                _ if self.input[self.pos..].starts_with(delimiter) => break,
+                _ => self.pos += 1,
+            }
+        }
+        let text = String::from_utf8_lossy(&self.input[start..self.pos]).into_owned();
+        self.pos += delimiter.len();
+        Ok(text)
+    }
+
+    /// Skip the line break right after the opening delimiter of a multiline
+    /// string
+    fn skip_newline(&mut self) {
+        if self.input[self.pos..].starts_with(b"\r\n") {
+            self.pos += 2;
+        } else if self.peek() == Some(b'\n') {
+            self.pos += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_manifest() {
+        let document = parse(
+            r#"
+# A manifest
+[package]
+name = "app"   # trailing comment
+version = '1.0.0'
+edition = 2024
+
+[dependencies]
+util = { path = "../util", features = ["a", "b"] }
+serde.workspace = true
+log = "0.4"
+
+[dependencies.core]
+path = '../core'
+
+[target.'cfg(unix)'.dev-dependencies]
+"test helpers" = { path = "../helpers" }
+
+[workspace]
+members = [
+    "crates/*",  # all crates
+    "tools/gen",
+]
+
+[[bin]]
+name = "first"
+[[bin]]
+name = "second"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            document
+                .pointer(&["package", "name"])
+                .and_then(Value::as_str),
+            Some("app")
+        );
+        assert_eq!(
+            document.pointer(&["package", "edition"]),
+            Some(&Value::Other("2024".to_string()))
+        );
+        let dependencies = document.get("dependencies").unwrap();
+        let names: Vec<&str> = dependencies
+            .entries()
+            .iter()
+            .map(|(k, _)| k.as_str())
+            .collect();
+        assert_eq!(names, ["util", "serde", "log", "core"]);
+        assert_eq!(
+            dependencies
+                .pointer(&["util", "path"])
+                .and_then(Value::as_str),
+            Some("../util")
+        );
+        assert_eq!(
+            dependencies
+                .pointer(&["util", "features"])
+                .unwrap()
+                .strings(),
+            ["a", "b"]
+        );
+        assert_eq!(
+            dependencies.pointer(&["serde", "workspace"]),
+            Some(&Value::Other("true".to_string()))
+        );
+        assert_eq!(
+            dependencies
+                .pointer(&["core", "path"])
+                .and_then(Value::as_str),
+            Some("../core")
+        );
+        assert_eq!(
+            document
+                .pointer(&[
+                    "target",
+                    "cfg(unix)",
+                    "dev-dependencies",
+                    "test helpers",
+                    "path"
+                ])
+                .and_then(Value::as_str),
+            Some("../helpers")
+        );
+        assert_eq!(
+            document
+                .pointer(&["workspace", "members"])
+                .unwrap()
+                .strings(),
+            ["crates/*", "tools/gen"]
+        );
+        match document.get("bin") {
+            Some(Value::Array(bins)) => {
+                let names: Vec<_> = bins.iter().filter_map(|b| b.get("name")).collect();
+                assert_eq!(
+                    names,
+                    [
+                        &Value::String("first".to_string()),
+                        &Value::String("second".to_string())
+                    ]
+                );
+            }
+            other => panic!("unexpected bin: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_strings() {
+        let document = parse(
+            "a = \"tab\\there \\\"quoted\\\" \\u00e9\"\n\
+             b = \"\"\"\nfirst\nsecond \\\n   third\"\"\"\n\
+             c = '''\nC:\\path\\'''\n\
+             d = 'it''s'\n",
+        );
+        assert_eq!(
+            document,
+            Err(TomlError {
+                line: 8,
+                message: "expected the end of the line".to_string()
+            })
+        );
+
+        let document = parse(
+            "a = \"tab\\there \\\"quoted\\\" \\u00e9\"\n\
+             b = \"\"\"\nfirst\nsecond \\\n   third\"\"\"\"\n\
+             c = '''\nC:\\path\\'''\n",
+        )
+        .unwrap();
+        let get = |key| document.get(key).and_then(Value::as_str);
+        assert_eq!(get("a"), Some("tab\there \"quoted\" \u{e9}"));
+        assert_eq!(get("b"), Some("first\nsecond third\""));
+        assert_eq!(get("c"), Some("C:\\path\\"));
+    }
+
+    #[test]
+    fn report_errors() {
+        for (input, line) in [
+            ("[package\nname = \"a\"\n", 1),
+            ("[package]\nname = \"a\n", 2),
+            ("a = 1\nb = [1, 2\n", 3),
+            ("a = 1\n= 2\n", 2),
+            ("a = 1\na.b = 2\n", 2),
+            ("a = \"\\q\"\n", 1),
+        ] {
+            match parse(input) {
+                Err(e) => assert_eq!(e.line, line, "{}: {}", input, e),
+                Ok(value) => panic!("{} parsed as {:?}", input, value),
+            }
+        }
+    }
+}
@@ -0,0 +1,224 @@
+// Unicode algorithms not provided by the standard library
+
+mod tables;
+
+use tables::{CANONICAL_DECOMPOSITION, CASE_FOLDING, COMBINING_CLASS, COMPOSITION};
+
+/// Unicode normalization forms
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalizationForm {
+    /// Canonical decomposition followed by canonical composition
+    Nfc,
+    /// Canonical decomposition
+    Nfd,
+}
+
+// Hangul syllables are composed and decomposed algorithmically
+const S_BASE: u32 = 0xAC00;
+const L_BASE: u32 = 0x1100;
+const V_BASE: u32 = 0x1161;
+const T_BASE: u32 = 0x11A7;
+const L_COUNT: u32 = 19;
+const V_COUNT: u32 = 21;
+const T_COUNT: u32 = 28;
+const N_COUNT: u32 = V_COUNT * T_COUNT;
+const S_COUNT: u32 = L_COUNT * N_COUNT;
+
+/// Apply full Unicode case folding to a sequence of characters, so that
+/// comparing folded sequences is a case-insensitive comparison.
+/// Note that folding may change the length, "ß" folds to "ss".
+pub fn fold_case(chars: &[char]) -> Vec<char> {
+    let mut folded = Vec::with_capacity(chars.len());
+    for &c in chars {
+        if c.is_ascii() {
+            folded.push(c.to_ascii_lowercase());
+            continue;
+        }
+        match CASE_FOLDING.binary_search_by_key(&c, |&(from, _)| from) {
+            Ok(idx) => folded.extend(CASE_FOLDING[idx].1.chars()),
+            Err(_) => folded.push(c),
+        }
+    }
+    folded
+}
+
+/// Normalize a string to the given normalization form
+pub fn normalize(s: &str, form: NormalizationForm) -> String {
+    // Fast path, ASCII is invariant under normalization
+    if s.is_ascii() {
+        return s.to_string();
+    }
+    let decomposed = decompose(s);
+    match form {
+        NormalizationForm::Nfd => decomposed.into_iter().collect(),
+        NormalizationForm::Nfc => compose(&decomposed).into_iter().collect(),
+    }
+}
+
+fn combining_class(c: char) -> u8 {
+    if c.is_ascii() {
+        return 0;
+    }
+    match COMBINING_CLASS.binary_search_by_key(&c, |&(from, _)| from) {
+        Ok(idx) => COMBINING_CLASS[idx].1,
+        Err(_) => 0,
+    }
+}
+
+/// Canonical decomposition, with combining marks put in canonical order
+fn decompose(s: &str) -> Vec<char> {
+    let mut chars = Vec::with_capacity(s.len());
+    for c in s.chars() {
+        let s_index = (c as u32).wrapping_sub(S_BASE);
+        if s_index < S_COUNT {
+            let l = L_BASE + s_index / N_COUNT;
+            let v = V_BASE + (s_index % N_COUNT) / T_COUNT;
+            let t = T_BASE + s_index % T_COUNT;
+            chars.extend([l, v].iter().filter_map(|&cp| char::from_u32(cp)));
+            if t != T_BASE {
+                chars.extend(char::from_u32(t));
+            }
+            continue;
+        }
+        match CANONICAL_DECOMPOSITION.binary_search_by_key(&c, |&(from, _)| from) {
+            Ok(idx) => chars.extend(CANONICAL_DECOMPOSITION[idx].1.chars()),
+            Err(_) => chars.push(c),
+        }
+    }
+
+    // Canonical ordering: stable sort of each run of non-starters
+    let mut start = 0;
+    while start < chars.len() {
+        if combining_class(chars[start]) == 0 {
+            start += 1;
+            continue;
+        }
+        let len = chars[start..]
+            .iter()
+            .take_while(|&&c| combining_class(c) != 0)
+            .count();
+        chars[start..start + len].sort_by_key(|&c| combining_class(c));
+        start += len;
+    }
+    chars
+}
+
+fn compose_pair(first: char, second: char) -> Option<char> {
+    let (a, b) = (first as u32, second as u32);
+
+    // Hangul L + V
+    if (L_BASE..L_BASE + L_COUNT).contains(&a) && (V_BASE..V_BASE + V_COUNT).contains(&b) {
+        return char::from_u32(S_BASE + ((a - L_BASE) * V_COUNT + (b - V_BASE)) * T_COUNT);
+    }
+    // Hangul LV + T
+    if (S_BASE..S_BASE + S_COUNT).contains(&a)
+        && (a - S_BASE).is_multiple_of(T_COUNT)
+        && (T_BASE + 1..T_BASE + T_COUNT).contains(&b)
+    {
+        return char::from_u32(a + b - T_BASE);
+    }
+
+    COMPOSITION
+        .binary_search_by_key(&(first, second), |&(pair, _)| pair)
+        .ok()
+        .map(|idx| COMPOSITION[idx].1)
+}
+
+/// Canonical composition of a decomposed sequence
+fn compose(chars: &[char]) -> Vec<char> {
+    let mut result: Vec<char> = Vec::with_capacity(chars.len());
+    // Index in result of the last starter, if any
+    let mut starter: Option<usize> = None;
+    // Combining class of the last character appended after the starter
+    let mut last_class: Option<u8> = None;
+
+    for &c in chars {
+        let class = combining_class(c);
+        if let Some(idx) = starter {
+            // A character is blocked from the starter when a character with a
+            // combining class of zero or greater or equal to its own sits in
+            // between
+            let blocked = match last_class {
+                None => false,
+                Some(last) => last == 0 || last >= class,
+            };
+            if !blocked && let Some(composed) = compose_pair(result[idx], c) {
+                result[idx] = composed;
+                continue;
+            }
+        }
+        if class == 0 {
+            starter = Some(result.len());
+            last_class = None;
+        } else {
+            last_class = Some(class);
+        }
+        result.push(c);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fold(s: &str) -> String {
+        let chars: Vec<char> = s.chars().collect();
+        fold_case(&chars).into_iter().collect()
+    }
+
+    #[test]
+    fn fold_case_ascii_and_unicode() {
+        assert_eq!(fold("README.MD"), "readme.md");
+        assert_eq!(fold("ÉCOLE"), "école");
+        assert_eq!(fold("Straße"), "strasse");
+        assert_eq!(fold("ΣΊΣΥΦΟΣ"), fold("σίσυφος"));
+        assert_eq!(fold("*?[A-Z]{a,b}"), "*?[a-z]{a,b}");
+    }
+
+    #[test]
+    fn normalize_latin() {
+        let nfc = "caf\u{e9}";
+        let nfd = "cafe\u{301}";
+        assert_eq!(normalize(nfd, NormalizationForm::Nfc), nfc);
+        assert_eq!(normalize(nfc, NormalizationForm::Nfd), nfd);
+        assert_eq!(normalize(nfc, NormalizationForm::Nfc), nfc);
+        assert_eq!(
+            normalize("src/main.rs", NormalizationForm::Nfd),
+            "src/main.rs"
+        );
+    }
+
+    #[test]
+    fn normalize_canonical_ordering() {
+        // Dot below (ccc 220) sorts before dot above (ccc 230)
+        let unordered = "q\u{307}\u{323}";
+        assert_eq!(
+            normalize(unordered, NormalizationForm::Nfd),
+            "q\u{323}\u{307}"
+        );
+        // Both marks compose with "s" into U+1E69
+        assert_eq!(
+            normalize("s\u{307}\u{323}", NormalizationForm::Nfc),
+            "\u{1e69}"
+        );
+    }
+
+    #[test]
+    fn normalize_hangul() {
+        let nfc = "\u{d55c}\u{ae00}";
+        let nfd = "\u{1112}\u{1161}\u{11ab}\u{1100}\u{1173}\u{11af}";
+        assert_eq!(normalize(nfc, NormalizationForm::Nfd), nfd);
+        assert_eq!(normalize(nfd, NormalizationForm::Nfc), nfc);
+    }
+
+    #[test]
+    fn normalize_exclusions() {
+        // Singletons and composition exclusions are not recomposed
+        assert_eq!(normalize("\u{212b}", NormalizationForm::Nfc), "\u{c5}");
+        assert_eq!(
+            normalize("\u{915}\u{93c}", NormalizationForm::Nfc),
+            "\u{915}\u{93c}"
+        );
+    }
+}
@@ -0,0 +1,177 @@
+// Interface for WebAssembly, used by the playground in web/. The host
+// writes the configuration and the changed paths in memory obtained with
+// `dcf_alloc`, calls `dcf_evaluate`, and reads the JSON output at
+// `dcf_output_ptr`. Nothing here reads stdin or the filesystem.
+
+use crate::config::Config;
+use crate::detect::{self, DetectOptions, OutputOrder};
+use crate::diff::{ChangedFiles, PathNormalization};
+use crate::json;
+use crate::unicode::NormalizationForm;
+
+/// Write an error as a JSON object, with its location if it has one
+fn error_json(message: &str, location: Option<(usize, usize, usize)>) -> String {
+    match location {
+        Some((line, column, end_column)) => format!(
+            "{{\"line\": {}, \"column\": {}, \"end_column\": {}, \"message\": {}}}",
+            line,
+            column,
+            end_column,
+            json::quote(message.as_bytes())
+        ),
+        None => format!("{{\"message\": {}}}", json::quote(message.as_bytes())),
+    }
+}
+
+/// Evaluate a configuration against changed paths, one per line. The
+/// outcome is a JSON object, either `{"groups": {"name": true, ...}}` with
+/// the groups in declaration order, or `{"errors": [...]}` with the
+/// problems found, located by line and columns for configuration errors.
+/// Patterns and paths are converted to the `unicode` normalization form if
+/// given.
+pub fn evaluate(config: &str, paths: &str, unicode: Option<NormalizationForm>) -> String {
+    let mut config = match Config::parse(config) {
+        Ok(config) => config,
+        Err(errors) => {
+            let errors: Vec<String> = errors
+                .iter()
+                .map(|e| error_json(&e.message, Some((e.line, e.column, e.end_column))))
+                .collect();
+            return format!("{{\"errors\": [{}]}}", errors.join(","));
+        }
+    };
+    if let Some(form) = unicode {
+        config.normalize_unicode(form);
+    }
+    let normalization = PathNormalization {
+        unicode,
+        ..PathNormalization::default()
+    };
+    let paths = paths.lines().map(str::trim).filter(|path| !path.is_empty());
+    let results = ChangedFiles::from_paths(paths, &normalization)
+        .map_err(detect::Error::from)
+        .and_then(|changed_files| {
+            config.expand_templates(&changed_files, None, false);
+            detect::detect(&config, &changed_files, &DetectOptions::default())
+        });
+    match results {
+        Ok(results) => format!(
+            "{{\"groups\": {}}}",
+            results.to_json(OutputOrder::Config, false, false)
+        ),
+        Err(e) => format!("{{\"errors\": [{}]}}", error_json(&e.to_string(), None)),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod exports {
+    use std::cell::RefCell;
+
+    thread_local! {
+        /// Output of the last evaluation
+        static OUTPUT: RefCell<String> = const { RefCell::new(String::new()) };
+    }
+
+    /// Allocate `len` bytes for the host to write its input to
+    #[unsafe(no_mangle)]
+    pub extern "C" fn dcf_alloc(len: usize) -> *mut u8 {
+        let mut buffer = Vec::<u8>::with_capacity(len);
+        let ptr = buffer.as_mut_ptr();
+        std::mem::forget(buffer);
+        ptr
+    }
+
+    /// Release memory obtained with `dcf_alloc`
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `dcf_alloc(len)` and not be released yet.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn dcf_dealloc(ptr: *mut u8, len: usize) {
+        drop(unsafe { Vec::from_raw_parts(ptr, 0, len) });
+    }
+
+    /// Evaluate the configuration and the changed paths, both UTF-8 text,
+    /// and keep the JSON output for `dcf_output_ptr` and `dcf_output_len`.
+    /// `unicode` is the normalization form of the patterns and the paths: 1
+    /// for NFC, 2 for NFD, and anything else for none.
+    ///
+    /// # Safety
+    ///
+    /// Both pointers must be valid for reads of their length.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn dcf_evaluate(
+        config_ptr: *const u8,
+        config_len: usize,
+        paths_ptr: *const u8,
+        paths_len: usize,
+        unicode: u32,
+    ) {
+        let config = unsafe { std::slice::from_raw_parts(config_ptr, config_len) };
+        let paths = unsafe { std::slice::from_raw_parts(paths_ptr, paths_len) };
+        let unicode = match unicode {
+            1 => Some(super::NormalizationForm::Nfc),
+            2 => Some(super::NormalizationForm::Nfd),
+            _ => None,
+        };
+        let output = super::evaluate(
+            &String::from_utf8_lossy(config),
+            &String::from_utf8_lossy(paths),
+            unicode,
+        );
+        OUTPUT.with(|o| *o.borrow_mut() = output);
+    }
+
+    /// Address of the output of the last evaluation
+    #[unsafe(no_mangle)]
+    pub extern "C" fn dcf_output_ptr() -> *const u8 {
+        OUTPUT.with(|o| o.borrow().as_ptr())
+    }
+
+    /// Length in bytes of the output of the last evaluation
+    #[unsafe(no_mangle)]
+    pub extern "C" fn dcf_output_len() -> usize {
+        OUTPUT.with(|o| o.borrow().len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluate_playground_input() {
+        let config = "[docs]\ndocs/**\n[code]\nsrc/**\n";
+        assert_eq!(
+            evaluate(config, "docs/index.md\n\n  README.md \n", None),
+            "{\"groups\": {\"docs\": true,\"code\": false}}"
+        );
+        assert_eq!(
+            evaluate("[a\nsrc/**\n", "", None),
+            "{\"errors\": [{\"line\": 1, \"column\": 1, \"end_column\": 3, \
+             \"message\": \"Invalid section header: missing closing ']'\"}]}"
+        );
+        assert_eq!(
+            evaluate(config, "../a.md\n", None),
+            "{\"errors\": [{\"message\": \"invalid path '../a.md': escapes the repository root\"}]}"
+        );
+        assert_eq!(
+            evaluate(
+                "[svc-{name}]\nservices/{name}/**\n",
+                "services/api/main.go\n",
+                None
+            ),
+            "{\"groups\": {\"svc-api\": true}}"
+        );
+        // "café" decomposed in the configuration, composed in the paths
+        let config = "[cafe\u{301}]\ncafe\u{301}/**\n";
+        assert_eq!(
+            evaluate(config, "caf\u{e9}/menu.txt\n", None),
+            "{\"groups\": {\"cafe\u{301}\": false}}"
+        );
+        assert_eq!(
+            evaluate(config, "caf\u{e9}/menu.txt\n", Some(NormalizationForm::Nfc)),
+            "{\"groups\": {\"cafe\u{301}\": true}}"
+        );
+    }
+}
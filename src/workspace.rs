@@ -0,0 +1,634 @@
+// Packages of monorepos, discovered from the manifests of their ecosystem
+// and reported like groups
+
+mod cargo;
+mod go;
+mod npm;
+
+use crate::detect::{self, GroupResult, Results};
+use crate::diff::{self, ChangedFiles, LineStats, PathNormalization};
+use crate::matching::{MatchPath, Pattern};
+use crate::paths;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Package manager whose workspace layout is read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ecosystem {
+    /// Cargo workspaces, from the members of the root `Cargo.toml`
+    Cargo,
+    /// npm, yarn and pnpm workspaces, from the `workspaces` of the root
+    /// `package.json` or from `pnpm-workspace.yaml`
+    Npm,
+    /// Go modules, from the `use` directives of `go.work`, or every
+    /// `go.mod` of the repository
+    Go,
+}
+
+impl Ecosystem {
+    /// Prefix of the names of the groups of the packages, like `cargo` in
+    /// `cargo:util`
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Ecosystem::Cargo => "cargo",
+            Ecosystem::Npm => "npm",
+            Ecosystem::Go => "go",
+        }
+    }
+}
+
+/// A package of a workspace
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Package {
+    /// Name of the package in its manifest
+    pub name: String,
+    /// Directory of the package relative to the repository root, empty for
+    /// a package at the root
+    pub dir: Vec<u8>,
+    /// Names of the packages of the workspace it depends on
+    pub dependencies: Vec<String>,
+}
+
+/// The packages of a workspace, sorted by directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Workspace {
+    /// Package manager of the workspace
+    pub ecosystem: Ecosystem,
+    /// The packages
+    pub packages: Vec<Package>,
+}
+
+/// Error raised when the manifests of a workspace cannot be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkspaceError {
+    /// File or directory relative to the repository root
+    pub path: Vec<u8>,
+    /// What is wrong with it
+    pub message: String,
+}
+
+impl fmt::Display for WorkspaceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "cannot read '{}': {}",
+            paths::display(&self.path),
+            self.message
+        )
+    }
+}
+
+impl std::error::Error for WorkspaceError {}
+
+impl Workspace {
+    /// Discover the packages of a workspace from the manifests in the root
+    /// of `normalization`, or in the current directory
+    pub fn load(
+        ecosystem: Ecosystem,
+        normalization: &PathNormalization,
+    ) -> Result<Self, WorkspaceError> {
+        let root = normalization.root_dir();
+        let mut packages = match ecosystem {
+            Ecosystem::Cargo => cargo::packages(&root)?,
+            Ecosystem::Npm => npm::packages(&root)?,
+            Ecosystem::Go => go::packages(&root)?,
+        };
+        packages.sort_by(|a, b| a.dir.cmp(&b.dir));
+        Ok(Workspace {
+            ecosystem,
+            packages,
+        })
+    }
+
+    /// Name of the group of a package, like `cargo:util`
+    pub fn group_name(&self, package: &Package) -> String {
+        format!("{}:{}", self.ecosystem.prefix(), package.name)
+    }
+
+    /// Index of the package owning a path: the one with the deepest
+    /// directory containing it
+    pub fn owner(&self, path: &[u8]) -> Option<usize> {
+        self.packages
+            .iter()
+            .enumerate()
+            .filter(|(_, package)| contains(&package.dir, path))
+            .max_by_key(|(_, package)| package.dir.len())
+            .map(|(index, _)| index)
+    }
+
+    /// Check the changed files against the packages, in the order of the
+    /// workspace. A package changes when files of its directory change, and
+    /// when one of the packages it depends on does, which
+    /// [`GroupResult::via`] tells. Files of nested packages only belong to
+    /// the innermost one.
+    pub fn detect(&self, changed_files: &ChangedFiles) -> Results {
+        let mut groups: Vec<GroupResult> = self
+            .packages
+            .iter()
+            .map(|package| GroupResult {
+                name: self.group_name(package),
+                matched: false,
+                direct: false,
+                via: Vec::new(),
+                lines: Some(LineStats::default()),
+                files: Vec::new(),
+            })
+            .collect();
+        for file in &changed_files.files {
+            let Some(index) = self.owner(&file.name) else {
+                continue;
+            };
+            let group = &mut groups[index];
+            group.matched = true;
+            group.direct = true;
+            group.files.push(file.name.clone());
+            group.lines = group
+                .lines
+                .zip(file.stats)
+                .map(|(total, stats)| total + stats);
+        }
+
+        let index = |name: &str| self.packages.iter().position(|p| p.name == name);
+        let dependencies: Vec<Vec<usize>> = self
+            .packages
+            .iter()
+            .map(|package| {
+                package
+                    .dependencies
+                    .iter()
+                    .filter_map(|d| index(d))
+                    .collect()
+            })
+            .collect();
+        detect::propagate(&mut groups, &dependencies);
+        Results { groups }
+    }
+}
+
+/// Whether a directory, relative to the repository root, contains a path
+fn contains(dir: &[u8], path: &[u8]) -> bool {
+    dir.is_empty() || (path.starts_with(dir) && path.get(dir.len()) == Some(&b'/'))
+}
+
+/// Resolve a path relative to a directory of the repository. Returns None
+/// if it goes above the repository root.
+fn join(dir: &[u8], relative: &str) -> Option<Vec<u8>> {
+    let path = [dir, b"/", relative.as_bytes()].concat();
+    diff::lexical_components(&path).map(|components| components.join(&b'/'))
+}
+
+/// Compile the directory globs of a manifest, like `crates/*`
+fn compile_globs(globs: &[&str], manifest: &[u8]) -> Result<Vec<Pattern>, WorkspaceError> {
+    globs
+        .iter()
+        .map(|glob| {
+            let glob = glob.trim_start_matches("./").trim_end_matches('/');
+            // Globs are relative to the workspace root, while patterns
+            // without a leading slash match at any depth
+            Pattern::compile(&format!("/{}", glob.trim_start_matches('/'))).map_err(|e| {
+                WorkspaceError {
+                    path: manifest.to_vec(),
+                    message: format!("invalid pattern '{}': {}", glob, e),
+                }
+            })
+        })
+        .collect()
+}
+
+/// Whether one of the globs matches a directory or one of its parents
+fn matches_dir(globs: &[Pattern], dir: &[u8]) -> bool {
+    let mut ancestor = dir;
+    loop {
+        if globs
+            .iter()
+            .any(|g| g.is_match(&MatchPath::from_bytes(ancestor)))
+        {
+            return true;
+        }
+        match ancestor.iter().rposition(|&b| b == b'/') {
+            Some(end) => ancestor = &ancestor[..end],
+            None => return false,
+        }
+    }
+}
+
+/// Read a file of the repository
+fn read(root: &Path, path: &[u8]) -> Result<String, WorkspaceError> {
+    fs::read(root.join(paths::to_path(path)))
+        .map(|content| String::from_utf8_lossy(&content).into_owned())
+        .map_err(|e| WorkspaceError {
+            path: path.to_vec(),
+            message: e.to_string(),
+        })
+}
+
+/// Path of a file in a directory of the repository
+fn file_in(dir: &[u8], name: &[u8]) -> Vec<u8> {
+    if dir.is_empty() {
+        name.to_vec()
+    } else {
+        [dir, b"/", name].concat()
+    }
+}
+
+/// Find the directories of the repository holding a file of that name,
+/// sorted. Hidden directories and the ones of build outputs and installed
+/// dependencies are skipped.
+fn find_dirs(root: &Path, name: &str) -> Result<Vec<Vec<u8>>, WorkspaceError> {
+    let mut found = Vec::new();
+    let mut pending = vec![Vec::new()];
+    while let Some(dir) = pending.pop() {
+        let entries =
+            fs::read_dir(root.join(paths::to_path(&dir))).map_err(|e| WorkspaceError {
+                path: if dir.is_empty() {
+                    b".".to_vec()
+                } else {
+                    dir.clone()
+                },
+                message: e.to_string(),
+            })?;
+        for entry in entries.flatten() {
+            let file_name = paths::from_os_str(&entry.file_name());
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                let skipped = file_name.starts_with(b".")
+                    || file_name == b"target"
+                    || file_name == b"node_modules";
+                if !skipped {
+                    pending.push(file_in(&dir, &file_name));
+                }
+            } else if file_name == name.as_bytes() {
+                found.push(dir.clone());
+            }
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Write files in a new temporary directory
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "detect-changed-files-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        for (path, content) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    fn load(ecosystem: Ecosystem, root: &Path) -> Result<Workspace, WorkspaceError> {
+        let mut normalization = PathNormalization::default();
+        normalization.set_root(root, Path::new("/")).unwrap();
+        Workspace::load(ecosystem, &normalization)
+    }
+
+    /// Names of the packages that changed, with whether they did directly
+    fn changed(workspace: &Workspace, paths: &[&str]) -> Vec<(String, bool)> {
+        let changed_files = ChangedFiles::from_paths(paths, &PathNormalization::default()).unwrap();
+        workspace
+            .detect(&changed_files)
+            .groups
+            .into_iter()
+            .filter(|g| g.matched)
+            .map(|g| (g.name, g.direct))
+            .collect()
+    }
+
+    #[test]
+    fn helpers() {
+        assert!(contains(b"", b"a.rs"));
+        assert!(contains(b"crates/a", b"crates/a/src/lib.rs"));
+        assert!(!contains(b"crates/a", b"crates/ab/src/lib.rs"));
+        assert_eq!(join(b"crates/a", "../b"), Some(b"crates/b".to_vec()));
+        assert_eq!(join(b"", "./tools/"), Some(b"tools".to_vec()));
+        assert_eq!(join(b"crates", "../../b"), None);
+        let globs = compile_globs(&["crates/*", "./tools/gen/"], b"Cargo.toml").unwrap();
+        assert!(matches_dir(&globs, b"crates/a"));
+        assert!(matches_dir(&globs, b"tools/gen"));
+        assert!(matches_dir(&globs, b"crates/a/nested"));
+        assert!(!matches_dir(&globs, b"tools/other"));
+        assert!(compile_globs(&["crates/[a"], b"Cargo.toml").is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn non_utf8_directories() {
+        let root = write_files(
+            "non-utf8-directories",
+            &[("Cargo.toml", "[workspace]\nmembers = [\"crates/*\"]\n")],
+        );
+        let dir = root.join(paths::to_path(b"crates/caf\xe9"));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("Cargo.toml"), "[package]\nname = \"cafe\"\n").unwrap();
+        assert_eq!(
+            find_dirs(&root, "Cargo.toml").unwrap(),
+            [b"".to_vec(), b"crates/caf\xe9".to_vec()]
+        );
+        let workspace = load(Ecosystem::Cargo, &root).unwrap();
+        assert_eq!(workspace.packages[0].dir, b"crates/caf\xe9");
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn cargo_workspace() {
+        let root = write_files(
+            "cargo-workspace",
+            &[
+                (
+                    "Cargo.toml",
+                    "[workspace]\nmembers = [\"crates/*\", \"crates/api/fixtures\", \"tools/gen\"]\n\
+                     exclude = [\"crates/legacy\"]\n\n\
+                     [workspace.dependencies]\nutil = { path = \"crates/util\" }\n\
+                     serde = \"1\"\n",
+                ),
+                (
+                    "crates/util/Cargo.toml",
+                    "[package]\nname = \"util\"\n[dependencies]\nserde.workspace = true\n",
+                ),
+                (
+                    "crates/core/Cargo.toml",
+                    "[package]\nname = \"core\"\n[dependencies]\nutil = { workspace = true }\n",
+                ),
+                (
+                    "crates/api/Cargo.toml",
+                    "[package]\nname = \"api-server\"\n\
+                     [dependencies]\ncore = { path = \"../core\" }\n\
+                     [target.'cfg(unix)'.dev-dependencies]\ngen = { path = \"../../tools/gen\" }\n",
+                ),
+                (
+                    "crates/api/fixtures/Cargo.toml",
+                    "[package]\nname = \"fixture\"\n",
+                ),
+                ("crates/legacy/Cargo.toml", "[package]\nname = \"legacy\"\n"),
+                ("tools/gen/Cargo.toml", "[package]\nname = \"gen\"\n"),
+                ("target/debug/Cargo.toml", "[package]\nname = \"output\"\n"),
+                // Matched by "crates/*" below the root only
+                ("vendor/crates/b/Cargo.toml", "[package]\nname = \"b\"\n"),
+            ],
+        );
+        let workspace = load(Ecosystem::Cargo, &root).unwrap();
+        let packages: Vec<_> = workspace
+            .packages
+            .iter()
+            .map(|p| (p.name.as_str(), p.dir.as_slice(), p.dependencies.clone()))
+            .collect();
+        assert_eq!(
+            packages,
+            [
+                (
+                    "api-server",
+                    &b"crates/api"[..],
+                    vec!["core".to_string(), "gen".to_string()]
+                ),
+                ("fixture", b"crates/api/fixtures", vec![]),
+                ("core", b"crates/core", vec!["util".to_string()]),
+                ("util", b"crates/util", vec![]),
+                ("gen", b"tools/gen", vec![]),
+            ]
+        );
+
+        assert_eq!(
+            changed(&workspace, &["crates/util/src/lib.rs", "README.md"]),
+            [
+                ("cargo:api-server".to_string(), false),
+                ("cargo:core".to_string(), false),
+                ("cargo:util".to_string(), true),
+            ]
+        );
+        // Files of nested packages only belong to them
+        assert_eq!(
+            changed(&workspace, &["crates/api/fixtures/src/lib.rs"]),
+            [("cargo:fixture".to_string(), true)]
+        );
+        assert!(changed(&workspace, &["vendor/crates/b/src/lib.rs"]).is_empty());
+        let changed_files =
+            ChangedFiles::from_paths(["tools/gen/src/main.rs"], &PathNormalization::default())
+                .unwrap();
+        let results = workspace.detect(&changed_files);
+        assert_eq!(results.get("cargo:api-server").unwrap().via, ["cargo:gen"]);
+        assert_eq!(
+            results.get("cargo:gen").unwrap().files,
+            [b"tools/gen/src/main.rs".to_vec()]
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn cargo_single_package() {
+        let root = write_files(
+            "cargo-package",
+            &[("Cargo.toml", "[package]\nname = \"app\"\n")],
+        );
+        let workspace = load(Ecosystem::Cargo, &root).unwrap();
+        assert_eq!(workspace.packages.len(), 1);
+        assert_eq!(
+            changed(&workspace, &["src/main.rs"]),
+            [("cargo:app".to_string(), true)]
+        );
+
+        fs::write(root.join("Cargo.toml"), "[package]\nversion = \"1.0.0\"\n").unwrap();
+        let error = load(Ecosystem::Cargo, &root).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "cannot read 'Cargo.toml': no package name"
+        );
+        fs::write(root.join("Cargo.toml"), "[package\n").unwrap();
+        let error = load(Ecosystem::Cargo, &root).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "cannot read 'Cargo.toml': invalid TOML at line 1: expected ']'"
+        );
+        fs::remove_dir_all(&root).unwrap();
+        assert!(load(Ecosystem::Cargo, &root).is_err());
+    }
+
+    #[test]
+    fn npm_workspace() {
+        let root = write_files(
+            "npm-workspace",
+            &[
+                (
+                    "package.json",
+                    r#"{"name": "monorepo", "private": true,
+                        "workspaces": ["packages/*", "apps/**", "!apps/legacy"]}"#,
+                ),
+                (
+                    "packages/ui/package.json",
+                    r#"{"name": "@acme/ui", "dependencies": {"react": "^19.0.0"}}"#,
+                ),
+                (
+                    "packages/utils/package.json",
+                    r#"{"name": "@acme/utils", "version": "1.0.0"}"#,
+                ),
+                (
+                    "apps/web/package.json",
+                    r#"{"name": "web", "dependencies": {"@acme/ui": "workspace:*"},
+                        "devDependencies": {"@acme/utils": "*", "typescript": "5"}}"#,
+                ),
+                ("apps/legacy/package.json", r#"{"name": "legacy"}"#),
+                (
+                    "apps/web/node_modules/react/package.json",
+                    r#"{"name": "react"}"#,
+                ),
+                // Matched by "packages/*" below the root only
+                ("vendor/packages/q/package.json", r#"{"name": "q"}"#),
+            ],
+        );
+        let workspace = load(Ecosystem::Npm, &root).unwrap();
+        let packages: Vec<_> = workspace
+            .packages
+            .iter()
+            .map(|p| (p.name.as_str(), p.dir.as_slice(), p.dependencies.clone()))
+            .collect();
+        assert_eq!(
+            packages,
+            [
+                (
+                    "web",
+                    &b"apps/web"[..],
+                    vec!["@acme/ui".to_string(), "@acme/utils".to_string()]
+                ),
+                ("@acme/ui", b"packages/ui", vec![]),
+                ("@acme/utils", b"packages/utils", vec![]),
+            ]
+        );
+        assert_eq!(
+            changed(&workspace, &["packages/utils/index.ts", "package.json"]),
+            [
+                ("npm:web".to_string(), false),
+                ("npm:@acme/utils".to_string(), true),
+            ]
+        );
+        assert!(changed(&workspace, &["vendor/packages/q/index.js"]).is_empty());
+
+        // Yarn lists the globs in a "packages" member
+        fs::write(
+            root.join("package.json"),
+            r#"{"workspaces": {"packages": ["packages/ui"]}}"#,
+        )
+        .unwrap();
+        let workspace = load(Ecosystem::Npm, &root).unwrap();
+        assert_eq!(workspace.packages.len(), 1);
+
+        // pnpm lists them in pnpm-workspace.yaml
+        fs::write(root.join("package.json"), r#"{"name": "monorepo"}"#).unwrap();
+        let error = load(Ecosystem::Npm, &root).unwrap_err();
+        assert!(error.message.contains("no \"workspaces\" member"));
+        fs::write(
+            root.join("pnpm-workspace.yaml"),
+            "packages:\n  - 'packages/*'\n  - '!packages/ui'\n",
+        )
+        .unwrap();
+        let workspace = load(Ecosystem::Npm, &root).unwrap();
+        assert_eq!(workspace.packages[0].name, "@acme/utils");
+        assert_eq!(workspace.packages.len(), 1);
+
+        fs::write(root.join("packages/ui/package.json"), "{").unwrap();
+        fs::write(
+            root.join("pnpm-workspace.yaml"),
+            "packages:\n  - packages/*\n",
+        )
+        .unwrap();
+        let error = load(Ecosystem::Npm, &root).unwrap_err();
+        assert_eq!(error.path, b"packages/ui/package.json");
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn go_modules() {
+        let root = write_files(
+            "go-modules",
+            &[
+                (
+                    "go.mod",
+                    "module example.com/app\n\ngo 1.22\n\n\
+                     require example.com/util v0.0.0\n\n\
+                     replace example.com/util => ./libs/util\n",
+                ),
+                ("libs/util/go.mod", "module example.com/util\n"),
+                (
+                    "services/api/go.mod",
+                    "module \"example.com/api\"\n\nrequire (\n\texample.com/util v0.0.0\n)\n",
+                ),
+                (
+                    "services/api/testdata/go.mod",
+                    "module example.com/fixture\n",
+                ),
+                ("vendor/example.com/dep/go.mod", "module example.com/dep\n"),
+            ],
+        );
+        let workspace = load(Ecosystem::Go, &root).unwrap();
+        let packages: Vec<_> = workspace
+            .packages
+            .iter()
+            .map(|p| (p.name.as_str(), p.dir.as_slice(), p.dependencies.clone()))
+            .collect();
+        // Without go.work, only local replacements link the modules
+        assert_eq!(
+            packages,
+            [
+                (
+                    "example.com/app",
+                    &b""[..],
+                    vec!["example.com/util".to_string()]
+                ),
+                ("example.com/util", b"libs/util", vec![]),
+                ("example.com/api", b"services/api", vec![]),
+            ]
+        );
+        assert_eq!(
+            changed(&workspace, &["libs/util/util.go"]),
+            [
+                ("go:example.com/app".to_string(), false),
+                ("go:example.com/util".to_string(), true),
+            ]
+        );
+        assert_eq!(
+            changed(&workspace, &["services/api/testdata/input.json"]),
+            [("go:example.com/api".to_string(), true)]
+        );
+
+        // With go.work, only the used modules are read, and they require
+        // each other from their directory
+        fs::write(
+            root.join("go.work"),
+            "go 1.22\n\nuse (\n\t./services/api\n\t./libs/util\n)\n",
+        )
+        .unwrap();
+        let workspace = load(Ecosystem::Go, &root).unwrap();
+        assert_eq!(
+            changed(&workspace, &["libs/util/util.go", "main.go"]),
+            [
+                ("go:example.com/util".to_string(), true),
+                ("go:example.com/api".to_string(), false),
+            ]
+        );
+
+        fs::write(root.join("go.work"), "use ./missing\n").unwrap();
+        let error = load(Ecosystem::Go, &root).unwrap_err();
+        assert_eq!(error.path, b"missing/go.mod");
+        fs::write(root.join("go.work"), "use .\n").unwrap();
+        fs::write(root.join("go.mod"), "go 1.22\n").unwrap();
+        let error = load(Ecosystem::Go, &root).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "cannot read 'go.mod': no module directive"
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,127 @@
+// Cargo workspaces: the members listed in the root manifest, and the path
+// dependencies between them
+
+use super::{Package, WorkspaceError, compile_globs, file_in, find_dirs, join, matches_dir, read};
+use crate::matching::MatchPath;
+use crate::toml::{self, Value};
+use std::path::Path;
+
+const MANIFEST: &str = "Cargo.toml";
+
+/// Tables of dependencies, at the top of a manifest or in a `target` table
+const DEPENDENCY_TABLES: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// Read and parse the manifest of a directory
+fn manifest(root: &Path, dir: &[u8]) -> Result<Value, WorkspaceError> {
+    let path = file_in(dir, MANIFEST.as_bytes());
+    toml::parse(&read(root, &path)?).map_err(|e| WorkspaceError {
+        path,
+        message: e.to_string(),
+    })
+}
+
+/// Dependencies declared by a manifest, by key, including the ones of
+/// platform-specific `target` tables
+fn dependencies(manifest: &Value) -> Vec<(&str, &Value)> {
+    let targets = manifest
+        .get("target")
+        .map(Value::entries)
+        .unwrap_or_default();
+    let tables = std::iter::once(manifest).chain(targets.iter().map(|(_, table)| table));
+    tables
+        .flat_map(|table| DEPENDENCY_TABLES.iter().filter_map(|name| table.get(name)))
+        .flat_map(|dependencies| dependencies.entries())
+        .map(|(key, spec)| (key.as_str(), spec))
+        .collect()
+}
+
+/// List the packages of the workspace at `root`: the members of its
+/// `[workspace]`, and the root package if there is one
+pub(super) fn packages(root: &Path) -> Result<Vec<Package>, WorkspaceError> {
+    let root_manifest = manifest(root, b"")?;
+    let mut dirs = Vec::new();
+    if root_manifest.get("package").is_some() {
+        dirs.push(Vec::new());
+    }
+    match root_manifest.get("workspace") {
+        Some(workspace) => {
+            let strings = |key| workspace.get(key).map(Value::strings).unwrap_or_default();
+            let members = compile_globs(&strings("members"), MANIFEST.as_bytes())?;
+            let exclude = compile_globs(&strings("exclude"), MANIFEST.as_bytes())?;
+            for dir in find_dirs(root, MANIFEST)? {
+                if !dir.is_empty()
+                    && members
+                        .iter()
+                        .any(|m| m.is_match(&MatchPath::from_bytes(&dir)))
+                    && !matches_dir(&exclude, &dir)
+                {
+                    dirs.push(dir);
+                }
+            }
+        }
+        None if dirs.is_empty() => {
+            return Err(WorkspaceError {
+                path: MANIFEST.as_bytes().to_vec(),
+                message: "neither a [workspace] nor a [package] table".to_string(),
+            });
+        }
+        None => {}
+    }
+
+    let manifests = dirs
+        .iter()
+        .map(|dir| {
+            if dir.is_empty() {
+                Ok(root_manifest.clone())
+            } else {
+                manifest(root, dir)
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut names = Vec::new();
+    for (dir, manifest) in dirs.iter().zip(&manifests) {
+        match manifest
+            .pointer(&["package", "name"])
+            .and_then(Value::as_str)
+        {
+            Some(name) => names.push(name.to_string()),
+            None => {
+                return Err(WorkspaceError {
+                    path: file_in(dir, MANIFEST.as_bytes()),
+                    message: "no package name".to_string(),
+                });
+            }
+        }
+    }
+
+    let mut packages = Vec::new();
+    for ((dir, manifest), name) in dirs.iter().zip(&manifests).zip(&names) {
+        let mut package = Package {
+            name: name.clone(),
+            dir: dir.clone(),
+            dependencies: Vec::new(),
+        };
+        for (key, spec) in dependencies(manifest) {
+            // Path dependencies, given in the manifest or inherited from the
+            // workspace
+            let path = match spec.get("path").and_then(Value::as_str) {
+                Some(path) => join(dir, path),
+                None if spec.get("workspace") == Some(&Value::Other("true".to_string())) => {
+                    root_manifest
+                        .pointer(&["workspace", "dependencies", key, "path"])
+                        .and_then(Value::as_str)
+                        .and_then(|path| join(b"", path))
+                }
+                None => None,
+            };
+            let target = path.and_then(|path| dirs.iter().position(|d| *d == path));
+            if let Some(target) = target
+                && !package.dependencies.contains(&names[target])
+            {
+                package.dependencies.push(names[target].clone());
+            }
+        }
+        packages.push(package);
+    }
+    Ok(packages)
+}
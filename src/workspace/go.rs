@@ -0,0 +1,221 @@
+// Go modules: the modules used by go.work, or every go.mod of the
+// repository, and the dependencies between them through local `replace`
+// directives, or through `require` directives within a go.work workspace
+
+use super::{Package, WorkspaceError, file_in, find_dirs, join, read};
+use std::path::Path;
+
+const MODULE_FILE: &str = "go.mod";
+const WORKSPACE_FILE: &str = "go.work";
+
+/// A directive of a go.mod or go.work file, with its arguments. Directives
+/// of blocks like `require ( ... )` are given one by one.
+#[derive(Debug, PartialEq, Eq)]
+struct Directive {
+    verb: String,
+    args: Vec<String>,
+}
+
+/// Read the directives of a go.mod or go.work file
+fn directives(content: &str) -> Vec<Directive> {
+    let mut directives = Vec::new();
+    let mut block: Option<String> = None;
+    for line in content.lines() {
+        let line = line.split("//").next().unwrap_or_default();
+        let tokens: Vec<String> = line
+            .split_whitespace()
+            .map(|token| token.trim_matches(['"', '`']).to_string())
+            .collect();
+        let Some(first) = tokens.first() else {
+            continue;
+        };
+        match &block {
+            Some(_) if first == ")" => block = None,
+            Some(verb) => directives.push(Directive {
+                verb: verb.clone(),
+                args: tokens,
+            }),
+            None if tokens.len() == 2 && tokens[1] == "(" => block = Some(first.clone()),
+            None => directives.push(Directive {
+                verb: first.clone(),
+                args: tokens[1..].to_vec(),
+            }),
+        }
+    }
+    directives
+}
+
+/// Split the arguments of a `replace` directive into the replaced module
+/// path and the replacement, if it is a local directory
+fn local_replacement(args: &[String]) -> Option<(&str, &str)> {
+    let arrow = args.iter().position(|arg| arg == "=>")?;
+    let target = args.get(arrow + 1)?;
+    let local = target.starts_with("./") || target.starts_with("../") || target == ".";
+    local.then(|| (args[0].as_str(), target.as_str()))
+}
+
+/// A go.mod file
+struct Module {
+    dir: Vec<u8>,
+    path: String,
+    requires: Vec<String>,
+    directives: Vec<Directive>,
+}
+
+fn read_module(root: &Path, dir: &[u8]) -> Result<Module, WorkspaceError> {
+    let file = file_in(dir, MODULE_FILE.as_bytes());
+    let directives = directives(&read(root, &file)?);
+    let path = directives
+        .iter()
+        .find(|d| d.verb == "module")
+        .and_then(|d| d.args.first())
+        .ok_or_else(|| WorkspaceError {
+            path: file.clone(),
+            message: "no module directive".to_string(),
+        })?
+        .clone();
+    let requires = directives
+        .iter()
+        .filter(|d| d.verb == "require")
+        .filter_map(|d| d.args.first().cloned())
+        .collect();
+    Ok(Module {
+        dir: dir.to_vec(),
+        path,
+        requires,
+        directives,
+    })
+}
+
+/// List the modules of the repository at `root`
+pub(super) fn packages(root: &Path) -> Result<Vec<Package>, WorkspaceError> {
+    // Directories used by go.work, and its own replacements
+    let workspace = match read(root, WORKSPACE_FILE.as_bytes()) {
+        Ok(content) => Some(directives(&content)),
+        Err(_) if !root.join(WORKSPACE_FILE).exists() => None,
+        Err(e) => return Err(e),
+    };
+    let dirs = match &workspace {
+        Some(directives) => {
+            let mut dirs = Vec::new();
+            for directive in directives.iter().filter(|d| d.verb == "use") {
+                let Some(dir) = directive.args.first().and_then(|path| join(b"", path)) else {
+                    return Err(WorkspaceError {
+                        path: WORKSPACE_FILE.as_bytes().to_vec(),
+                        message: format!("invalid use directive: {}", directive.args.join(" ")),
+                    });
+                };
+                if !dirs.contains(&dir) {
+                    dirs.push(dir);
+                }
+            }
+            dirs
+        }
+        // Like the go command, skip the vendor and testdata directories
+        None => find_dirs(root, MODULE_FILE)?
+            .into_iter()
+            .filter(|dir| {
+                !dir.split(|&b| b == b'/')
+                    .any(|name| name == b"vendor" || name == b"testdata")
+            })
+            .collect(),
+    };
+    let modules = dirs
+        .iter()
+        .map(|dir| read_module(root, dir))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let module_at = |dir: Option<Vec<u8>>| {
+        dir.and_then(|dir| modules.iter().find(|m| m.dir == dir))
+            .map(|m| m.path.clone())
+    };
+    let workspace_replacements: Vec<(&str, &str)> = workspace
+        .iter()
+        .flatten()
+        .filter(|d| d.verb == "replace")
+        .filter_map(|d| local_replacement(&d.args))
+        .collect();
+
+    let packages = modules
+        .iter()
+        .map(|module| {
+            let mut dependencies: Vec<String> = Vec::new();
+            let mut add = |dependency: Option<String>| {
+                if let Some(dependency) = dependency
+                    && dependency != module.path
+                    && !dependencies.contains(&dependency)
+                {
+                    dependencies.push(dependency);
+                }
+            };
+            let replacements = module
+                .directives
+                .iter()
+                .filter(|d| d.verb == "replace")
+                .filter_map(|d| local_replacement(&d.args));
+            for (_, target) in replacements {
+                add(module_at(join(&module.dir, target)));
+            }
+            // Within a go.work workspace, required modules of the workspace
+            // are used from their directory
+            if workspace.is_some() {
+                for required in &module.requires {
+                    if modules.iter().any(|m| &m.path == required) {
+                        add(Some(required.clone()));
+                    }
+                    for (_, target) in workspace_replacements.iter().filter(|(r, _)| r == required)
+                    {
+                        add(module_at(join(b"", target)));
+                    }
+                }
+            }
+            Package {
+                name: module.path.clone(),
+                dir: module.dir.clone(),
+                dependencies,
+            }
+        })
+        .collect();
+    Ok(packages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directive(verb: &str, args: &[&str]) -> Directive {
+        Directive {
+            verb: verb.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn read_directives() {
+        let content = "module \"example.com/api\" // the API\n\ngo 1.22\n\n\
+                       require (\n\texample.com/util v0.0.0\n\t// comment\n\
+                       \tgolang.org/x/mod v0.17.0 // indirect\n)\n\
+                       replace example.com/util v0.0.0 => ../util\n\
+                       replace golang.org/x/mod => golang.org/x/mod v0.18.0\n";
+        let found = directives(content);
+        assert_eq!(
+            found,
+            [
+                directive("module", &["example.com/api"]),
+                directive("go", &["1.22"]),
+                directive("require", &["example.com/util", "v0.0.0"]),
+                directive("require", &["golang.org/x/mod", "v0.17.0"]),
+                directive("replace", &["example.com/util", "v0.0.0", "=>", "../util"]),
+                directive(
+                    "replace",
+                    &["golang.org/x/mod", "=>", "golang.org/x/mod", "v0.18.0"]
+                ),
+            ]
+        );
+        assert_eq!(
+            local_replacement(&found[4].args),
+            Some(("example.com/util", "../util"))
+        );
+        assert_eq!(local_replacement(&found[5].args), None);
+    }
+}
@@ -0,0 +1,150 @@
+// npm, yarn and pnpm workspaces: the packages matched by the `workspaces`
+// globs of the root package.json, or by the `packages` list of
+// pnpm-workspace.yaml, and the dependencies between them
+
+use super::{Package, WorkspaceError, compile_globs, file_in, find_dirs, read};
+use crate::json::{self, Value};
+use crate::matching::MatchPath;
+use std::path::Path;
+
+const MANIFEST: &str = "package.json";
+const PNPM_WORKSPACE: &str = "pnpm-workspace.yaml";
+
+/// Members of a manifest listing dependencies
+const DEPENDENCY_FIELDS: [&str; 4] = [
+    "dependencies",
+    "devDependencies",
+    "peerDependencies",
+    "optionalDependencies",
+];
+
+/// Read and parse the manifest of a directory
+fn manifest(root: &Path, dir: &[u8]) -> Result<Value, WorkspaceError> {
+    let path = file_in(dir, MANIFEST.as_bytes());
+    json::parse(&read(root, &path)?).map_err(|e| WorkspaceError {
+        path,
+        message: e.to_string(),
+    })
+}
+
+/// Strings of a JSON array
+fn strings(value: Option<&Value>) -> Vec<&str> {
+    match value {
+        Some(Value::Array(items)) => items.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Read the `packages` list of a pnpm-workspace.yaml file: the items of
+/// the top-level `packages:` block, unquoted
+fn pnpm_packages(content: &str) -> Vec<&str> {
+    let mut packages = Vec::new();
+    let mut in_packages = false;
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if !line.starts_with([' ', '\t', '-']) {
+            in_packages = trimmed.trim_end_matches(':').trim_end() == "packages";
+            continue;
+        }
+        if in_packages && let Some(item) = trimmed.strip_prefix('-') {
+            let item = item.split(" #").next().unwrap_or_default().trim();
+            let item = item
+                .strip_prefix(['"', '\''])
+                .and_then(|item| item.strip_suffix(['"', '\'']))
+                .unwrap_or(item);
+            packages.push(item);
+        }
+    }
+    packages
+}
+
+/// List the packages of the workspace at `root`. The root package.json
+/// itself is not one of them.
+pub(super) fn packages(root: &Path) -> Result<Vec<Package>, WorkspaceError> {
+    let root_manifest = manifest(root, b"")?;
+    // Yarn also accepts an object with a `packages` member
+    let workspaces = root_manifest.get("workspaces");
+    let mut globs = match workspaces {
+        Some(Value::Object(_)) => strings(workspaces.and_then(|w| w.get("packages"))),
+        _ => strings(workspaces),
+    };
+    let pnpm_workspace;
+    let mut origin = MANIFEST;
+    if workspaces.is_none() {
+        pnpm_workspace = read(root, PNPM_WORKSPACE.as_bytes()).map_err(|_| WorkspaceError {
+            path: MANIFEST.as_bytes().to_vec(),
+            message: format!("no \"workspaces\" member, and no {}", PNPM_WORKSPACE),
+        })?;
+        globs = pnpm_packages(&pnpm_workspace);
+        origin = PNPM_WORKSPACE;
+    }
+    // Globs starting with "!" exclude directories
+    let (exclude, include): (Vec<&str>, Vec<&str>) =
+        globs.into_iter().partition(|glob| glob.starts_with('!'));
+    let exclude: Vec<&str> = exclude.iter().map(|glob| &glob[1..]).collect();
+    let include = compile_globs(&include, origin.as_bytes())?;
+    let exclude = compile_globs(&exclude, origin.as_bytes())?;
+
+    let mut members = Vec::new();
+    for dir in find_dirs(root, MANIFEST)? {
+        let path = MatchPath::from_bytes(&dir);
+        if !dir.is_empty()
+            && include.iter().any(|glob| glob.is_match(&path))
+            && !exclude.iter().any(|glob| glob.is_match(&path))
+        {
+            let manifest = manifest(root, &dir)?;
+            let Some(name) = manifest.get("name").and_then(Value::as_str) else {
+                return Err(WorkspaceError {
+                    path: file_in(&dir, MANIFEST.as_bytes()),
+                    message: "no package name".to_string(),
+                });
+            };
+            members.push((dir, name.to_string(), manifest));
+        }
+    }
+
+    let packages = members
+        .iter()
+        .map(|(dir, name, manifest)| {
+            let mut dependencies: Vec<String> = Vec::new();
+            let declared = DEPENDENCY_FIELDS
+                .iter()
+                .filter_map(|field| match manifest.get(field) {
+                    Some(Value::Object(members)) => Some(members),
+                    _ => None,
+                })
+                .flatten();
+            for (dependency, _) in declared {
+                let is_member = members.iter().any(|(_, name, _)| name == dependency);
+                if is_member && dependency != name && !dependencies.contains(dependency) {
+                    dependencies.push(dependency.clone());
+                }
+            }
+            Package {
+                name: name.clone(),
+                dir: dir.clone(),
+                dependencies,
+            }
+        })
+        .collect();
+    Ok(packages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_pnpm_packages() {
+        let content = "# Workspace\npackages:\n  - 'packages/*'\n  - \"apps/**\" # apps\n\
+                       \x20 - '!**/test/**'\n\ncatalog:\n  - react\n";
+        assert_eq!(
+            pnpm_packages(content),
+            ["packages/*", "apps/**", "!**/test/**"]
+        );
+        assert!(pnpm_packages("catalog:\n  - react\n").is_empty());
+    }
+}
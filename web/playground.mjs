@@ -0,0 +1,52 @@
+// Loader for the WebAssembly build, shared by the playground page and the
+// tests. Build it with:
+//
+//     cargo build --release --lib --target wasm32-unknown-unknown
+
+const encoder = new TextEncoder();
+const decoder = new TextDecoder();
+
+// Codes of the Unicode normalization forms given to dcf_evaluate
+const UNICODE_FORMS = { nfc: 1, nfd: 2 };
+
+// Instantiate the module from its bytes and return a function evaluating a
+// configuration against changed paths, one per line. The function returns
+// the parsed JSON output: either { groups: { name: matched, ... } } or
+// { errors: [{ line, column, end_column, message }, ...] }. Set
+// normalizeUnicode to "nfc" or "nfd" to convert the patterns and the paths
+// to that Unicode normalization form.
+export async function load(bytes) {
+  const { instance } = await WebAssembly.instantiate(bytes, {});
+  const exports = instance.exports;
+
+  function write(text) {
+    const data = encoder.encode(text);
+    const ptr = exports.dcf_alloc(data.length);
+    new Uint8Array(exports.memory.buffer, ptr, data.length).set(data);
+    return [ptr, data.length];
+  }
+
+  return function evaluate(config, paths, { normalizeUnicode } = {}) {
+    const [configPtr, configLen] = write(config);
+    const [pathsPtr, pathsLen] = write(paths);
+    try {
+      exports.dcf_evaluate(
+        configPtr,
+        configLen,
+        pathsPtr,
+        pathsLen,
+        UNICODE_FORMS[normalizeUnicode] ?? 0,
+      );
+    } finally {
+      exports.dcf_dealloc(configPtr, configLen);
+      exports.dcf_dealloc(pathsPtr, pathsLen);
+    }
+    // Read the memory only now, as evaluating may have grown it
+    const output = new Uint8Array(
+      exports.memory.buffer,
+      exports.dcf_output_ptr(),
+      exports.dcf_output_len(),
+    );
+    return JSON.parse(decoder.decode(output));
+  };
+}
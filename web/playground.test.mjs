@@ -0,0 +1,64 @@
+// Tests of the WebAssembly build, run with `node --test web/` after building
+// it. DCF_WASM overrides the path of the module.
+
+import assert from "node:assert/strict";
+import { readFile } from "node:fs/promises";
+import { test } from "node:test";
+import { load } from "./playground.mjs";
+
+const path =
+  process.env.DCF_WASM ??
+  new URL(
+    "../target/wasm32-unknown-unknown/release/detect_changed_files.wasm",
+    import.meta.url,
+  );
+const evaluate = await load(await readFile(path));
+
+const config = "[docs]\ndocs/**\n*.md\n\n[code]\nsrc/**\n";
+
+test("matches groups in declaration order", () => {
+  const result = evaluate(config, "README.md\n");
+  assert.deepEqual(result, { groups: { docs: true, code: false } });
+  assert.deepEqual(Object.keys(result.groups), ["docs", "code"]);
+});
+
+test("handles blank lines and non-ASCII paths", () => {
+  assert.deepEqual(evaluate(config, "\n  src/café.rs \n\n"), {
+    groups: { docs: false, code: true },
+  });
+});
+
+test("normalizes patterns and paths to the same Unicode form", () => {
+  const decomposed = "[cafe\u0301]\ncafe\u0301/**\n";
+  const composed = "caf\u00e9/menu.txt\n";
+  assert.deepEqual(evaluate(decomposed, composed), {
+    groups: { "cafe\u0301": false },
+  });
+  assert.deepEqual(evaluate(decomposed, composed, { normalizeUnicode: "nfc" }), {
+    groups: { "cafe\u0301": true },
+  });
+});
+
+test("reports every configuration error with its location", () => {
+  const result = evaluate("[a\nsrc/**\n[]\n", "src/main.rs\n");
+  assert.equal(result.errors.length, 2);
+  assert.deepEqual(result.errors[0], {
+    line: 1,
+    column: 1,
+    end_column: 3,
+    message: "Invalid section header: missing closing ']'",
+  });
+  assert.equal(result.errors[1].line, 3);
+});
+
+test("reports invalid paths", () => {
+  const result = evaluate(config, "../outside.md\n");
+  assert.match(result.errors[0].message, /escapes the repository root/);
+});
+
+test("evaluates repeatedly with the same instance", () => {
+  for (let i = 0; i < 100; i++) {
+    const paths = `docs/page-${i}.txt\n`.repeat(50);
+    assert.equal(evaluate(config, paths).groups.docs, true);
+  }
+});